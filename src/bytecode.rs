@@ -7,8 +7,28 @@ use easter::decl::Dtor;
 use easter::punc::AssopTag;
 use easter::patt::AssignTarget;
 use easter::patt::Patt;
-use joker;
+use easter::stmt::ForHead;
+use easter::stmt::ForInHead;
+use easter::stmt::ForOfHead;
+use easter::stmt::Catch;
+use easter::id::Id;
+use easter::decl::Decl;
+use easter::fun::Fun;
+use easter::fun::Params;
+use easter::obj::DotKey;
+use easter::obj::PropKey;
+use easter::obj::PropVal;
+use easter::punc::UnopTag;
+use easter::punc::LogopTag;
+use joker::track::Posn;
+use joker::track::Span;
+use joker::track::TrackingRef;
+use std::fmt;
+use std::mem;
 
+/// Instructions are named in upper case, like the opcodes of an assembly
+/// listing.
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Clone)]
 pub enum Instruction {
     PUSHNUM(f64),
@@ -19,10 +39,24 @@ pub enum Instruction {
     DIV,
     PUSHTRUE,
     PUSHFALSE,
+    PUSHNULL,
     EQ,
     SEQ,
     NEQ,
     SNEQ,
+    LT,
+    LTE,
+    GT,
+    GTE,
+    MOD,
+    SHL,
+    SHR,
+    USHR,
+    BITAND,
+    BITOR,
+    BITXOR,
+    IN,
+    INSTANCEOF,
     PUSHVAR(String),
     UNDEFINED,
     READIDENT(String),
@@ -31,9 +65,69 @@ pub enum Instruction {
     ASSIGNSUBEQ(String),
     ASSIGNDIVEQ(String),
     ASSIGNMLPEQ(String),
+    /// Adds one to a variable, leaving the new value
+    PREINC(String),
+    PREDEC(String),
+    /// Adds one to a variable, leaving the old value as a number
+    POSTINC(String),
+    POSTDEC(String),
+    POP,
+    JUMP(usize),
+    JUMPIFFALSE(usize),
+    JUMPIFTRUE(usize),
+    DECLAREVAR(String),
+    DECLARELET(String),
+    DECLARECONST(String),
+    INITBINDING(String),
+    PUSHSCOPE,
+    POPSCOPE,
+    COPYSCOPE,
+    MAKEFUNCTION(usize),
+    CALL(usize),
+    RETURN,
+    ARG(usize),
+    ARGUMENTS,
+    CALLEE,
+    GETPROP,
+    THROW,
+    PUSHRESUME(usize),
+    RESUME,
+    POPBELOW(usize),
+    NEWOBJECT,
+    INITPROP,
+    /// Defines the function on top of the stack as a getter
+    INITGETTER,
+    INITSETTER,
+    SETPROP,
+    /// Like `PREINC`, but for the property whose object and key are on the
+    /// stack
+    PREINCPROP,
+    PREDECPROP,
+    POSTINCPROP,
+    POSTDECPROP,
+    NOT,
+    NEG,
+    TONUMBER,
+    BITNOT,
+    TYPEOF,
+    /// `typeof` of a variable, which gives "undefined" rather than throwing
+    /// when the variable is not declared
+    TYPEOFIDENT(String),
+    DELETEPROP,
+    DELETEIDENT(String),
+    DUP,
+    DUP2,
+    /// Calls a function with the object below it as `this`
+    CALLMETHOD(usize),
+    NEW(usize),
+    THIS,
+    NEWARRAY,
+    ARRAYPUSH,
+    /// Leaves a hole at the end of an array literal
+    ARRAYHOLE,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Image {
     pub script: Block,
     pub blocks: Vec<Block>,
@@ -42,7 +136,7 @@ pub struct Image {
 impl Image {
     pub fn new() -> Image {
         Image {
-            script: Block { instructions: Vec::new() },
+            script: Block::new(),
             blocks: Vec::new(),
         }
     }
@@ -58,70 +152,920 @@ impl Image {
     pub fn push_string(&mut self, s: String) {
         self.script.instructions.push(Instruction::PUSHSTRLIT(s));
     }
+
+    /// Index the next pushed instruction will get, used as a jump target.
+    pub fn next_index(&self) -> usize {
+        self.script.instructions.len()
+    }
+
+    /// Pushes a jump with a placeholder target and returns its index so it
+    /// can be resolved with `patch_jump` once the target is known.
+    pub fn push_jump(&mut self, instr: Instruction) -> usize {
+        let index = self.next_index();
+        self.push_instruction(instr);
+        index
+    }
+
+    /// Points the jump at `index` to the next instruction to be pushed.
+    pub fn patch_jump(&mut self, index: usize) {
+        let target = self.next_index();
+        self.patch_jump_to(index, target);
+    }
+
+    pub fn patch_jump_to(&mut self, index: usize, target: usize) {
+        match self.script.instructions[index] {
+            Instruction::JUMP(ref mut to)
+            | Instruction::JUMPIFFALSE(ref mut to)
+            | Instruction::JUMPIFTRUE(ref mut to)
+            | Instruction::PUSHRESUME(ref mut to) => *to = target,
+            _ => panic!("Tried to patch a non-jump instruction"),
+        }
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Block {
-    pub instructions: Vec<Instruction>
+    pub instructions: Vec<Instruction>,
+    /// Exception handlers of the block, innermost first
+    pub handlers: Vec<Handler>,
+}
+
+impl Block {
+    pub fn new() -> Block {
+        Block { instructions: Vec::new(), handlers: Vec::new() }
+    }
+}
+
+/// Catches whatever is thrown by the instructions from `start` up to `end`,
+/// which continue at `target` with the thrown value pushed.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Handler {
+    pub start: usize,
+    pub end: usize,
+    pub target: usize,
+    /// Values the block keeps on the stack at `target`, not counting the thrown one
+    pub stack_depth: usize,
+    /// Block scopes that are entered at `target`
+    pub scope_depth: usize,
+}
+
+/// Raised when a script uses a construct the compiler does not support yet.
+#[derive(Debug, PartialEq)]
+pub struct CompileError {
+    /// The construct as it reads in source, like `for…in` or `<<=`
+    pub construct: String,
+    pub location: Option<Span>,
+}
+
+impl CompileError {
+    fn unsupported(construct: &str, location: &Option<Span>) -> CompileError {
+        CompileError { construct: construct.to_owned(), location: *location }
+    }
+
+    /// The line and column of the construct, both counting from 1.
+    pub fn position(&self) -> Option<(u32, u32)> {
+        self.location.map(|span| (span.start.line + 1, column(&span.start) + 1))
+    }
+
+    /// The source line holding the construct, with the construct underlined.
+    pub fn excerpt(&self, source: &str) -> Option<String> {
+        let span = self.location?;
+        let line = source.lines().nth(span.start.line as usize)?;
+
+        let width = if span.end.line == span.start.line && span.end.column > span.start.column {
+            (span.end.column - span.start.column) as usize
+        } else {
+            1
+        };
+
+        let mut marker: String = line.chars().take(column(&span.start) as usize)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        marker.push_str(&"^".repeat(width));
+
+        Some(format!("{}\n{}", line, marker))
+    }
+
+    /// The error message followed by the excerpt of `source` it points at.
+    pub fn report(&self, source: &str) -> String {
+        match self.excerpt(source) {
+            Some(excerpt) => format!("{}\n{}", self, excerpt),
+            None => format!("{}", self),
+        }
+    }
+}
+
+/// The 0-based column of `posn`. esprit counts the newline itself as column
+/// 0 of the following line, so only the first line starts at column 0.
+fn column(posn: &Posn) -> u32 {
+    if posn.line == 0 { posn.column } else { posn.column - 1 }
+}
+
+/// esprit starts a statement's span after its leading keyword has been
+/// lexed, so step back over the keyword to point at the statement itself.
+fn keyword_span(location: &Option<Span>, keyword: &str) -> Option<Span> {
+    location.map(|mut span| {
+        let length = keyword.len() as u32;
+        span.start.offset -= length;
+        span.start.column -= length;
+        span
+    })
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.position() {
+            Some((line, column)) => write!(f, "unsupported: `{}` at {}:{}", self.construct, line, column),
+            None => write!(f, "unsupported: `{}`", self.construct),
+        }
+    }
+}
+
+/// Compile-time state for the statements currently being compiled.
+struct Context {
+    /// Enclosing statements `break` and `continue` can target, innermost last
+    breakables: Vec<Breakable>,
+    /// Number of block scopes entered within the function being compiled
+    scope_depth: usize,
+    /// Number of values the function being compiled keeps on the stack
+    /// between statements
+    stack_depth: usize,
+    /// Enclosing `finally` blocks, innermost last
+    finalizers: Vec<Finalizer>,
+}
+
+/// A loop or labeled statement along with the jumps that still have to be
+/// pointed at its end (`break`) or its next iteration (`continue`).
+struct Breakable {
+    labels: Vec<String>,
+    is_loop: bool,
+    scope_depth: usize,
+    stack_depth: usize,
+    /// Number of `finally` blocks around the statement
+    finalizer_count: usize,
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+impl Context {
+    fn new() -> Context {
+        Context { breakables: Vec::new(), scope_depth: 0, stack_depth: 0, finalizers: Vec::new() }
+    }
+
+    fn breakable(&self, labels: Vec<String>, is_loop: bool) -> Breakable {
+        Breakable {
+            labels,
+            is_loop,
+            scope_depth: self.scope_depth,
+            stack_depth: self.stack_depth,
+            finalizer_count: self.finalizers.len(),
+            breaks: Vec::new(),
+            continues: Vec::new(),
+        }
+    }
+
+    fn find_breakable(&mut self, label: &Option<String>, is_continue: bool) -> &mut Breakable {
+        let found = self.breakables.iter_mut().rev().find(|breakable| {
+            match label {
+                Some(label) => breakable.labels.contains(label),
+                // An unlabeled break or continue targets the innermost loop
+                None => breakable.is_loop,
+            }
+        });
+
+        match found {
+            Some(ref breakable) if is_continue && !breakable.is_loop => panic!("Continue target is not a loop"),
+            Some(breakable) => breakable,
+            None => panic!("Unresolved break or continue target"),
+        }
+    }
 }
 
-pub fn compile_to_image(body: Vec<easter::stmt::StmtListItem>) -> Image {
+/// A `finally` block being compiled, along with the exits that have to run
+/// it before they continue.
+struct Finalizer {
+    scope_depth: usize,
+    stack_depth: usize,
+    /// The `PUSHRESUME` and the jump into the finally block of every exit
+    exits: Vec<(usize, usize, Exit)>,
+}
+
+/// A way of leaving a try statement other than falling off its end or
+/// throwing.
+enum Exit {
+    Break(Option<String>, bool),
+    Return,
+}
+
+pub fn compile_to_image(body: Vec<easter::stmt::StmtListItem>) -> Result<Image, CompileError> {
     let mut image = Image::new();
+    compile_body(&mut image, body, true)?;
+    Ok(image)
+}
 
-    for stmt_item in body {
-        // println!("{:#?}", stmtItem);
+/// Compiles the body of a script or function into the block currently being
+/// emitted, hoisting `var` and function declarations to the top.
+fn compile_body(image: &mut Image, body: Vec<StmtListItem>, is_script: bool) -> Result<(), CompileError> {
+    let mut ctx = Context::new();
 
+    let mut var_names = Vec::new();
+    collect_var_names(&body, &mut var_names);
+    for name in var_names {
+        image.push_instruction(Instruction::DECLAREVAR(name));
+    }
+
+    let (decls, stmts): (Vec<StmtListItem>, Vec<StmtListItem>) = body.into_iter().partition(|item| {
+        match *item {
+            StmtListItem::Decl(_) => true,
+            StmtListItem::Stmt(_) => false,
+        }
+    });
+
+    for decl in decls {
+        if let StmtListItem::Decl(decl) = decl {
+            compile_decl(image, decl)?;
+        }
+    }
+
+    let last = stmts.len();
+    for (index, stmt_item) in stmts.into_iter().enumerate() {
         match stmt_item {
-            StmtListItem::Decl(decl) => compile_decl(&mut image, decl),
-            StmtListItem::Stmt(stmt) => compile_stmt(&mut image, stmt),
+            // The value of a trailing expression statement is left on the
+            // stack so it can be read back as the result of the script
+            StmtListItem::Stmt(Stmt::Expr(_, expr, _)) if is_script && index + 1 == last => compile_expression(image, expr)?,
+            StmtListItem::Decl(decl) => compile_decl(image, decl)?,
+            StmtListItem::Stmt(stmt) => compile_stmt(image, &mut ctx, stmt)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Collects the names declared with `var` in a function body, without
+/// descending into nested functions.
+fn collect_var_names(items: &[StmtListItem], names: &mut Vec<String>) {
+    for item in items {
+        if let StmtListItem::Stmt(stmt) = item {
+            collect_stmt_var_names(stmt, names);
+        }
+    }
+}
+
+fn add_var_name(id: &Id, names: &mut Vec<String>) {
+    let name = id.name.as_ref().to_owned();
+    if !names.contains(&name) {
+        names.push(name);
+    }
+}
+
+fn add_dtor_var_names(dtors: &[Dtor], names: &mut Vec<String>) {
+    for dtor in dtors {
+        if let Dtor::Simple(_, id, _) = dtor {
+            add_var_name(id, names);
+        }
+    }
+}
+
+fn collect_stmt_var_names(stmt: &Stmt, names: &mut Vec<String>) {
+    match stmt {
+        Stmt::Var(_, dtors, _) => add_dtor_var_names(dtors, names),
+        Stmt::Block(_, items) => collect_var_names(items, names),
+        Stmt::If(_, _, consequent, alternate) => {
+            collect_stmt_var_names(consequent, names);
+            if let Some(alternate) = alternate {
+                collect_stmt_var_names(alternate, names);
+            }
+        },
+        Stmt::Label(_, _, body)
+        | Stmt::While(_, _, body)
+        | Stmt::DoWhile(_, body, _, _)
+        | Stmt::With(_, _, body) => collect_stmt_var_names(body, names),
+        Stmt::For(_, head, _, _, body) => {
+            if let Some(head) = head {
+                if let ForHead::Var(_, ref dtors) = **head {
+                    add_dtor_var_names(dtors, names);
+                }
+            }
+            collect_stmt_var_names(body, names);
+        },
+        Stmt::ForIn(_, head, _, body) => {
+            match **head {
+                ForInHead::VarInit(_, ref id, _) | ForInHead::Var(_, Patt::Simple(ref id)) => add_var_name(id, names),
+                _ => {},
+            }
+            collect_stmt_var_names(body, names);
+        },
+        Stmt::ForOf(_, head, _, body) => {
+            if let ForOfHead::Var(_, Patt::Simple(ref id)) = **head {
+                add_var_name(id, names);
+            }
+            collect_stmt_var_names(body, names);
+        },
+        Stmt::Switch(_, _, cases) => {
+            for case in cases {
+                collect_var_names(&case.body, names);
+            }
+        },
+        Stmt::Try(_, body, catch, finally) => {
+            collect_var_names(body, names);
+            if let Some(catch) = catch {
+                collect_var_names(&catch.body, names);
+            }
+            if let Some(finally) = finally {
+                collect_var_names(finally, names);
+            }
+        },
+        _ => {},
+    }
+}
+
+fn compile_decl(image: &mut Image, decl: Decl) -> Result<(), CompileError> {
+    match decl {
+        Decl::Fun(fun) => {
+            let name = match fun.id {
+                Some(ref id) => id.name.as_ref().to_owned(),
+                None => return Err(CompileError::unsupported("anonymous function declaration", &fun.location)),
+            };
+
+            let block = compile_function(image, fun, false)?;
+            image.push_instruction(Instruction::MAKEFUNCTION(block));
+            image.push_instruction(Instruction::PUSHVAR(name));
+        },
+    }
+
+    Ok(())
+}
+
+/// Compiles a function body into its own block in `image.blocks`, returning
+/// the index of that block.
+fn compile_function(image: &mut Image, fun: Fun, is_expression: bool) -> Result<usize, CompileError> {
+    let outer = mem::replace(&mut image.script, Block::new());
+
+    // A named function expression can refer to itself by its name, which
+    // `arguments` and the parameters shadow
+    if is_expression {
+        if let Some(id) = fun.id {
+            image.push_instruction(Instruction::CALLEE);
+            image.push_instruction(Instruction::PUSHVAR(id_to_string(id)));
         }
     }
 
-    image
+    image.push_instruction(Instruction::ARGUMENTS);
+    image.push_instruction(Instruction::PUSHVAR("arguments".to_owned()));
+
+    for (index, param) in fun.params.list.into_iter().enumerate() {
+        match param {
+            Patt::Simple(id) => {
+                image.push_instruction(Instruction::ARG(index));
+                image.push_instruction(Instruction::PUSHVAR(id_to_string(id)));
+            },
+            Patt::Compound(patt) => {
+                image.script = outer;
+                return Err(CompileError::unsupported("destructuring parameter", patt.tracking_ref()))
+            },
+        }
+    }
+
+    if let Err(why) = compile_body(image, fun.body, false) {
+        image.script = outer;
+        return Err(why)
+    }
+    image.push_instruction(Instruction::UNDEFINED);
+    image.push_instruction(Instruction::RETURN);
+
+    let block = mem::replace(&mut image.script, outer);
+    image.blocks.push(block);
+    Ok(image.blocks.len() - 1)
 }
 
-fn compile_decl(image: &mut Image, decl: easter::decl::Decl) {
+fn compile_stmt_list(image: &mut Image, ctx: &mut Context, items: Vec<StmtListItem>) -> Result<(), CompileError> {
+    for stmt_item in items {
+        match stmt_item {
+            StmtListItem::Decl(decl) => compile_decl(image, decl)?,
+            StmtListItem::Stmt(stmt) => compile_stmt(image, ctx, stmt)?,
+        }
+    }
 
+    Ok(())
 }
 
-fn compile_stmt(image: &mut Image, stmt: Stmt) {
+fn compile_stmt(image: &mut Image, ctx: &mut Context, stmt: Stmt) -> Result<(), CompileError> {
     match stmt {
-        Stmt::Expr(_, expr, _) => compile_expression(image, expr),
-        Stmt::Var(_, dtor_vec, _) => compile_dtor_vec(image, dtor_vec),
-        _ => panic!("Unsupported statement"),
+        Stmt::Empty(_) => {},
+        Stmt::Block(_, items) => compile_stmt_list(image, ctx, items)?,
+        Stmt::Expr(_, expr, _) => {
+            compile_expression(image, expr)?;
+            image.push_instruction(Instruction::POP);
+        },
+        Stmt::Var(_, dtor_vec, _) => compile_dtor_vec(image, dtor_vec)?,
+        Stmt::If(_, test, consequent, alternate) => compile_if(image, ctx, test, *consequent, alternate)?,
+        Stmt::Label(_, id, body) => compile_labeled(image, ctx, vec![id_to_string(id)], *body)?,
+        Stmt::Break(_, label, _) => compile_break(image, ctx, label.map(id_to_string), false),
+        Stmt::Cont(_, label, _) => compile_break(image, ctx, label.map(id_to_string), true),
+        Stmt::While(..) | Stmt::DoWhile(..) | Stmt::For(..) => compile_loop(image, ctx, Vec::new(), stmt)?,
+        Stmt::Return(_, expr, _) => {
+            match expr {
+                Some(expr) => compile_expression(image, expr)?,
+                None => image.push_instruction(Instruction::UNDEFINED),
+            }
+
+            ctx.stack_depth += 1;
+            compile_return(image, ctx);
+            ctx.stack_depth -= 1;
+        },
+        Stmt::Throw(_, expr, _) => {
+            compile_expression(image, expr)?;
+            image.push_instruction(Instruction::THROW);
+        },
+        Stmt::Try(_, body, catch, finally) => compile_try(image, ctx, body, catch, finally)?,
+        Stmt::With(ref location, ..) => return Err(CompileError::unsupported("with", &keyword_span(location, "with"))),
+        Stmt::Switch(ref location, ..) => return Err(CompileError::unsupported("switch", &keyword_span(location, "switch"))),
+        Stmt::ForIn(ref location, ..) => return Err(CompileError::unsupported("for…in", &keyword_span(location, "for"))),
+        Stmt::ForOf(ref location, ..) => return Err(CompileError::unsupported("for…of", &keyword_span(location, "for"))),
+        Stmt::Debugger(ref location, _) => return Err(CompileError::unsupported("debugger", &keyword_span(location, "debugger"))),
+    }
+
+    Ok(())
+}
+
+fn compile_if(image: &mut Image, ctx: &mut Context, test: Expr, consequent: Stmt, alternate: Option<Box<Stmt>>) -> Result<(), CompileError> {
+    compile_expression(image, test)?;
+    let to_alternate = image.push_jump(Instruction::JUMPIFFALSE(0));
+
+    compile_stmt(image, ctx, consequent)?;
+
+    match alternate {
+        Some(alternate) => {
+            let to_end = image.push_jump(Instruction::JUMP(0));
+            image.patch_jump(to_alternate);
+            compile_stmt(image, ctx, *alternate)?;
+            image.patch_jump(to_end);
+        },
+        None => image.patch_jump(to_alternate),
+    }
+
+    Ok(())
+}
+
+/// Compiles a `break` or `continue`, leaving the block scopes entered since
+/// the statement it targets.
+fn compile_break(image: &mut Image, ctx: &mut Context, label: Option<String>, is_continue: bool) {
+    let (scope_depth, stack_depth) = (ctx.scope_depth, ctx.stack_depth);
+    let (target_scope_depth, target_stack_depth, finalizer_count) = {
+        let breakable = ctx.find_breakable(&label, is_continue);
+        (breakable.scope_depth, breakable.stack_depth, breakable.finalizer_count)
+    };
+
+    // Finally blocks on the way out run first, and continue the jump once
+    // they are done
+    if ctx.finalizers.len() > finalizer_count {
+        return enter_finalizer(image, ctx, Exit::Break(label, is_continue));
+    }
+
+    for _ in target_scope_depth..scope_depth {
+        image.push_instruction(Instruction::POPSCOPE);
+    }
+    for _ in target_stack_depth..stack_depth {
+        image.push_instruction(Instruction::POP);
+    }
+
+    let jump = image.push_jump(Instruction::JUMP(0));
+    let breakable = ctx.find_breakable(&label, is_continue);
+    if is_continue {
+        breakable.continues.push(jump);
+    } else {
+        breakable.breaks.push(jump);
     }
 }
 
-fn compile_expression(image: &mut Image, expr: Expr) {
+fn compile_return(image: &mut Image, ctx: &mut Context) {
+    if ctx.finalizers.is_empty() {
+        image.push_instruction(Instruction::RETURN);
+    } else {
+        enter_finalizer(image, ctx, Exit::Return);
+    }
+}
+
+/// Leaves the protected code of the innermost try statement for its finally
+/// block, which resumes `exit` afterwards.
+fn enter_finalizer(image: &mut Image, ctx: &mut Context, exit: Exit) {
+    let (scope_depth, stack_depth) = {
+        let finalizer = ctx.finalizers.last().unwrap();
+        (finalizer.scope_depth, finalizer.stack_depth)
+    };
+
+    for _ in scope_depth..ctx.scope_depth {
+        image.push_instruction(Instruction::POPSCOPE);
+    }
+
+    // The finally block expects a value and the address to resume at on top
+    // of the stack of the try statement
+    match exit {
+        Exit::Return => {
+            let below = ctx.stack_depth - 1 - stack_depth;
+            if below > 0 {
+                image.push_instruction(Instruction::POPBELOW(below));
+            }
+        },
+        Exit::Break(..) => {
+            for _ in stack_depth..ctx.stack_depth {
+                image.push_instruction(Instruction::POP);
+            }
+            image.push_instruction(Instruction::UNDEFINED);
+        },
+    }
+
+    let resume = image.push_jump(Instruction::PUSHRESUME(0));
+    let jump = image.push_jump(Instruction::JUMP(0));
+    ctx.finalizers.last_mut().unwrap().exits.push((resume, jump, exit));
+}
+
+fn compile_try(image: &mut Image, ctx: &mut Context, body: Vec<StmtListItem>, catch: Option<Box<Catch>>, finally: Option<Vec<StmtListItem>>) -> Result<(), CompileError> {
+    let start = image.next_index();
+    if finally.is_some() {
+        ctx.finalizers.push(Finalizer { scope_depth: ctx.scope_depth, stack_depth: ctx.stack_depth, exits: Vec::new() });
+    }
+
+    let result = compile_try_body(image, ctx, body, catch, start);
+
+    let finally = match finally {
+        Some(finally) => finally,
+        None => return result,
+    };
+    let finalizer = ctx.finalizers.pop().unwrap();
+    result?;
+
+    let end = image.next_index();
+    let (scope_depth, stack_depth) = (ctx.scope_depth, ctx.stack_depth);
+
+    // Falling off the end of the try or catch block resumes after the
+    // try statement
+    image.push_instruction(Instruction::UNDEFINED);
+    let normal_resume = image.push_jump(Instruction::PUSHRESUME(0));
+    let to_finally = image.push_jump(Instruction::JUMP(0));
+
+    // Anything thrown in the try or catch block is thrown again after the
+    // finally block
+    let throw_target = image.next_index();
+    image.script.handlers.push(Handler { start, end, target: throw_target, stack_depth, scope_depth });
+    let throw_resume = image.push_jump(Instruction::PUSHRESUME(0));
+
+    image.patch_jump(to_finally);
+    for &(_, jump, _) in finalizer.exits.iter() {
+        image.patch_jump(jump);
+    }
+
+    ctx.stack_depth += 2;
+    let result = compile_stmt_list(image, ctx, finally);
+    ctx.stack_depth -= 2;
+    result?;
+    image.push_instruction(Instruction::RESUME);
+
+    image.patch_jump(throw_resume);
+    image.push_instruction(Instruction::THROW);
+
+    for (resume, _, exit) in finalizer.exits {
+        image.patch_jump(resume);
+        match exit {
+            Exit::Break(label, is_continue) => {
+                image.push_instruction(Instruction::POP);
+                compile_break(image, ctx, label, is_continue);
+            },
+            Exit::Return => {
+                ctx.stack_depth += 1;
+                compile_return(image, ctx);
+                ctx.stack_depth -= 1;
+            },
+        }
+    }
+
+    image.patch_jump(normal_resume);
+    image.push_instruction(Instruction::POP);
+
+    Ok(())
+}
+
+/// Compiles the try and catch blocks of a try statement, registering the
+/// handler that enters the catch block.
+fn compile_try_body(image: &mut Image, ctx: &mut Context, body: Vec<StmtListItem>, catch: Option<Box<Catch>>, start: usize) -> Result<(), CompileError> {
+    compile_stmt_list(image, ctx, body)?;
+
+    let catch = match catch {
+        Some(catch) => *catch,
+        None => return Ok(()),
+    };
+
+    let to_end = image.push_jump(Instruction::JUMP(0));
+    let end = image.next_index();
+    image.script.handlers.push(Handler { start, end, target: end, stack_depth: ctx.stack_depth, scope_depth: ctx.scope_depth });
+
+    // The catch parameter lives in a scope of its own, around the block
+    let param = match catch.param {
+        Patt::Simple(id) => id_to_string(id),
+        Patt::Compound(patt) => return Err(CompileError::unsupported("destructuring catch parameter", patt.tracking_ref())),
+    };
+    image.push_instruction(Instruction::PUSHSCOPE);
+    image.push_instruction(Instruction::DECLARELET(param.clone()));
+    image.push_instruction(Instruction::INITBINDING(param));
+
+    ctx.scope_depth += 1;
+    let result = compile_stmt_list(image, ctx, catch.body);
+    ctx.scope_depth -= 1;
+    result?;
+
+    image.push_instruction(Instruction::POPSCOPE);
+    image.patch_jump(to_end);
+
+    Ok(())
+}
+
+fn compile_labeled(image: &mut Image, ctx: &mut Context, mut labels: Vec<String>, body: Stmt) -> Result<(), CompileError> {
+    match body {
+        Stmt::Label(_, id, body) => {
+            labels.push(id_to_string(id));
+            compile_labeled(image, ctx, labels, *body)
+        },
+        Stmt::While(..) | Stmt::DoWhile(..) | Stmt::For(..) => compile_loop(image, ctx, labels, body),
+        _ => {
+            // Any statement can be labeled, but only `break` may target it
+            let breakable = ctx.breakable(labels, false);
+            ctx.breakables.push(breakable);
+            let result = compile_stmt(image, ctx, body);
+
+            let breakable = ctx.breakables.pop().unwrap();
+            for jump in breakable.breaks {
+                image.patch_jump(jump);
+            }
+            result
+        },
+    }
+}
+
+fn compile_loop(image: &mut Image, ctx: &mut Context, labels: Vec<String>, stmt: Stmt) -> Result<(), CompileError> {
+    let breakable = ctx.breakable(labels, true);
+    ctx.breakables.push(breakable);
+    let mut has_head_scope = false;
+
+    let continue_target = match stmt {
+        Stmt::While(_, test, body) => {
+            let start = image.next_index();
+            compile_expression(image, test)?;
+            let to_end = image.push_jump(Instruction::JUMPIFFALSE(0));
+            ctx.breakables.last_mut().unwrap().breaks.push(to_end);
+
+            compile_stmt(image, ctx, *body)?;
+            image.push_instruction(Instruction::JUMP(start));
+            start
+        },
+        Stmt::DoWhile(_, body, test, _) => {
+            let start = image.next_index();
+            compile_stmt(image, ctx, *body)?;
+
+            let test_start = image.next_index();
+            compile_expression(image, test)?;
+            image.push_instruction(Instruction::JUMPIFTRUE(start));
+            test_start
+        },
+        Stmt::For(_, head, test, update, body) => {
+            if let Some(head) = head {
+                match *head {
+                    ForHead::Var(_, dtor_vec) => compile_dtor_vec(image, dtor_vec)?,
+                    ForHead::Expr(_, expr) => {
+                        compile_expression(image, expr)?;
+                        image.push_instruction(Instruction::POP);
+                    },
+                    ForHead::Let(_, dtor_vec) => {
+                        // `let` declarations in the head live in a scope of
+                        // their own, which is copied for every iteration so
+                        // closures capture the value of that iteration
+                        image.push_instruction(Instruction::PUSHSCOPE);
+                        ctx.scope_depth += 1;
+                        ctx.breakables.last_mut().unwrap().scope_depth = ctx.scope_depth;
+                        has_head_scope = true;
+
+                        compile_lexical_dtor_vec(image, dtor_vec, false)?;
+                        image.push_instruction(Instruction::COPYSCOPE);
+                    },
+                }
+            }
+
+            let start = image.next_index();
+            if let Some(test) = test {
+                compile_expression(image, test)?;
+                let to_end = image.push_jump(Instruction::JUMPIFFALSE(0));
+                ctx.breakables.last_mut().unwrap().breaks.push(to_end);
+            }
+
+            compile_stmt(image, ctx, *body)?;
+
+            let update_start = image.next_index();
+            if has_head_scope {
+                image.push_instruction(Instruction::COPYSCOPE);
+            }
+            if let Some(update) = update {
+                compile_expression(image, update)?;
+                image.push_instruction(Instruction::POP);
+            }
+            image.push_instruction(Instruction::JUMP(start));
+            update_start
+        },
+        _ => unreachable!("Only loops are compiled as loops"),
+    };
+
+    let breakable = ctx.breakables.pop().unwrap();
+    for jump in breakable.continues {
+        image.patch_jump_to(jump, continue_target);
+    }
+    for jump in breakable.breaks {
+        image.patch_jump(jump);
+    }
+
+    if has_head_scope {
+        image.push_instruction(Instruction::POPSCOPE);
+        ctx.scope_depth -= 1;
+    }
+
+    Ok(())
+}
+
+fn compile_expression(image: &mut Image, expr: Expr) -> Result<(), CompileError> {
     match expr {
         Expr::Binop(_, op, left, right) => {
-            compile_expression(image, *right);
-            compile_expression(image, *left);
+            compile_expression(image, *left)?;
+            compile_expression(image, *right)?;
             compile_bin_op(image, op);
         },
+        Expr::Unop(_, op, argument) => compile_unop(image, op.tag, *argument)?,
+        Expr::Logop(_, op, left, right) => {
+            // The left operand is the result when it decides the outcome,
+            // without evaluating the right one
+            compile_expression(image, *left)?;
+            image.push_instruction(Instruction::DUP);
+            let to_end = match op.tag {
+                LogopTag::And => image.push_jump(Instruction::JUMPIFFALSE(0)),
+                LogopTag::Or => image.push_jump(Instruction::JUMPIFTRUE(0)),
+            };
+            image.push_instruction(Instruction::POP);
+            compile_expression(image, *right)?;
+            image.patch_jump(to_end);
+        },
+        Expr::Cond(_, test, consequent, alternate) => {
+            compile_expression(image, *test)?;
+            let to_alternate = image.push_jump(Instruction::JUMPIFFALSE(0));
+            compile_expression(image, *consequent)?;
+            let to_end = image.push_jump(Instruction::JUMP(0));
+            image.patch_jump(to_alternate);
+            compile_expression(image, *alternate)?;
+            image.patch_jump(to_end);
+        },
         Expr::Number(_, number) => image.push_number(number.value),
         Expr::String(_, string_literal) => image.push_string(string_literal.value),
         Expr::True(_) => image.push_instruction(Instruction::PUSHTRUE),
         Expr::False(_) => image.push_instruction(Instruction::PUSHFALSE),
-        Expr::Id(id) => {
-            match id.name {
-                joker::word::Name::String(string) => image.push_instruction(Instruction::READIDENT(string)),
-                _ => panic!("Unsupported statement"),
+        Expr::Null(_) => image.push_instruction(Instruction::PUSHNULL),
+        Expr::Id(id) => image.push_instruction(Instruction::READIDENT(id_to_string(id))),
+        Expr::Assign(location, op, target, value) => {
+            match target {
+                Patt::Simple(target) => compile_ass_op(image, op, target, *value)?,
+                Patt::Compound(_) => return Err(CompileError::unsupported("destructuring assignment", &location)),
             }
         },
-        Expr::Assign(_, op, target, value) => {
-            compile_expression(image, *value);
-            match target {
-                Patt::Simple(AssignTarget::Id(id)) => {
-                    match id.name {
-                        joker::word::Name::String(string) => compile_ass_op(image, op, string),
-                        _ => panic!("Unsupported statement"),
-                    }
+        Expr::PreInc(_, operand) if is_reference(&operand) => compile_update(image, *operand, Instruction::PREINC, Instruction::PREINCPROP)?,
+        Expr::PreDec(_, operand) if is_reference(&operand) => compile_update(image, *operand, Instruction::PREDEC, Instruction::PREDECPROP)?,
+        Expr::PostInc(_, operand) if is_reference(&operand) => compile_update(image, *operand, Instruction::POSTINC, Instruction::POSTINCPROP)?,
+        Expr::PostDec(_, operand) if is_reference(&operand) => compile_update(image, *operand, Instruction::POSTDEC, Instruction::POSTDECPROP)?,
+        Expr::Obj(_, props) => {
+            image.push_instruction(Instruction::NEWOBJECT);
+
+            for prop in props {
+                match prop.key {
+                    PropKey::Id(_, name) => image.push_string(name),
+                    PropKey::String(_, string_literal) => image.push_string(string_literal.value),
+                    PropKey::Number(_, number) => image.push_number(number.value),
+                }
+
+                match prop.val {
+                    PropVal::Init(value) => {
+                        compile_expression(image, value)?;
+                        image.push_instruction(Instruction::INITPROP);
+                    },
+                    PropVal::Get(location, body) => {
+                        let params = Params { location: None, list: vec![] };
+                        let block = compile_function(image, Fun { location, id: None, params, body }, false)?;
+                        image.push_instruction(Instruction::MAKEFUNCTION(block));
+                        image.push_instruction(Instruction::INITGETTER);
+                    },
+                    PropVal::Set(location, param, body) => {
+                        let params = Params { location: None, list: vec![param] };
+                        let block = compile_function(image, Fun { location, id: None, params, body }, false)?;
+                        image.push_instruction(Instruction::MAKEFUNCTION(block));
+                        image.push_instruction(Instruction::INITSETTER);
+                    },
                 }
-                _ => panic!("Unsupported expression"),
             }
-        }
-        _ => panic!("Unsupported expression"),
+        },
+        Expr::This(_) => image.push_instruction(Instruction::THIS),
+        Expr::New(_, callee, args) => {
+            compile_expression(image, *callee)?;
+
+            // `new F` is the same as `new F()`
+            let args = args.unwrap_or_default();
+            let argc = args.len();
+            for arg in args {
+                compile_expression(image, arg)?;
+            }
+            image.push_instruction(Instruction::NEW(argc));
+        },
+        Expr::Arr(_, elements) => {
+            image.push_instruction(Instruction::NEWARRAY);
+
+            for element in elements {
+                match element {
+                    Some(element) => {
+                        compile_expression(image, element)?;
+                        image.push_instruction(Instruction::ARRAYPUSH);
+                    },
+                    None => image.push_instruction(Instruction::ARRAYHOLE),
+                }
+            }
+        },
+        Expr::Fun(fun) => {
+            let block = compile_function(image, fun, true)?;
+            image.push_instruction(Instruction::MAKEFUNCTION(block));
+        },
+        Expr::Call(_, callee, args) => {
+            // Calling a member keeps the object around to become `this`
+            let is_method = match *callee {
+                Expr::Dot(_, object, DotKey { value, .. }) => {
+                    compile_expression(image, *object)?;
+                    image.push_instruction(Instruction::DUP);
+                    image.push_string(value);
+                    image.push_instruction(Instruction::GETPROP);
+                    true
+                },
+                Expr::Brack(_, object, key) => {
+                    compile_expression(image, *object)?;
+                    image.push_instruction(Instruction::DUP);
+                    compile_expression(image, *key)?;
+                    image.push_instruction(Instruction::GETPROP);
+                    true
+                },
+                callee => {
+                    compile_expression(image, callee)?;
+                    false
+                },
+            };
+
+            let argc = args.len();
+            for arg in args {
+                compile_expression(image, arg)?;
+            }
+
+            if is_method {
+                image.push_instruction(Instruction::CALLMETHOD(argc));
+            } else {
+                image.push_instruction(Instruction::CALL(argc));
+            }
+        },
+        Expr::Dot(_, object, DotKey { value, .. }) => {
+            compile_expression(image, *object)?;
+            image.push_string(value);
+            image.push_instruction(Instruction::GETPROP);
+        },
+        Expr::Brack(_, object, key) => {
+            compile_expression(image, *object)?;
+            compile_expression(image, *key)?;
+            image.push_instruction(Instruction::GETPROP);
+        },
+        _ => return Err(CompileError::unsupported(expr_construct(&expr), &expr_location(&expr))),
+    }
+
+    Ok(())
+}
+
+/// Names an expression the way it reads in source, for error messages.
+fn expr_construct(expr: &Expr) -> &'static str {
+    match expr {
+        &Expr::Seq(..) => ",",
+        &Expr::PreInc(..) | &Expr::PostInc(..) => "++",
+        &Expr::PreDec(..) | &Expr::PostDec(..) => "--",
+        &Expr::NewTarget(_) => "new.target",
+        &Expr::RegExp(..) => "regular expression",
+        _ => "expression",
+    }
+}
+
+/// esprit starts postfix expressions after their operand, so their span is
+/// rebuilt from the operand onwards.
+fn expr_location(expr: &Expr) -> Option<Span> {
+    let first = match expr {
+        &Expr::PostInc(_, ref operand) | &Expr::PostDec(_, ref operand) => operand.tracking_ref(),
+        _ => return *expr.tracking_ref(),
+    };
+
+    match (first, expr.tracking_ref()) {
+        (&Some(first), &Some(whole)) => Some(Span { start: first.start, end: whole.end }),
+        _ => *expr.tracking_ref(),
     }
 }
 
@@ -135,43 +1079,227 @@ fn compile_bin_op(image: &mut Image, binop: easter::punc::Binop) {
         BinopTag::Eq => image.push_instruction(Instruction::EQ),
         BinopTag::StrictNEq => image.push_instruction(Instruction::SNEQ),
         BinopTag::NEq => image.push_instruction(Instruction::NEQ),
+        BinopTag::Lt => image.push_instruction(Instruction::LT),
+        BinopTag::LEq => image.push_instruction(Instruction::LTE),
+        BinopTag::Gt => image.push_instruction(Instruction::GT),
+        BinopTag::GEq => image.push_instruction(Instruction::GTE),
+        BinopTag::Mod => image.push_instruction(Instruction::MOD),
+        BinopTag::LShift => image.push_instruction(Instruction::SHL),
+        BinopTag::RShift => image.push_instruction(Instruction::SHR),
+        BinopTag::URShift => image.push_instruction(Instruction::USHR),
+        BinopTag::BitAnd => image.push_instruction(Instruction::BITAND),
+        BinopTag::BitOr => image.push_instruction(Instruction::BITOR),
+        BinopTag::BitXor => image.push_instruction(Instruction::BITXOR),
+        BinopTag::In => image.push_instruction(Instruction::IN),
+        BinopTag::Instanceof => image.push_instruction(Instruction::INSTANCEOF),
+    }
+}
 
-        _ => panic!("Unsupported statement"),
+fn compile_unop(image: &mut Image, op: UnopTag, argument: Expr) -> Result<(), CompileError> {
+    if let (UnopTag::Typeof, Expr::Id(id)) = (&op, &argument) {
+        image.push_instruction(Instruction::TYPEOFIDENT(id.name.as_ref().to_owned()));
+        return Ok(())
     }
+
+    let instruction = match op {
+        UnopTag::Not => Instruction::NOT,
+        UnopTag::Minus => Instruction::NEG,
+        UnopTag::Plus => Instruction::TONUMBER,
+        UnopTag::BitNot => Instruction::BITNOT,
+        UnopTag::Typeof => Instruction::TYPEOF,
+        UnopTag::Void => {
+            compile_expression(image, argument)?;
+            image.push_instruction(Instruction::POP);
+            image.push_instruction(Instruction::UNDEFINED);
+            return Ok(())
+        },
+        UnopTag::Delete => {
+            match argument {
+                Expr::Dot(_, object, DotKey { value, .. }) => {
+                    compile_expression(image, *object)?;
+                    image.push_string(value);
+                    image.push_instruction(Instruction::DELETEPROP);
+                },
+                Expr::Brack(_, object, key) => {
+                    compile_expression(image, *object)?;
+                    compile_expression(image, *key)?;
+                    image.push_instruction(Instruction::DELETEPROP);
+                },
+                Expr::Id(id) => image.push_instruction(Instruction::DELETEIDENT(id_to_string(id))),
+                // Deleting anything that is not a reference does nothing
+                argument => {
+                    compile_expression(image, argument)?;
+                    image.push_instruction(Instruction::POP);
+                    image.push_instruction(Instruction::PUSHTRUE);
+                },
+            }
+            return Ok(())
+        },
+    };
+
+    compile_expression(image, argument)?;
+    image.push_instruction(instruction);
+    Ok(())
 }
 
-fn compile_ass_op(image: &mut Image, assop: easter::punc::Assop, id: String) {
-    match assop.tag {
-        AssopTag::Eq => image.push_instruction(Instruction::ASSIGNEQ(id)),
-        AssopTag::PlusEq => image.push_instruction(Instruction::ASSIGNPLUSEQ(id)),
-        AssopTag::MinusEq => image.push_instruction(Instruction::ASSIGNSUBEQ(id)),
-        AssopTag::DivEq => image.push_instruction(Instruction::ASSIGNDIVEQ(id)),
-        AssopTag::TimesEq => image.push_instruction(Instruction::ASSIGNMLPEQ(id)),
+fn compile_ass_op(image: &mut Image, assop: easter::punc::Assop, target: AssignTarget, value: Expr) -> Result<(), CompileError> {
+    let id = match target {
+        AssignTarget::Id(id) => id_to_string(id),
+        AssignTarget::Dot(_, object, DotKey { value: key, .. }) => {
+            compile_expression(image, *object)?;
+            image.push_string(key);
+            return compile_member_ass_op(image, assop, value);
+        },
+        AssignTarget::Brack(_, object, key) => {
+            compile_expression(image, *object)?;
+            compile_expression(image, *key)?;
+            return compile_member_ass_op(image, assop, value);
+        },
+    };
+
+    let operator = match assop.tag {
+        AssopTag::Eq => Instruction::ASSIGNEQ(id),
+        AssopTag::PlusEq => Instruction::ASSIGNPLUSEQ(id),
+        AssopTag::MinusEq => Instruction::ASSIGNSUBEQ(id),
+        AssopTag::DivEq => Instruction::ASSIGNDIVEQ(id),
+        AssopTag::TimesEq => Instruction::ASSIGNMLPEQ(id),
+
+        ref tag => {
+            // The other operators read the variable, apply the operator and
+            // assign the result
+            let operator = compound_operator(tag).unwrap();
+            image.push_instruction(Instruction::READIDENT(id.clone()));
+            compile_expression(image, value)?;
+            image.push_instruction(operator);
+            image.push_instruction(Instruction::ASSIGNEQ(id));
+            return Ok(())
+        },
+    };
+
+    compile_expression(image, value)?;
+    image.push_instruction(operator);
+
+    Ok(())
+}
+
+/// Whether an expression can be assigned to.
+fn is_reference(expr: &Expr) -> bool {
+    matches!(expr, &Expr::Id(_) | &Expr::Dot(..) | &Expr::Brack(..))
+}
 
+/// Compiles `++` or `--`, using `ident` for variables and `property` for
+/// members.
+fn compile_update(image: &mut Image, operand: Expr, ident: fn(String) -> Instruction, property: Instruction) -> Result<(), CompileError> {
+    match operand {
+        Expr::Id(id) => image.push_instruction(ident(id_to_string(id))),
+        Expr::Dot(_, object, DotKey { value, .. }) => {
+            compile_expression(image, *object)?;
+            image.push_string(value);
+            image.push_instruction(property);
+        },
+        Expr::Brack(_, object, key) => {
+            compile_expression(image, *object)?;
+            compile_expression(image, *key)?;
+            image.push_instruction(property);
+        },
+        _ => unreachable!("update of an expression that is not a reference"),
+    }
+
+    Ok(())
+}
 
-        _ => panic!("Unsupported assign operation"),
+/// The binary operator a compound assignment applies, `None` for `=`.
+fn compound_operator(tag: &AssopTag) -> Option<Instruction> {
+    match *tag {
+        AssopTag::Eq => None,
+        AssopTag::PlusEq => Some(Instruction::ADD),
+        AssopTag::MinusEq => Some(Instruction::SUB),
+        AssopTag::DivEq => Some(Instruction::DIV),
+        AssopTag::TimesEq => Some(Instruction::MLP),
+        AssopTag::ModEq => Some(Instruction::MOD),
+        AssopTag::LShiftEq => Some(Instruction::SHL),
+        AssopTag::RShiftEq => Some(Instruction::SHR),
+        AssopTag::URShiftEq => Some(Instruction::USHR),
+        AssopTag::BitAndEq => Some(Instruction::BITAND),
+        AssopTag::BitOrEq => Some(Instruction::BITOR),
+        AssopTag::BitXorEq => Some(Instruction::BITXOR),
     }
 }
 
-fn compile_dtor_vec(image: &mut Image, dtor_vec: Vec<Dtor>) {
+/// Assigns to the property whose object and key are on top of the stack.
+fn compile_member_ass_op(image: &mut Image, assop: easter::punc::Assop, value: Expr) -> Result<(), CompileError> {
+    let operator = compound_operator(&assop.tag);
+
+    match operator {
+        Some(operator) => {
+            // Keep the object and key around for the final SETPROP
+            image.push_instruction(Instruction::DUP2);
+            image.push_instruction(Instruction::GETPROP);
+            compile_expression(image, value)?;
+            image.push_instruction(operator);
+        },
+        None => compile_expression(image, value)?,
+    }
+    image.push_instruction(Instruction::SETPROP);
+
+    Ok(())
+}
+
+fn id_to_string(id: Id) -> String {
+    id.name.into_string()
+}
+
+fn compile_dtor_vec(image: &mut Image, dtor_vec: Vec<Dtor>) -> Result<(), CompileError> {
     for dtor in dtor_vec {
-        compile_dtor(image, dtor)
+        compile_dtor(image, dtor)?;
     }
+
+    Ok(())
 }
 
-fn compile_dtor(image: &mut Image, dtor: Dtor) {
+fn compile_dtor(image: &mut Image, dtor: Dtor) -> Result<(), CompileError> {
     match dtor {
         Dtor::Simple(_, identifier, expressions) => {
-            match expressions {
-                Some(expr) => compile_expression(image, expr),
-                None => image.push_instruction(Instruction::UNDEFINED)
+            // The declaration itself is hoisted, so `var a;` has nothing
+            // left to do here
+            if let Some(expr) = expressions {
+                compile_expression(image, expr)?;
+                image.push_instruction(Instruction::PUSHVAR(id_to_string(identifier)));
             }
+        },
+        Dtor::Compound(location, ..) => return Err(CompileError::unsupported("destructuring declaration", &location)),
+    }
+
+    Ok(())
+}
 
-            match identifier.name {
-                joker::word::Name::String(string) => image.push_instruction(Instruction::PUSHVAR(string)),
-                _ => panic!("Unsupported statement"),
+/// Compiles `let` and `const` declarations into the current block scope. All
+/// names are declared up front so they are in their temporal dead zone while
+/// the initializers run.
+fn compile_lexical_dtor_vec(image: &mut Image, dtor_vec: Vec<Dtor>, is_const: bool) -> Result<(), CompileError> {
+    for dtor in &dtor_vec {
+        if let Dtor::Simple(_, id, _) = dtor {
+            let name = id.name.as_ref().to_owned();
+            if is_const {
+                image.push_instruction(Instruction::DECLARECONST(name));
+            } else {
+                image.push_instruction(Instruction::DECLARELET(name));
             }
-        },
-        _ => panic!("Unsupported statement"),
+        }
+    }
+
+    for dtor in dtor_vec {
+        match dtor {
+            Dtor::Simple(_, identifier, expressions) => {
+                match expressions {
+                    Some(expr) => compile_expression(image, expr)?,
+                    None => image.push_instruction(Instruction::UNDEFINED),
+                }
+                image.push_instruction(Instruction::INITBINDING(id_to_string(identifier)));
+            },
+            Dtor::Compound(location, ..) => return Err(CompileError::unsupported("destructuring declaration", &location)),
+        }
     }
+
+    Ok(())
 }
@@ -4,28 +4,94 @@ extern crate rustyline;
 extern crate ansi_term;
 extern crate joker;
 
+#[cfg(not(test))]
 use rustyline::error::ReadlineError;
+#[cfg(not(test))]
 use rustyline::Editor;
 
+#[cfg(not(test))]
 use ansi_term::Colour::RGB;
 
+#[cfg(not(test))]
 use std::env;
+#[cfg(not(test))]
+use std::process;
+#[cfg(not(test))]
+use std::sync::mpsc;
+#[cfg(not(test))]
+use std::sync::mpsc::RecvTimeoutError;
+#[cfg(not(test))]
+use std::thread;
+#[cfg(not(test))]
+use std::time::Duration;
 
 mod bytecode;
 mod tests;
 mod vm;
 
+#[cfg(not(test))]
+const USAGE: &str = "usage: yukon repl [--fuel <instructions>] [--timeout <ms>] [--step <instructions>]
+                  [--heap-limit <bytes>] [--string-limit <bytes>] [--array-limit <length>]
+                  [--gc-budget <objects>] [--gc-stress]";
+
+/// How the REPL runs every line, set with command line flags.
+#[cfg(not(test))]
+#[derive(Default)]
+struct Options {
+    limits: vm::heap::Limits,
+    fuel: Option<u64>,
+    /// Interrupts lines running longer than this
+    timeout: Option<Duration>,
+    /// Pauses lines after this many instructions, asking whether to go on
+    step: Option<u64>,
+    gc_budget: Option<usize>,
+    gc_stress: bool,
+}
+
 #[cfg(not(test))]
 fn main() {
     let args: Vec<String> = env::args().collect();
 
     if args.len() > 1 && args[1] == "repl" {
-        run_repl();
+        match parse_options(&args[2..]) {
+            Ok(options) => run_repl(&options),
+            Err(why) => {
+                println!("{}\n{}", why, USAGE);
+                process::exit(2)
+            },
+        }
     } else {
         devel();
     }
 }
 
+#[cfg(not(test))]
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    fn number<T: std::str::FromStr>(flag: &str, value: Option<&String>) -> Result<T, String> {
+        match value.map(|value| value.parse()) {
+            Some(Ok(number)) => Ok(number),
+            _ => Err(format!("{} needs a number", flag)),
+        }
+    }
+
+    let mut options = Options::default();
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--fuel" => options.fuel = Some(number(flag, args.next())?),
+            "--timeout" => options.timeout = Some(Duration::from_millis(number(flag, args.next())?)),
+            "--step" => options.step = Some(number(flag, args.next())?),
+            "--heap-limit" => options.limits.heap_bytes = Some(number(flag, args.next())?),
+            "--string-limit" => options.limits.string_length = Some(number(flag, args.next())?),
+            "--array-limit" => options.limits.array_length = Some(number(flag, args.next())?),
+            "--gc-budget" => options.gc_budget = Some(number(flag, args.next())?),
+            "--gc-stress" => options.gc_stress = true,
+            _ => return Err(format!("Unknown option {}", flag)),
+        }
+    }
+    Ok(options)
+}
+
 #[cfg(not(test))]
 fn devel() {
     match esprit::script("") {
@@ -35,7 +101,7 @@ fn devel() {
 }
 
 #[cfg(not(test))]
-fn run_repl() {
+fn run_repl(options: &Options) {
     let mut rl = Editor::<()>::new();
     let mut scope = vm::scope::Scope::new_global();
 
@@ -44,8 +110,10 @@ fn run_repl() {
         match readline {
             Ok(line) => {
                 rl.add_history_entry(&line);
-                if !line.is_empty() {
-                    compile_repl(&line, &mut scope);
+                if line.trim() == ".gc" {
+                    collect_garbage(&mut scope);
+                } else if !line.is_empty() {
+                    compile_repl(&line, &mut scope, options, &mut rl);
                 }
             },
             Err(ReadlineError::Interrupted) => {
@@ -64,17 +132,68 @@ fn run_repl() {
     }
 }
 
+/// Runs a collection for the `.gc` command and reports on the heap.
 #[cfg(not(test))]
-fn compile_repl(code: &str, mut scope: &mut vm::scope::Scope) {
-    let image: bytecode::Image;
+fn collect_garbage(scope: &mut vm::scope::Scope) {
+    let image = bytecode::Image { script: bytecode::Block::new(), blocks: Vec::new() };
+    let mut engine = vm::VM::new(image, scope);
+    let freed = engine.collect_garbage();
+    println!("{}", RGB(130, 130, 130).paint(format!("freed {} objects, {} alive, {:?}", freed, engine.live_objects(), engine.gc_stats())));
+}
 
-    match esprit::script(code) {
-        Err(why) => panic!("Could not compile {:?}", why),
-        Ok(ast) => image = bytecode::compile_to_image(ast.body)
+#[cfg(not(test))]
+fn compile_repl(code: &str, scope: &mut vm::scope::Scope, options: &Options, rl: &mut Editor<()>) {
+    let image = match esprit::script(code) {
+        Err(why) => return println!("SyntaxError: {:?}", why),
+        Ok(ast) => match bytecode::compile_to_image(ast.body) {
+            Err(why) => return println!("{}", why.report(code)),
+            Ok(image) => image,
+        }
     };
-    let mut engine = vm::VM::new(image, &mut scope);
-    engine.run();
+    let mut engine = vm::VM::new(image, scope);
+    engine.set_limits(options.limits);
+    engine.set_fuel(options.fuel);
+    engine.set_gc_budget(options.gc_budget);
+    engine.set_gc_stress(options.gc_stress);
 
-    let retval = engine.read_stack_end();
-    println!("{}", RGB(130, 130, 130).paint(vm::repl::ret_value_fmt(&retval)));
+    // Lines that finish in time drop `_finished`, which calls off the watchdog
+    let (_finished, finishing) = mpsc::channel::<()>();
+    if let Some(timeout) = options.timeout {
+        let handle = engine.interrupt_handle();
+        thread::spawn(move || {
+            if let Err(RecvTimeoutError::Timeout) = finishing.recv_timeout(timeout) {
+                handle.interrupt();
+            }
+        });
+    }
+
+    match run_stepping(&mut engine, options.step, rl) {
+        Ok(retval) => println!("{}", RGB(130, 130, 130).paint(vm::repl::ret_value_fmt(&retval))),
+        Err(ref why) if why.termination.is_some() => println!("{}", RGB(224, 108, 117).paint(why.to_string())),
+        Err(why) => println!("{}", RGB(224, 108, 117).paint(format!("Uncaught {}", why))),
+    }
+}
+
+/// Runs the line, pausing every `step` instructions to ask whether it
+/// should go on. Stopping it interrupts it.
+#[cfg(not(test))]
+fn run_stepping(engine: &mut vm::VM, step: Option<u64>, rl: &mut Editor<()>) -> Result<vm::JsValue, vm::error::JsError> {
+    let step = match step {
+        Some(step) => step,
+        None => return engine.run(),
+    };
+
+    let mut status = engine.run_for(step)?;
+    loop {
+        match status {
+            vm::Status::Finished(value) => return Ok(value),
+            vm::Status::Suspended => {
+                let answer = rl.readline(&format!("paused after {} instructions, continue? [Y/n] ", step));
+                if !matches!(answer.as_ref().map(|answer| answer.trim()), Ok("") | Ok("y") | Ok("Y")) {
+                    engine.interrupt_handle().interrupt();
+                }
+                status = engine.resume(Some(step))?;
+            },
+        }
+    }
 }
@@ -1,3 +1,7 @@
+// The tests keep the style they were first written in, with explicit
+// returns and `as f64` number literals
+#![allow(clippy::unnecessary_cast, clippy::needless_late_init, clippy::needless_return)]
+
 #[cfg(test)]
 mod bytecode_tests {
     use bytecode::*;
@@ -8,7 +12,7 @@ mod bytecode_tests {
 
         match esprit::script(code) {
             Err(why) => panic!("Could not compile, {:?}", why),
-            Ok(ast) => image = compile_to_image(ast.body)
+            Ok(ast) => image = compile_to_image(ast.body).unwrap_or_else(|why| panic!("{}", why))
         };
 
         return image;
@@ -17,7 +21,7 @@ mod bytecode_tests {
     #[test]
     fn bytecode_binaryop_plus() {
         assert_eq!(Image {
-            script: Block {instructions: vec![Instruction::PUSHNUM(1 as f64), Instruction::PUSHNUM(10 as f64), Instruction::ADD]},
+            script: Block {instructions: vec![Instruction::PUSHNUM(10 as f64), Instruction::PUSHNUM(1 as f64), Instruction::ADD], handlers: vec![]},
             blocks: vec![],
         }, compile_or_panic("10 + 1"));
     }
@@ -25,7 +29,7 @@ mod bytecode_tests {
     #[test]
     fn bytecode_binaryop_minus() {
         assert_eq!(Image {
-            script: Block {instructions: vec![Instruction::PUSHNUM(1 as f64), Instruction::PUSHNUM(10 as f64), Instruction::SUB]},
+            script: Block {instructions: vec![Instruction::PUSHNUM(10 as f64), Instruction::PUSHNUM(1 as f64), Instruction::SUB], handlers: vec![]},
             blocks: vec![],
         }, compile_or_panic("10 - 1"));
     }
@@ -33,7 +37,7 @@ mod bytecode_tests {
     #[test]
     fn bytecode_binaryop_divide() {
         assert_eq!(Image {
-            script: Block {instructions: vec![Instruction::PUSHNUM(1 as f64), Instruction::PUSHNUM(10 as f64), Instruction::DIV]},
+            script: Block {instructions: vec![Instruction::PUSHNUM(10 as f64), Instruction::PUSHNUM(1 as f64), Instruction::DIV], handlers: vec![]},
             blocks: vec![],
         }, compile_or_panic("10 / 1"));
     }
@@ -41,20 +45,274 @@ mod bytecode_tests {
     #[test]
     fn bytecode_binaryop_multiply() {
         assert_eq!(Image {
-            script: Block {instructions: vec![Instruction::PUSHNUM(1 as f64), Instruction::PUSHNUM(10 as f64), Instruction::MLP]},
+            script: Block {instructions: vec![Instruction::PUSHNUM(10 as f64), Instruction::PUSHNUM(1 as f64), Instruction::MLP], handlers: vec![]},
             blocks: vec![],
         }, compile_or_panic("10 * 1"));
     }
 
     #[test]
     fn bytecode_helloworld_string() {
-        let image: Image;
-
         assert_eq!(Image {
-            script: Block {instructions: vec![Instruction::PUSHSTRLIT("hello, world".to_owned())]},
+            script: Block {instructions: vec![Instruction::PUSHSTRLIT("hello, world".to_owned())], handlers: vec![]},
             blocks: vec![],
         }, compile_or_panic("\"hello, world\""));
     }
+
+    #[test]
+    fn bytecode_if_else() {
+        assert_eq!(Image {
+            script: Block {instructions: vec![
+                Instruction::PUSHTRUE,
+                Instruction::JUMPIFFALSE(5),
+                Instruction::PUSHNUM(1 as f64),
+                Instruction::POP,
+                Instruction::JUMP(7),
+                Instruction::PUSHNUM(2 as f64),
+                Instruction::POP,
+            ], handlers: vec![]},
+            blocks: vec![],
+        }, compile_or_panic("if (true) 1; else 2;"));
+    }
+
+    #[test]
+    fn bytecode_function_block() {
+        assert_eq!(Image {
+            script: Block {instructions: vec![
+                Instruction::MAKEFUNCTION(0),
+                Instruction::PUSHVAR("f".to_owned()),
+                Instruction::READIDENT("f".to_owned()),
+                Instruction::PUSHNUM(1 as f64),
+                Instruction::CALL(1),
+            ], handlers: vec![]},
+            blocks: vec![Block {instructions: vec![
+                Instruction::ARGUMENTS,
+                Instruction::PUSHVAR("arguments".to_owned()),
+                Instruction::ARG(0),
+                Instruction::PUSHVAR("a".to_owned()),
+                Instruction::READIDENT("a".to_owned()),
+                Instruction::RETURN,
+                Instruction::UNDEFINED,
+                Instruction::RETURN,
+            ], handlers: vec![]}],
+        }, compile_or_panic("function f(a) { return a } f(1)"));
+    }
+
+    #[test]
+    fn bytecode_try_catch() {
+        assert_eq!(Image {
+            script: Block {instructions: vec![
+                Instruction::PUSHNUM(1 as f64),
+                Instruction::POP,
+                Instruction::JUMP(9),
+                Instruction::PUSHSCOPE,
+                Instruction::DECLARELET("e".to_owned()),
+                Instruction::INITBINDING("e".to_owned()),
+                Instruction::PUSHNUM(2 as f64),
+                Instruction::POP,
+                Instruction::POPSCOPE,
+            ], handlers: vec![
+                Handler { start: 0, end: 3, target: 3, stack_depth: 0, scope_depth: 0 },
+            ]},
+            blocks: vec![],
+        }, compile_or_panic("try { 1 } catch (e) { 2 }"));
+    }
+
+    fn compile_error(code: &str) -> CompileError {
+        match esprit::script(code) {
+            Err(why) => panic!("Could not parse, {:?}", why),
+            Ok(ast) => compile_to_image(ast.body).expect_err("Expected a compile error"),
+        }
+    }
+
+    #[test]
+    fn compile_error_names_construct_and_position() {
+        let code = "var o = 1;\nvar k;\n    for (k in o) {}";
+        let error = compile_error(code);
+
+        assert_eq!("for…in", error.construct);
+        assert_eq!(Some((3, 5)), error.position());
+        assert_eq!("unsupported: `for…in` at 3:5", error.to_string());
+    }
+
+    #[test]
+    fn compile_error_excerpt() {
+        let code = "var a = 1;\na, 2;";
+        let error = compile_error(code);
+
+        assert_eq!("unsupported: `,` at 2:1", error.to_string());
+        assert_eq!(Some("a, 2;\n^^^^".to_owned()), error.excerpt(code));
+        assert_eq!("unsupported: `,` at 2:1\na, 2;\n^^^^", error.report(code));
+    }
+
+    #[test]
+    fn bytecode_member_compound_assignment() {
+        assert_eq!(Image {
+            script: Block {instructions: vec![
+                Instruction::READIDENT("o".to_owned()),
+                Instruction::PUSHSTRLIT("a".to_owned()),
+                Instruction::DUP2,
+                Instruction::GETPROP,
+                Instruction::PUSHNUM(1 as f64),
+                Instruction::ADD,
+                Instruction::SETPROP,
+            ], handlers: vec![]},
+            blocks: vec![],
+        }, compile_or_panic("o.a += 1"));
+    }
+
+    #[test]
+    fn bytecode_update() {
+        assert_eq!(Image {
+            script: Block {instructions: vec![
+                Instruction::POSTINC("i".to_owned()),
+                Instruction::POP,
+                Instruction::READIDENT("o".to_owned()),
+                Instruction::PUSHSTRLIT("a".to_owned()),
+                Instruction::PREDECPROP,
+            ], handlers: vec![]},
+            blocks: vec![],
+        }, compile_or_panic("i++; --o.a"));
+    }
+
+    #[test]
+    fn compile_error_update_of_non_reference() {
+        let code = "var f;\nf()++;";
+        let error = compile_error(code);
+
+        assert_eq!("unsupported: `++` at 2:1", error.to_string());
+        assert_eq!(Some("f()++;\n^^^^^".to_owned()), error.excerpt(code));
+    }
+
+    #[test]
+    fn bytecode_array_literal_with_hole() {
+        assert_eq!(Image {
+            script: Block {instructions: vec![
+                Instruction::NEWARRAY,
+                Instruction::PUSHNUM(1 as f64),
+                Instruction::ARRAYPUSH,
+                Instruction::ARRAYHOLE,
+                Instruction::PUSHNUM(3 as f64),
+                Instruction::ARRAYPUSH,
+            ], handlers: vec![]},
+            blocks: vec![],
+        }, compile_or_panic("[1, , 3]"));
+    }
+
+    #[test]
+    fn bytecode_method_call() {
+        assert_eq!(Image {
+            script: Block {instructions: vec![
+                Instruction::READIDENT("a".to_owned()),
+                Instruction::DUP,
+                Instruction::PUSHSTRLIT("push".to_owned()),
+                Instruction::GETPROP,
+                Instruction::PUSHNUM(1 as f64),
+                Instruction::CALLMETHOD(1),
+            ], handlers: vec![]},
+            blocks: vec![],
+        }, compile_or_panic("a.push(1)"));
+    }
+
+    #[test]
+    fn bytecode_new() {
+        assert_eq!(Image {
+            script: Block {instructions: vec![
+                Instruction::READIDENT("F".to_owned()),
+                Instruction::PUSHNUM(1 as f64),
+                Instruction::NEW(1),
+                Instruction::POP,
+                Instruction::READIDENT("G".to_owned()),
+                Instruction::NEW(0),
+            ], handlers: vec![]},
+            blocks: vec![],
+        }, compile_or_panic("new F(1); new G"));
+    }
+
+    #[test]
+    fn bytecode_unary_operators() {
+        assert_eq!(Image {
+            script: Block {instructions: vec![
+                Instruction::PUSHNUM(1 as f64),
+                Instruction::NEG,
+                Instruction::NOT,
+                Instruction::POP,
+                Instruction::READIDENT("x".to_owned()),
+                Instruction::POP,
+                Instruction::UNDEFINED,
+            ], handlers: vec![]},
+            blocks: vec![],
+        }, compile_or_panic("!-1; void x"));
+    }
+
+    #[test]
+    fn bytecode_delete() {
+        assert_eq!(Image {
+            script: Block {instructions: vec![
+                Instruction::READIDENT("o".to_owned()),
+                Instruction::PUSHSTRLIT("a".to_owned()),
+                Instruction::DELETEPROP,
+                Instruction::POP,
+                Instruction::DELETEIDENT("x".to_owned()),
+            ], handlers: vec![]},
+            blocks: vec![],
+        }, compile_or_panic("delete o.a; delete x"));
+    }
+
+    #[test]
+    fn bytecode_compound_shift_assignment() {
+        assert_eq!(Image {
+            script: Block {instructions: vec![
+                Instruction::READIDENT("a".to_owned()),
+                Instruction::PUSHNUM(2 as f64),
+                Instruction::SHL,
+                Instruction::ASSIGNEQ("a".to_owned()),
+            ], handlers: vec![]},
+            blocks: vec![],
+        }, compile_or_panic("a <<= 2"));
+    }
+
+    #[test]
+    fn bytecode_logical_and() {
+        assert_eq!(Image {
+            script: Block {instructions: vec![
+                Instruction::READIDENT("a".to_owned()),
+                Instruction::DUP,
+                Instruction::JUMPIFFALSE(7),
+                Instruction::POP,
+                Instruction::READIDENT("b".to_owned()),
+                Instruction::PUSHSTRLIT("c".to_owned()),
+                Instruction::GETPROP,
+            ], handlers: vec![]},
+            blocks: vec![],
+        }, compile_or_panic("a && b.c"));
+    }
+
+    #[test]
+    fn bytecode_getter() {
+        assert_eq!(Image {
+            script: Block {instructions: vec![
+                Instruction::NEWOBJECT,
+                Instruction::PUSHSTRLIT("a".to_owned()),
+                Instruction::MAKEFUNCTION(0),
+                Instruction::INITGETTER,
+            ], handlers: vec![]},
+            blocks: vec![Block {instructions: vec![
+                Instruction::ARGUMENTS,
+                Instruction::PUSHVAR("arguments".to_owned()),
+                Instruction::PUSHNUM(1 as f64),
+                Instruction::RETURN,
+                Instruction::UNDEFINED,
+                Instruction::RETURN,
+            ], handlers: vec![]}],
+        }, compile_or_panic("({get a() { return 1; }})"));
+    }
+
+    #[test]
+    fn compile_error_inside_function() {
+        let error = compile_error("function f() {\n  return a, b\n}");
+
+        assert_eq!("unsupported: `,` at 2:10", error.to_string());
+    }
 }
 
 #[cfg(test)]
@@ -63,22 +321,43 @@ mod vm_tests {
     use esprit;
     use vm;
     use vm::scope::Scope;
+    use vm::error::JsError;
 
     pub fn compile_repl(code: &str) -> vm::JsValue {
         let image: Image;
-        let mut scope = Scope::new_global();
 
         match esprit::script(code) {
             Err(why) => panic!("Could not compile {:?}", why),
-            Ok(ast) => image = compile_to_image(ast.body)
+            Ok(ast) => image = compile_to_image(ast.body).unwrap_or_else(|why| panic!("{}", why))
         };
 
         // println!("{:#?}", image);
 
+        return run_image(image);
+    }
+
+    pub fn run_image(image: Image) -> vm::JsValue {
+        let mut scope = Scope::new_global();
+        let mut engine = vm::VM::new(image, &mut scope);
+
+        match engine.run() {
+            Ok(value) => return value,
+            Err(why) => panic!("Uncaught {}", why),
+        }
+    }
+
+    pub fn compile_repl_error(code: &str) -> JsError {
+        match esprit::script(code) {
+            Err(why) => panic!("Could not compile {:?}", why),
+            Ok(ast) => return run_image_error(compile_to_image(ast.body).unwrap_or_else(|why| panic!("{}", why))),
+        }
+    }
+
+    pub fn run_image_error(image: Image) -> JsError {
+        let mut scope = Scope::new_global();
         let mut engine = vm::VM::new(image, &mut scope);
-        engine.run();
 
-        return engine.read_stack_end();
+        return engine.run().expect_err("Expected an uncaught error");
     }
 
     mod binary_operations {
@@ -87,107 +366,107 @@ mod vm_tests {
 
         #[test]
         fn vm_add_numnum() {
-            assert_eq!(compile_repl("1 + 2"), vm::JsValue::JsNumber(3 as f64))
+            assert_eq!(compile_repl("1 + 2"), vm::JsValue::number(3 as f64))
         }
 
         #[test]
         fn vm_mlp_valid() {
-            assert_eq!(compile_repl("1 * 2"), vm::JsValue::JsNumber(2 as f64))
+            assert_eq!(compile_repl("1 * 2"), vm::JsValue::number(2 as f64))
         }
 
         #[test]
         fn vm_div_valid() {
-            assert_eq!(compile_repl("10 / 2"), vm::JsValue::JsNumber(5 as f64))
+            assert_eq!(compile_repl("10 / 2"), vm::JsValue::number(5 as f64))
         }
 
         #[test]
         fn vm_sub_valid() {
-            assert_eq!(compile_repl("10 - 2"), vm::JsValue::JsNumber(8 as f64))
+            assert_eq!(compile_repl("10 - 2"), vm::JsValue::number(8 as f64))
         }
 
         #[test]
         fn vm_concat_strnum() {
-            assert_eq!(compile_repl("10 + 'hello'"), vm::JsValue::JsString("10hello".to_owned()))
+            assert_eq!(compile_repl("10 + 'hello'"), vm::JsValue::string("10hello".to_owned()))
         }
 
         #[test]
         fn vm_concat_strstr() {
-            assert_eq!(compile_repl("'hello, ' + 'world'"), vm::JsValue::JsString("hello, world".to_owned()))
+            assert_eq!(compile_repl("'hello, ' + 'world'"), vm::JsValue::string("hello, world".to_owned()))
         }
 
         #[test]
         fn vm_sub_invalid() {
-            assert_eq!(compile_repl("10 - \"\""), vm::JsValue::JsNan)
+            assert_eq!(compile_repl("10 - \"ten\""), vm::JsValue::NAN)
         }
 
         #[test]
         fn vm_mlp_invalid() {
-            assert_eq!(compile_repl("10 * \"\""), vm::JsValue::JsNan)
+            assert_eq!(compile_repl("10 * \"ten\""), vm::JsValue::NAN)
         }
 
         #[test]
         fn vm_div_invalid() {
-            assert_eq!(compile_repl("10 / \"\""), vm::JsValue::JsNan)
+            assert_eq!(compile_repl("10 / \"ten\""), vm::JsValue::NAN)
         }
 
         #[test]
         fn vm_sub_numstr() {
-            assert_eq!(compile_repl("10 - \"5\""), vm::JsValue::JsNumber(5 as f64))
+            assert_eq!(compile_repl("10 - \"5\""), vm::JsValue::number(5 as f64))
         }
 
         #[test]
         fn vm_mlp_numstr() {
-            assert_eq!(compile_repl("10 * \"5\""), vm::JsValue::JsNumber(50 as f64))
+            assert_eq!(compile_repl("10 * \"5\""), vm::JsValue::number(50 as f64))
         }
 
         #[test]
         fn vm_div_numstr() {
-            assert_eq!(compile_repl("10 / \"5\""), vm::JsValue::JsNumber(2 as f64))
+            assert_eq!(compile_repl("10 / \"5\""), vm::JsValue::number(2 as f64))
         }
 
         #[test]
         fn vm_sub_numstr_invalid() {
-            assert_eq!(compile_repl("10 - \"a\""), vm::JsValue::JsNan)
+            assert_eq!(compile_repl("10 - \"a\""), vm::JsValue::NAN)
         }
 
         #[test]
         fn vm_mlp_numstr_invalid() {
-            assert_eq!(compile_repl("10 * \"a\""), vm::JsValue::JsNan)
+            assert_eq!(compile_repl("10 * \"a\""), vm::JsValue::NAN)
         }
 
         #[test]
         fn vm_div_numstr_invalid() {
-            assert_eq!(compile_repl("10 / \"a\""), vm::JsValue::JsNan)
+            assert_eq!(compile_repl("10 / \"a\""), vm::JsValue::NAN)
         }
 
         #[test]
         fn vm_sub_strnum() {
-            assert_eq!(compile_repl("\"10\" - 5"), vm::JsValue::JsNumber(5 as f64))
+            assert_eq!(compile_repl("\"10\" - 5"), vm::JsValue::number(5 as f64))
         }
 
         #[test]
         fn vm_mlp_strnum() {
-            assert_eq!(compile_repl("\"5\" * 10"), vm::JsValue::JsNumber(50 as f64))
+            assert_eq!(compile_repl("\"5\" * 10"), vm::JsValue::number(50 as f64))
         }
 
         #[test]
         fn vm_div_strnum() {
-            assert_eq!(compile_repl("\"10\" / 5"), vm::JsValue::JsNumber(2 as f64))
+            assert_eq!(compile_repl("\"10\" / 5"), vm::JsValue::number(2 as f64))
         }
 
         #[test]
         fn vm_sub_strnum_invalid() {
-            assert_eq!(compile_repl("\"a\" - 5"), vm::JsValue::JsNan)
+            assert_eq!(compile_repl("\"a\" - 5"), vm::JsValue::NAN)
         }
 
         #[test]
         fn vm_mlp_strnum_invalid() {
-            assert_eq!(compile_repl("\"a\" * 10"), vm::JsValue::JsNan)
+            assert_eq!(compile_repl("\"a\" * 10"), vm::JsValue::NAN)
         }
 
         #[test]
         fn vm_div_strnum_invalid() {
-            assert_eq!(compile_repl("\"a\" / 5"), vm::JsValue::JsNan)
+            assert_eq!(compile_repl("\"a\" / 5"), vm::JsValue::NAN)
         }
     }
 
@@ -197,143 +476,2551 @@ mod vm_tests {
 
         #[test]
         fn num_eq_strnum() {
-            assert_eq!(compile_repl("1 == '1'"), vm::JsValue::JsTrue)
+            assert_eq!(compile_repl("1 == '1'"), vm::JsValue::TRUE)
         }
 
         #[test]
         fn num_neq_strnum() {
-            assert_eq!(compile_repl("1 != '2'"), vm::JsValue::JsTrue)
+            assert_eq!(compile_repl("1 != '2'"), vm::JsValue::TRUE)
         }
 
         #[test]
         fn num_eq_numstr() {
-            assert_eq!(compile_repl("'1' == 1"), vm::JsValue::JsTrue)
+            assert_eq!(compile_repl("'1' == 1"), vm::JsValue::TRUE)
         }
 
         #[test]
         fn num_neq_numstr() {
-            assert_eq!(compile_repl("'2' != 1"), vm::JsValue::JsTrue)
+            assert_eq!(compile_repl("'2' != 1"), vm::JsValue::TRUE)
         }
 
         #[test]
         fn num_seq_strnum() {
-            assert_eq!(compile_repl("1 === '1'"), vm::JsValue::JsFalse)
+            assert_eq!(compile_repl("1 === '1'"), vm::JsValue::FALSE)
         }
 
         #[test]
         fn num_sneq_strnum() {
-            assert_eq!(compile_repl("1 !== '1'"), vm::JsValue::JsTrue)
+            assert_eq!(compile_repl("1 !== '1'"), vm::JsValue::TRUE)
         }
 
         #[test]
         fn num_seq_numstr() {
-            assert_eq!(compile_repl("'1' === 1"), vm::JsValue::JsFalse)
+            assert_eq!(compile_repl("'1' === 1"), vm::JsValue::FALSE)
         }
 
         #[test]
         fn num_sneq_numstr() {
-            assert_eq!(compile_repl("'1' !== 1"), vm::JsValue::JsTrue)
+            assert_eq!(compile_repl("'1' !== 1"), vm::JsValue::TRUE)
         }
 
         #[test]
         fn bool_empty_string() {
-            assert_eq!(compile_repl("true == ''"), vm::JsValue::JsFalse)
+            assert_eq!(compile_repl("true == ''"), vm::JsValue::FALSE)
         }
 
         #[test]
         fn empty_string_bool() {
-            assert_eq!(compile_repl("'' == false"), vm::JsValue::JsTrue)
+            assert_eq!(compile_repl("'' == false"), vm::JsValue::TRUE)
         }
 
         #[test]
         fn num_sneq() {
-            assert_eq!(compile_repl("2 !== 1"), vm::JsValue::JsTrue)
+            assert_eq!(compile_repl("2 !== 1"), vm::JsValue::TRUE)
         }
 
         #[test]
         fn num_seq() {
-            assert_eq!(compile_repl("1 === 1"), vm::JsValue::JsTrue)
+            assert_eq!(compile_repl("1 === 1"), vm::JsValue::TRUE)
         }
 
         #[test]
         fn str_seq() {
-            assert_eq!(compile_repl("'HELLO' === 'HELLO'"), vm::JsValue::JsTrue)
+            assert_eq!(compile_repl("'HELLO' === 'HELLO'"), vm::JsValue::TRUE)
         }
 
         #[test]
         fn str_eq() {
-            assert_eq!(compile_repl("'HELLO' == 'HELLO'"), vm::JsValue::JsTrue)
+            assert_eq!(compile_repl("'HELLO' == 'HELLO'"), vm::JsValue::TRUE)
         }
 
         #[test]
         fn zerostr_true() {
-            assert_eq!(compile_repl("'0' == false"), vm::JsValue::JsTrue)
+            assert_eq!(compile_repl("'0' == false"), vm::JsValue::TRUE)
         }
 
         #[test]
         fn zero_true() {
-            assert_eq!(compile_repl("0 == false"), vm::JsValue::JsTrue)
+            assert_eq!(compile_repl("0 == false"), vm::JsValue::TRUE)
         }
 
         #[test]
         fn seq_true() {
-            assert_eq!(compile_repl("true === true"), vm::JsValue::JsTrue)
+            assert_eq!(compile_repl("true === true"), vm::JsValue::TRUE)
         }
 
         #[test]
         fn eq_false() {
-            assert_eq!(compile_repl("false == false"), vm::JsValue::JsTrue)
+            assert_eq!(compile_repl("false == false"), vm::JsValue::TRUE)
         }
 
         #[test]
         fn eq_true() {
-            assert_eq!(compile_repl("true == true"), vm::JsValue::JsTrue)
+            assert_eq!(compile_repl("true == true"), vm::JsValue::TRUE)
         }
 
         #[test]
         fn seq_false() {
-            assert_eq!(compile_repl("false === false"), vm::JsValue::JsTrue)
+            assert_eq!(compile_repl("false === false"), vm::JsValue::TRUE)
         }
 
         #[test]
         fn num_eq() {
-            assert_eq!(compile_repl("1 == 1"), vm::JsValue::JsTrue)
+            assert_eq!(compile_repl("1 == 1"), vm::JsValue::TRUE)
         }
     }
 
     mod variables {
         use super::compile_repl;
         use super::vm;
+        use bytecode::*;
+        use esprit;
+        use vm::scope::Scope;
 
         #[test]
         fn variable_assign() {
-            assert_eq!(compile_repl("var a = 1; a;"), vm::JsValue::JsNumber(1 as f64))
+            assert_eq!(compile_repl("var a = 1; a;"), vm::JsValue::number(1 as f64))
         }
 
         #[test]
         fn variable_reassign() {
-            assert_eq!(compile_repl("var a = 1; a = 10; a;"), vm::JsValue::JsNumber(10 as f64))
+            assert_eq!(compile_repl("var a = 1; a = 10; a;"), vm::JsValue::number(10 as f64))
         }
 
         #[test]
         fn variable_pluseq() {
-            assert_eq!(compile_repl("var a = 1; a += 10;"), vm::JsValue::JsNumber(11 as f64))
+            assert_eq!(compile_repl("var a = 1; a += 10;"), vm::JsValue::number(11 as f64))
         }
 
         #[test]
         fn variable_subeq() {
-            assert_eq!(compile_repl("var a = 10; a -= 5;"), vm::JsValue::JsNumber(5 as f64))
+            assert_eq!(compile_repl("var a = 10; a -= 5;"), vm::JsValue::number(5 as f64))
         }
 
         #[test]
         fn variable_diveq() {
-            assert_eq!(compile_repl("var a = 10; a /= 2;"), vm::JsValue::JsNumber(5 as f64))
+            assert_eq!(compile_repl("var a = 10; a /= 2;"), vm::JsValue::number(5 as f64))
         }
 
         #[test]
         fn variable_mlpeq() {
-            assert_eq!(compile_repl("var a = 10; a *= 5;"), vm::JsValue::JsNumber(50 as f64))
+            assert_eq!(compile_repl("var a = 10; a *= 5;"), vm::JsValue::number(50 as f64))
+        }
+
+        #[test]
+        fn global_constants_ignore_assignments() {
+            assert_eq!(compile_repl("undefined = 1; typeof undefined;"), vm::JsValue::string("undefined"));
+            assert_eq!(compile_repl("var undefined = 1; undefined === void 0;"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("NaN = 1; NaN !== NaN;"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("Infinity = 0; Infinity > 1e308;"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("function f() { undefined = 1; } f(); undefined;"), vm::JsValue::UNDEFINED)
+        }
+
+        #[test]
+        fn global_constants_survive_later_lines() {
+            let mut scope = Scope::new_global();
+            let assign = compile_to_image(esprit::script("undefined = 1;").unwrap().body).unwrap();
+            vm::VM::new(assign, &mut scope).run().unwrap();
+
+            let read = compile_to_image(esprit::script("typeof undefined;").unwrap().body).unwrap();
+            assert_eq!(vm::VM::new(read, &mut scope).run().unwrap(), vm::JsValue::string("undefined"))
+        }
+    }
+
+    mod conditionals {
+        use super::compile_repl;
+        use super::vm;
+
+        #[test]
+        fn if_true() {
+            assert_eq!(compile_repl("var a = 1; if (true) { a = 2; } a;"), vm::JsValue::number(2 as f64))
+        }
+
+        #[test]
+        fn if_false() {
+            assert_eq!(compile_repl("var a = 1; if (false) { a = 2; } a;"), vm::JsValue::number(1 as f64))
+        }
+
+        #[test]
+        fn if_else() {
+            assert_eq!(compile_repl("var a; if (0) a = 'then'; else a = 'else'; a;"), vm::JsValue::string("else".to_owned()))
+        }
+
+        #[test]
+        fn else_if_chain() {
+            assert_eq!(compile_repl("var a = 2, b; if (a == 1) b = 1; else if (a == 2) b = 2; else b = 3; b;"), vm::JsValue::number(2 as f64))
+        }
+
+        #[test]
+        fn truthy_values() {
+            assert_eq!(compile_repl("var a = 0; if ('0') a += 1; if (0.5) a += 1; if ('false') a += 1; a;"), vm::JsValue::number(3 as f64))
+        }
+
+        #[test]
+        fn falsy_values() {
+            assert_eq!(compile_repl("var a = 0, u; if ('') a += 1; if (0) a += 1; if (null) a += 1; if (u) a += 1; if ('a' * 1) a += 1; a;"), vm::JsValue::number(0 as f64))
+        }
+    }
+
+    mod loops {
+        use super::compile_repl;
+        use super::vm;
+
+        #[test]
+        fn while_loop() {
+            assert_eq!(compile_repl("var i = 0; while (i != 5) { i += 1; } i;"), vm::JsValue::number(5 as f64))
+        }
+
+        #[test]
+        fn while_false_skips_body() {
+            assert_eq!(compile_repl("var i = 0; while (false) i = 10; i;"), vm::JsValue::number(0 as f64))
+        }
+
+        #[test]
+        fn do_while_runs_once() {
+            assert_eq!(compile_repl("var i = 0; do { i += 1; } while (false); i;"), vm::JsValue::number(1 as f64))
+        }
+
+        #[test]
+        fn do_while_loop() {
+            assert_eq!(compile_repl("var s = ''; do s += 'a'; while (s != 'aaa'); s;"), vm::JsValue::string("aaa".to_owned()))
+        }
+
+        #[test]
+        fn for_loop_with_var() {
+            assert_eq!(compile_repl("var sum = 0; for (var i = 0; i != 5; i += 1) sum += i; sum;"), vm::JsValue::number(10 as f64))
+        }
+
+        #[test]
+        fn for_loop_with_expression_head() {
+            assert_eq!(compile_repl("var i, sum = 0; for (i = 1; i != 4; i += 1) sum += i; i + sum;"), vm::JsValue::number(10 as f64))
+        }
+
+        #[test]
+        fn for_loop_without_test() {
+            assert_eq!(compile_repl("var i = 0; for (;;) { i += 1; if (i == 3) break; } i;"), vm::JsValue::number(3 as f64))
+        }
+
+        #[test]
+        fn while_break() {
+            assert_eq!(compile_repl("var i = 0; while (true) { if (i == 7) break; i += 1; } i;"), vm::JsValue::number(7 as f64))
+        }
+
+        #[test]
+        fn while_continue() {
+            assert_eq!(compile_repl("var i = 0, n = 0, skip = false; while (i != 10) { i += 1; if (skip) { skip = false; continue; } skip = true; n += 1; } n;"), vm::JsValue::number(5 as f64))
+        }
+
+        #[test]
+        fn do_while_continue_runs_test() {
+            assert_eq!(compile_repl("var i = 0; do { i += 1; continue; i = 100; } while (i != 3); i;"), vm::JsValue::number(3 as f64))
+        }
+
+        #[test]
+        fn for_continue_runs_update() {
+            assert_eq!(compile_repl("var n = 0; for (var i = 0; i != 6; i += 1) { if (i == 2) continue; n += 1; } n;"), vm::JsValue::number(5 as f64))
+        }
+
+        #[test]
+        fn nested_loops() {
+            assert_eq!(compile_repl("var n = 0; for (var i = 0; i != 3; i += 1) { var j = 0; while (j != 4) { j += 1; n += 1; } } n;"), vm::JsValue::number(12 as f64))
+        }
+
+        #[test]
+        fn nested_break_only_exits_inner() {
+            assert_eq!(compile_repl("var n = 0; for (var i = 0; i != 3; i += 1) { for (;;) { n += 1; break; } } n;"), vm::JsValue::number(3 as f64))
+        }
+
+        #[test]
+        fn labeled_break_exits_outer() {
+            assert_eq!(compile_repl("var n = 0; outer: for (var i = 0; i != 3; i += 1) { for (var j = 0; j != 3; j += 1) { if (j == 1) break outer; n += 1; } } n;"), vm::JsValue::number(1 as f64))
+        }
+
+        #[test]
+        fn labeled_continue_skips_outer_iteration() {
+            assert_eq!(compile_repl("var n = 0; outer: for (var i = 0; i != 3; i += 1) { for (var j = 0; j != 3; j += 1) { if (j == 1) continue outer; n += 1; } n += 100; } n;"), vm::JsValue::number(3 as f64))
+        }
+
+        #[test]
+        fn multiple_labels() {
+            assert_eq!(compile_repl("var n = 0; a: b: while (true) { while (true) { n += 1; break a; } } n;"), vm::JsValue::number(1 as f64))
+        }
+
+        #[test]
+        fn labeled_block_break() {
+            assert_eq!(compile_repl("var n = 0; block: { n = 1; break block; n = 2; } n;"), vm::JsValue::number(1 as f64))
+        }
+    }
+
+    mod functions {
+        use super::compile_repl;
+        use super::vm;
+
+        #[test]
+        fn call_declaration() {
+            assert_eq!(compile_repl("function add(a, b) { return a + b; } add(1, 2);"), vm::JsValue::number(3 as f64))
+        }
+
+        #[test]
+        fn operands_are_evaluated_left_to_right() {
+            let log = "var log = ''; function a() { log += 'a'; return 1; } function b() { log += 'b'; return 2; } ";
+            for code in ["a() + b(); log;", "a() - b(); log;", "a() < b(); log;", "a() == b(); log;", "a() in {x: b()}; log;"].iter() {
+                assert_eq!(compile_repl(&format!("{}{}", log, code)), vm::JsValue::string("ab".to_owned()), "{}", code);
+            }
+            assert_eq!(compile_repl(&format!("{}{}", log, "a() - b();")), vm::JsValue::number(-1 as f64))
+        }
+
+        #[test]
+        fn declaration_is_hoisted() {
+            assert_eq!(compile_repl("var a = twice(4); function twice(x) { return x * 2; } a;"), vm::JsValue::number(8 as f64))
+        }
+
+        #[test]
+        fn function_expression() {
+            assert_eq!(compile_repl("var greet = function (name) { return 'hello, ' + name; }; greet('world');"), vm::JsValue::string("hello, world".to_owned()))
+        }
+
+        #[test]
+        fn named_function_expression_recursion() {
+            assert_eq!(compile_repl("var f = function fact(n) { if (n == 1) return 1; return n * fact(n - 1); }; f(5);"), vm::JsValue::number(120 as f64))
+        }
+
+        #[test]
+        fn return_without_value() {
+            assert_eq!(compile_repl("function f() { return; } f();"), vm::JsValue::UNDEFINED)
+        }
+
+        #[test]
+        fn missing_return() {
+            assert_eq!(compile_repl("function f() { 1 + 1; } f();"), vm::JsValue::UNDEFINED)
+        }
+
+        #[test]
+        fn missing_arguments_are_undefined() {
+            assert_eq!(compile_repl("function f(a, b) { return b; } f(1);"), vm::JsValue::UNDEFINED)
+        }
+
+        #[test]
+        fn arguments_length() {
+            assert_eq!(compile_repl("function f(a) { return arguments.length; } f(1, 2, 3);"), vm::JsValue::number(3 as f64))
+        }
+
+        #[test]
+        fn arguments_index() {
+            assert_eq!(compile_repl("function f() { return arguments[1]; } f('a', 'b');"), vm::JsValue::string("b".to_owned()))
+        }
+
+        #[test]
+        fn locals_do_not_leak() {
+            assert_eq!(compile_repl("var a = 1; function f() { var a = 2; return a; } f() + a;"), vm::JsValue::number(3 as f64))
+        }
+
+        #[test]
+        fn var_is_hoisted_in_function() {
+            assert_eq!(compile_repl("var a = 1; function f() { a = 2; var a; } f(); a;"), vm::JsValue::number(1 as f64))
+        }
+
+        #[test]
+        fn assign_global_from_function() {
+            assert_eq!(compile_repl("var a = 1; function f() { a = 2; } f(); a;"), vm::JsValue::number(2 as f64))
+        }
+
+        #[test]
+        fn return_from_loop() {
+            assert_eq!(compile_repl("function f() { for (var i = 0; ; i += 1) { if (i == 4) return i; } } f();"), vm::JsValue::number(4 as f64))
+        }
+
+        #[test]
+        fn recursive_fibonacci() {
+            assert_eq!(compile_repl("function fib(n) { if (n == 0) return 0; if (n == 1) return 1; return fib(n - 1) + fib(n - 2); } fib(20);"), vm::JsValue::number(6765 as f64))
+        }
+
+        #[test]
+        fn redeclared_var_keeps_value() {
+            assert_eq!(compile_repl("var a = 1; var a; a;"), vm::JsValue::number(1 as f64))
         }
     }
 
+    mod closures {
+        use super::compile_repl;
+        use super::vm;
+
+        #[test]
+        fn counter() {
+            assert_eq!(compile_repl("function counter() { var n = 0; return function () { n += 1; return n; }; } var c = counter(); c(); c(); c();"), vm::JsValue::number(3 as f64))
+        }
+
+        #[test]
+        fn counters_are_independent() {
+            assert_eq!(compile_repl("function counter() { var n = 0; return function () { n += 1; return n; }; } var a = counter(), b = counter(); a(); a(); b();"), vm::JsValue::number(1 as f64))
+        }
+
+        #[test]
+        fn module_pattern() {
+            assert_eq!(compile_repl("
+                var module = (function () {
+                    var secret = 10;
+                    function add(n) { secret += n; }
+                    function get() { return secret; }
+                    return function (op, n) { if (op == 'add') add(n); return get(); };
+                })();
+                module('add', 5);
+                module('add', 1);
+                module('get');
+            "), vm::JsValue::number(16 as f64))
+        }
+
+        #[test]
+        fn lookup_walks_outward() {
+            assert_eq!(compile_repl("var a = 'global'; function outer() { var b = 'outer'; function inner() { return a + ' ' + b; } return inner(); } outer();"), vm::JsValue::string("global outer".to_owned()))
+        }
+
+        #[test]
+        fn inner_declaration_shadows() {
+            assert_eq!(compile_repl("var a = 1; function f() { var a = 2; function g() { return a; } return g(); } f() + a;"), vm::JsValue::number(3 as f64))
+        }
+
+        #[test]
+        fn assignment_updates_enclosing_scope() {
+            assert_eq!(compile_repl("function f() { var a = 1; function set() { a = 5; } set(); return a; } f();"), vm::JsValue::number(5 as f64))
+        }
+
+        #[test]
+        fn scope_is_lexical() {
+            assert_eq!(compile_repl("var a = 'global'; function read() { return a; } function call() { var a = 'local'; return read(); } call();"), vm::JsValue::string("global".to_owned()))
+        }
+
+        #[test]
+        fn undeclared_assignment_creates_global() {
+            assert_eq!(compile_repl("function f() { function g() { leaked = 'yes'; } g(); } f(); leaked;"), vm::JsValue::string("yes".to_owned()))
+        }
+
+        #[test]
+        fn closure_outlives_call() {
+            assert_eq!(compile_repl("function adder(x) { return function (y) { return x + y; }; } var add5 = adder(5); adder(100); add5(2);"), vm::JsValue::number(7 as f64))
+        }
+    }
+
+    mod block_scoping {
+        use super::compile_repl;
+        use super::compile_repl_error;
+        use super::run_image;
+        use super::run_image_error;
+        use super::vm;
+        use bytecode::*;
+
+        fn script(instructions: Vec<Instruction>) -> Image {
+            Image { script: Block { instructions, handlers: vec![] }, blocks: vec![] }
+        }
+
+        #[test]
+        fn for_let_loop() {
+            assert_eq!(compile_repl("var sum = 0; for (let i = 0; i != 4; i += 1) sum += i; sum;"), vm::JsValue::number(6 as f64))
+        }
+
+        #[test]
+        fn for_let_is_not_visible_after_loop() {
+            assert_eq!(compile_repl("var i = 'outer'; for (let i = 0; i != 2; i += 1) {} i;"), vm::JsValue::string("outer".to_owned()))
+        }
+
+        #[test]
+        fn for_let_binding_per_iteration() {
+            assert_eq!(compile_repl("
+                var first, second;
+                for (let i = 0; i != 2; i += 1) {
+                    if (i == 0) first = function () { return i; };
+                    else second = function () { return i; };
+                }
+                first() + ' ' + second();
+            "), vm::JsValue::string("0 1".to_owned()))
+        }
+
+        #[test]
+        fn for_var_binding_is_shared() {
+            assert_eq!(compile_repl("
+                var first;
+                for (var i = 0; i != 2; i += 1) {
+                    if (i == 0) first = function () { return i; };
+                }
+                first();
+            "), vm::JsValue::number(2 as f64))
+        }
+
+        #[test]
+        fn for_let_break_and_continue() {
+            assert_eq!(compile_repl("
+                var n = 0, i = 'outer';
+                outer: for (let i = 0; i != 5; i += 1) {
+                    for (let j = 0; j != 5; j += 1) {
+                        if (j == 1) continue outer;
+                        if (i == 3) break outer;
+                        n += 1;
+                    }
+                }
+                n + i;
+            "), vm::JsValue::string("3outer".to_owned()))
+        }
+
+        #[test]
+        fn for_let_initializer_in_dead_zone() {
+            assert_eq!(compile_repl_error("for (let i = i; ;) break;").name(), Some("ReferenceError".to_owned()))
+        }
+
+        #[test]
+        fn read_before_initialization() {
+            assert_eq!(run_image_error(script(vec![
+                Instruction::PUSHSCOPE,
+                Instruction::DECLARELET("a".to_owned()),
+                Instruction::READIDENT("a".to_owned()),
+            ])).name(), Some("ReferenceError".to_owned()))
+        }
+
+        #[test]
+        fn const_can_be_read() {
+            assert_eq!(run_image(script(vec![
+                Instruction::PUSHSCOPE,
+                Instruction::DECLARECONST("a".to_owned()),
+                Instruction::PUSHNUM(1 as f64),
+                Instruction::INITBINDING("a".to_owned()),
+                Instruction::READIDENT("a".to_owned()),
+            ])), vm::JsValue::number(1 as f64))
+        }
+
+        #[test]
+        fn const_reassignment() {
+            assert_eq!(run_image_error(script(vec![
+                Instruction::PUSHSCOPE,
+                Instruction::DECLARECONST("a".to_owned()),
+                Instruction::PUSHNUM(1 as f64),
+                Instruction::INITBINDING("a".to_owned()),
+                Instruction::PUSHNUM(2 as f64),
+                Instruction::ASSIGNEQ("a".to_owned()),
+            ])).name(), Some("TypeError".to_owned()))
+        }
+
+        #[test]
+        fn block_scope_shadows_and_ends() {
+            assert_eq!(run_image(script(vec![
+                Instruction::PUSHNUM(1 as f64),
+                Instruction::PUSHVAR("a".to_owned()),
+                Instruction::PUSHSCOPE,
+                Instruction::DECLARELET("a".to_owned()),
+                Instruction::PUSHNUM(2 as f64),
+                Instruction::INITBINDING("a".to_owned()),
+                Instruction::POPSCOPE,
+                Instruction::READIDENT("a".to_owned()),
+            ])), vm::JsValue::number(1 as f64))
+        }
+    }
+
+    mod runtime_errors {
+        use super::compile_repl_error;
+        use super::run_image_error;
+        use super::vm;
+        use bytecode::*;
+
+        #[test]
+        fn call_non_function() {
+            let error = compile_repl_error("var a = 1; a();");
+
+            assert_eq!(error.name(), Some("TypeError".to_owned()));
+            assert_eq!(error.to_string(), "TypeError: 1 is not a function");
+        }
+
+        #[test]
+        fn read_property_of_undefined() {
+            let error = compile_repl_error("var a; a.b;");
+
+            assert_eq!(error.message(), Some("Cannot read property 'b' of undefined".to_owned()));
+        }
+
+        #[test]
+        fn read_undeclared_variable() {
+            let error = compile_repl_error("nothing_here;");
+
+            assert_eq!(error.to_string(), "ReferenceError: nothing_here is not defined");
+            assert_eq!(compile_repl_error("function f() { return nothing_here; } f();").name(), Some("ReferenceError".to_owned()))
+        }
+
+        #[test]
+        fn runaway_recursion() {
+            assert_eq!(compile_repl_error("function f() { return f() } f()").name(), Some("RangeError".to_owned()))
+        }
+
+        #[test]
+        fn error_is_a_js_object() {
+            match compile_repl_error("var a; a();").value.as_object() {
+                Some(error) => assert_eq!(error.get("message"), vm::JsValue::string("undefined is not a function".to_owned())),
+                None => panic!("Expected an error object"),
+            }
+        }
+
+        #[test]
+        fn stack_underflow() {
+            let error = run_image_error(Image { script: Block { instructions: vec![Instruction::POP], handlers: vec![] }, blocks: vec![] });
+
+            assert_eq!(error.to_string(), "InternalError: Stack underflow");
+        }
+
+        #[test]
+        fn function_cannot_pop_its_arguments() {
+            let error = run_image_error(Image {
+                script: Block { instructions: vec![
+                    Instruction::MAKEFUNCTION(0),
+                    Instruction::PUSHNUM(1 as f64),
+                    Instruction::CALL(1),
+                ], handlers: vec![]},
+                blocks: vec![Block { instructions: vec![Instruction::POP, Instruction::RETURN], handlers: vec![] }],
+            });
+
+            assert_eq!(error.to_string(), "InternalError: Stack underflow");
+        }
+
+        #[test]
+        fn initialize_undeclared_binding() {
+            let error = run_image_error(Image {
+                script: Block { instructions: vec![Instruction::PUSHNUM(1 as f64), Instruction::INITBINDING("x".to_owned())], handlers: vec![] },
+                blocks: vec![],
+            });
+
+            assert_eq!(error.to_string(), "InternalError: Initialized the undeclared binding 'x'");
+        }
+
+        #[test]
+        fn missing_function_block() {
+            let error = run_image_error(Image { script: Block { instructions: vec![Instruction::MAKEFUNCTION(3)], handlers: vec![] }, blocks: vec![] });
+
+            assert_eq!(error.name(), Some("InternalError".to_owned()));
+        }
+    }
+
+    mod exceptions {
+        use super::compile_repl;
+        use super::compile_repl_error;
+        use super::vm;
+
+        #[test]
+        fn uncaught_throw() {
+            assert_eq!(compile_repl_error("throw 1;").value, vm::JsValue::number(1 as f64))
+        }
+
+        #[test]
+        fn catch_binds_thrown_value() {
+            assert_eq!(compile_repl("var r; try { throw 5; } catch (e) { r = e; } r;"), vm::JsValue::number(5 as f64))
+        }
+
+        #[test]
+        fn catch_runtime_error() {
+            assert_eq!(compile_repl("var m; try { null.x; } catch (e) { m = e.message; } m;"), vm::JsValue::string("Cannot read property 'x' of null".to_owned()))
+        }
+
+        #[test]
+        fn catch_parameter_is_scoped() {
+            assert_eq!(compile_repl("var e = 1; try { throw 2; } catch (e) { e = 3; } e;"), vm::JsValue::number(1 as f64))
+        }
+
+        #[test]
+        fn catch_across_calls() {
+            assert_eq!(compile_repl("
+                function f() { throw 'x'; }
+                var r;
+                try { r = 1 + f(); } catch (e) { r = e; }
+                r;
+            "), vm::JsValue::string("x".to_owned()))
+        }
+
+        #[test]
+        fn catch_runaway_recursion() {
+            assert_eq!(compile_repl("
+                function f() { return f(); }
+                var r;
+                try { f(); } catch (e) { r = e.name; }
+                r;
+            "), vm::JsValue::string("RangeError".to_owned()))
+        }
+
+        #[test]
+        fn catch_leaves_block_scopes() {
+            assert_eq!(compile_repl("
+                var n = 0;
+                for (let i = 0; i != 3; i += 1) {
+                    try { for (let j = 0; ; ) throw i; } catch (e) { n += e; }
+                }
+                n;
+            "), vm::JsValue::number(3 as f64))
+        }
+
+        #[test]
+        fn finally_on_normal_completion() {
+            assert_eq!(compile_repl("var log = ''; try { log += 'a'; } finally { log += 'b'; } log;"), vm::JsValue::string("ab".to_owned()))
+        }
+
+        #[test]
+        fn finally_on_throw() {
+            assert_eq!(compile_repl("
+                var log = '';
+                try {
+                    try { throw 'x'; } finally { log += 'f'; }
+                } catch (e) { log += e; }
+                log;
+            "), vm::JsValue::string("fx".to_owned()))
+        }
+
+        #[test]
+        fn finally_after_catch() {
+            assert_eq!(compile_repl("var log = ''; try { throw 'x'; } catch (e) { log += e; } finally { log += 'f'; } log;"), vm::JsValue::string("xf".to_owned()))
+        }
+
+        #[test]
+        fn finally_on_throw_from_catch() {
+            let error = compile_repl_error("try { throw 1; } catch (e) { throw 2; } finally { log = 'f'; }");
+
+            assert_eq!(error.value, vm::JsValue::number(2 as f64));
+        }
+
+        #[test]
+        fn finally_on_break() {
+            assert_eq!(compile_repl("var log = ''; while (true) { try { break; } finally { log += 'f'; } } log;"), vm::JsValue::string("f".to_owned()))
+        }
+
+        #[test]
+        fn finally_on_continue() {
+            assert_eq!(compile_repl("
+                var i = 0, log = '';
+                while (i != 2) {
+                    i += 1;
+                    try { continue; } finally { log += i; }
+                }
+                log;
+            "), vm::JsValue::string("12".to_owned()))
+        }
+
+        #[test]
+        fn finally_on_break_from_catch() {
+            assert_eq!(compile_repl("var log = ''; for (;;) { try { throw 1; } catch (e) { break; } finally { log += 'f'; } } log;"), vm::JsValue::string("f".to_owned()))
+        }
+
+        #[test]
+        fn finally_on_labeled_break() {
+            assert_eq!(compile_repl("
+                var log = '';
+                outer: while (true) {
+                    try {
+                        while (true) {
+                            try { break outer; } finally { log += 'a'; }
+                        }
+                    } finally { log += 'b'; }
+                }
+                log;
+            "), vm::JsValue::string("ab".to_owned()))
+        }
+
+        #[test]
+        fn finally_on_return() {
+            assert_eq!(compile_repl("
+                var log = '';
+                function f() {
+                    try {
+                        try { return 'r'; } finally { log += 'a'; }
+                    } finally { log += 'b'; }
+                }
+                var r = f();
+                r + log;
+            "), vm::JsValue::string("rab".to_owned()))
+        }
+
+        #[test]
+        fn finally_return_overrides_return() {
+            assert_eq!(compile_repl("function f() { try { return 1; } finally { return 2; } } f();"), vm::JsValue::number(2 as f64))
+        }
+
+        #[test]
+        fn finally_return_in_finally_runs_outer_finally() {
+            assert_eq!(compile_repl("
+                var log = '';
+                function f() {
+                    try {
+                        try { throw 1; } finally { return 'r'; }
+                    } finally { log += 'f'; }
+                }
+                var r = f();
+                r + log;
+            "), vm::JsValue::string("rf".to_owned()))
+        }
+
+        #[test]
+        fn finally_break_discards_throw() {
+            assert_eq!(compile_repl("for (;;) { try { throw 1; } finally { break; } } 'ok';"), vm::JsValue::string("ok".to_owned()))
+        }
+
+        #[test]
+        fn caught_errors_have_prototypes() {
+            assert_eq!(compile_repl("var r; try { null.x } catch (e) { r = e instanceof TypeError; } r;"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("var r; try { nothing_here } catch (e) { r = e instanceof ReferenceError && e instanceof Error; } r;"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("var r; try { null.x } catch (e) { r = Object.getPrototypeOf(e) == TypeError.prototype; } r;"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("var r; try { null.x } catch (e) { r = e.toString(); } r;"), vm::JsValue::string("TypeError: Cannot read property 'x' of null"))
+        }
+
+        #[test]
+        fn error_constructors() {
+            assert_eq!(compile_repl("new Error('boom').message;"), vm::JsValue::string("boom"));
+            assert_eq!(compile_repl("RangeError('r') instanceof RangeError;"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("'' + new RangeError('r');"), vm::JsValue::string("RangeError: r"));
+            assert_eq!(compile_repl("new Error().toString();"), vm::JsValue::string("Error"));
+            assert_eq!(compile_repl("Object.getPrototypeOf(TypeError) == Error;"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl_error("throw new TypeError('t');").to_string(), "TypeError: t")
+        }
+    }
+
+    mod objects {
+        use super::compile_repl;
+        use super::compile_repl_error;
+        use super::vm;
+
+        #[test]
+        fn literal_and_dot_read() {
+            assert_eq!(compile_repl("var o = {a: 1, 'b': 2}; o.a + o.b;"), vm::JsValue::number(3 as f64))
+        }
+
+        #[test]
+        fn numeric_key() {
+            assert_eq!(compile_repl("var o = {3: 'c'}; o[3] + o['3'];"), vm::JsValue::string("cc".to_owned()))
+        }
+
+        #[test]
+        fn nested_literal() {
+            assert_eq!(compile_repl("var o = {inner: {x: 5}}; o.inner.x;"), vm::JsValue::number(5 as f64))
+        }
+
+        #[test]
+        fn missing_property() {
+            assert_eq!(compile_repl("({}).a;"), vm::JsValue::UNDEFINED)
+        }
+
+        #[test]
+        fn dot_write() {
+            assert_eq!(compile_repl("var o = {}; o.a = 4; o.a;"), vm::JsValue::number(4 as f64))
+        }
+
+        #[test]
+        fn computed_write() {
+            assert_eq!(compile_repl("var o = {}, k = 'x'; o[k] = 1; o.x;"), vm::JsValue::number(1 as f64))
+        }
+
+        #[test]
+        fn assignment_value() {
+            assert_eq!(compile_repl("var o = {}; (o.a = 3) + 1;"), vm::JsValue::number(4 as f64))
+        }
+
+        #[test]
+        fn compound_assignment() {
+            assert_eq!(compile_repl("var o = {n: 1}; o.n += 2; o['n'] *= 3; o.n -= 1; o.n /= 2; o.n;"), vm::JsValue::number(4 as f64))
+        }
+
+        #[test]
+        fn compound_assignment_concatenates() {
+            assert_eq!(compile_repl("var o = {s: 'a'}; o.s += 'b'; o.s;"), vm::JsValue::string("ab".to_owned()))
+        }
+
+        #[test]
+        fn compound_assignment_evaluates_target_once() {
+            assert_eq!(compile_repl("
+                var calls = 0, o = {c: 1};
+                function f() { calls += 1; return o; }
+                f().c += 1;
+                calls + o.c;
+            "), vm::JsValue::number(3 as f64))
+        }
+
+        #[test]
+        fn objects_are_shared() {
+            assert_eq!(compile_repl("var a = {}; var b = a; b.x = 1; a.x;"), vm::JsValue::number(1 as f64))
+        }
+
+        #[test]
+        fn write_to_undefined() {
+            assert_eq!(compile_repl_error("var o; o.x = 1;").to_string(), "TypeError: Cannot set property 'x' of undefined")
+        }
+    }
+    mod arrays {
+        use super::compile_repl;
+        use super::compile_repl_error;
+        use super::vm;
+
+        fn string(s: &str) -> vm::JsValue {
+            vm::JsValue::string(s.to_owned())
+        }
+
+        #[test]
+        fn literal_index_and_length() {
+            assert_eq!(compile_repl("var a = [1, 'b', 3]; a[1] + a.length;"), string("b3"))
+        }
+
+        #[test]
+        fn holes() {
+            assert_eq!(compile_repl("var a = [1, , 3]; a.length;"), vm::JsValue::number(3 as f64));
+            assert_eq!(compile_repl("[1, , 3][1];"), vm::JsValue::UNDEFINED);
+            assert_eq!(compile_repl("[, , ].length;"), vm::JsValue::number(2 as f64));
+        }
+
+        #[test]
+        fn write_past_end_grows_length() {
+            assert_eq!(compile_repl("var a = []; a[4] = 1; a.length;"), vm::JsValue::number(5 as f64))
+        }
+
+        #[test]
+        fn non_index_key_does_not_grow_length() {
+            assert_eq!(compile_repl("var a = []; a['01'] = 1; a.x = 2; a.length + a['01'];"), vm::JsValue::number(1 as f64))
+        }
+
+        #[test]
+        fn length_truncates_and_extends() {
+            assert_eq!(compile_repl("var a = [1, 2, 3]; a.length = 1; a.length = 3; a.join();"), string("1,,"))
+        }
+
+        #[test]
+        fn invalid_length() {
+            assert_eq!(compile_repl_error("var a = []; a.length = 1.5;").to_string(), "RangeError: Invalid array length")
+        }
+
+        #[test]
+        fn to_string_joins_elements() {
+            assert_eq!(compile_repl("'' + [1, [2, 3], null, undefined];"), string("1,2,3,,"))
+        }
+
+        #[test]
+        fn cyclic_to_string() {
+            assert_eq!(compile_repl("var a = [1]; a[1] = a; '' + a;"), string("1,"))
+        }
+
+        #[test]
+        fn push_and_pop() {
+            assert_eq!(compile_repl("var a = [1]; var n = a.push(2, 3); var last = a.pop(); n + last + a.length;"), vm::JsValue::number(8 as f64));
+            assert_eq!(compile_repl("[].pop();"), vm::JsValue::UNDEFINED)
+        }
+
+        #[test]
+        fn slice() {
+            assert_eq!(compile_repl("[1, 2, 3, 4].slice(1, 3).join();"), string("2,3"));
+            assert_eq!(compile_repl("[1, 2, 3, 4].slice(0 - 2).join();"), string("3,4"));
+            assert_eq!(compile_repl("var a = [1, 2]; var b = a.slice(); b[0] = 5; a[0];"), vm::JsValue::number(1 as f64))
+        }
+
+        #[test]
+        fn splice() {
+            assert_eq!(compile_repl("var a = [1, 2, 3, 4]; var removed = a.splice(1, 2, 'x', 'y', 'z'); removed.join() + ';' + a.join();"), string("2,3;1,x,y,z,4"));
+            assert_eq!(compile_repl("var a = [1, 2, 3]; a.splice(1); a.join();"), string("1"))
+        }
+
+        #[test]
+        fn index_of() {
+            assert_eq!(compile_repl("['a', 'b', 'a'].indexOf('a', 1);"), vm::JsValue::number(2 as f64));
+            assert_eq!(compile_repl("[1].indexOf('1') + 1;"), vm::JsValue::number(0 as f64))
+        }
+
+        #[test]
+        fn join_separator() {
+            assert_eq!(compile_repl("[1, 2, 3].join(' - ');"), string("1 - 2 - 3"))
+        }
+
+        #[test]
+        fn join_holes() {
+            assert_eq!(compile_repl("[1, , 3, , ].join('-');"), string("1--3-"));
+            assert_eq!(compile_repl("var a = [, 'a']; a.length = 4; a.join();"), string(",a,,"))
+        }
+
+        #[test]
+        fn methods_skip_the_holes_of_huge_arrays() {
+            let setup = "var a = []; a.length = 4294967295; a[7] = 'x'; a[4000000000] = 'y';";
+            let run = |code: &str| compile_repl(&format!("{} {}", setup, code));
+
+            assert_eq!(run("var seen = ''; a.forEach(function (v, i) { seen += i + v; }); seen;"), string("7x4000000000y"));
+            assert_eq!(run("var s = a.slice(5); '' + s.length + s[2] + s[3999999995];"), string("4294967290xy"));
+            assert_eq!(run("a.join('');"), string("xy"));
+            assert_eq!(run("a.reduce(function (s, v) { return s + v; });"), string("xy"));
+            assert_eq!(run("a.map(function (v) { return v + v; })[4000000000];"), string("yy"));
+            assert_eq!(run("a.filter(function () { return true; }).length;"), vm::JsValue::number(2 as f64));
+            assert_eq!(run("a.indexOf('y');"), vm::JsValue::number(4000000000.0));
+            assert_eq!(compile_repl_error(&format!("{} a.join();", setup)).to_string(), "RangeError: Invalid string length")
+        }
+
+        #[test]
+        fn callbacks_see_holes_filled_on_the_way() {
+            assert_eq!(compile_repl("var a = [1, , , 4]; var seen = ''; a.forEach(function (v, i) { seen += v; if (i == 0) { a[2] = 3; a[3] = undefined; delete a[3]; a[9] = 9; } }); seen;"), string("13"))
+        }
+
+        #[test]
+        fn map_filter_reduce() {
+            assert_eq!(compile_repl("[1, 2, 3, 4].map(function (x) { return x * 2; }).filter(function (x, i) { return i != 1; }).reduce(function (sum, x) { return sum + x; }, 0);"), vm::JsValue::number(16 as f64))
+        }
+
+        #[test]
+        fn reduce_without_initial_value() {
+            assert_eq!(compile_repl("[1, 2, 3].reduce(function (a, b) { return a + b; });"), vm::JsValue::number(6 as f64));
+            assert_eq!(compile_repl_error("[].reduce(function (a, b) { return a + b; });").to_string(), "TypeError: Reduce of empty array with no initial value")
+        }
+
+        #[test]
+        fn for_each_skips_holes() {
+            assert_eq!(compile_repl("var s = ''; [1, , 3].forEach(function (x, i) { s += i + ':' + x + ' '; }); s;"), string("0:1 2:3 "))
+        }
+
+        #[test]
+        fn callback_error_is_catchable() {
+            assert_eq!(compile_repl("var r; try { [1].map(function () { throw 'no'; }); } catch (e) { r = e; } r;"), string("no"))
+        }
+
+        #[test]
+        fn runaway_recursion_through_callbacks() {
+            assert_eq!(compile_repl_error("function f(x) { return [x].map(f); } f(1);").to_string(), "RangeError: Maximum call stack size exceeded")
+        }
+
+        #[test]
+        fn callback_must_be_a_function() {
+            assert_eq!(compile_repl_error("[1].map(1);").name(), Some("TypeError".to_owned()))
+        }
+
+        #[test]
+        fn default_sort() {
+            assert_eq!(compile_repl("[10, 9, undefined, 1, , 'b', 'a'].sort().join();"), string("1,10,9,a,b,,"))
+        }
+
+        #[test]
+        fn sort_with_comparator() {
+            assert_eq!(compile_repl("[3, 1, 10, 2].sort(function (a, b) { return a - b; }).join();"), string("1,2,3,10"))
+        }
+
+        #[test]
+        fn constructor_and_is_array() {
+            assert_eq!(compile_repl("Array(3).length;"), vm::JsValue::number(3 as f64));
+            assert_eq!(compile_repl("Array(1, 2).join();"), string("1,2"));
+            assert_eq!(compile_repl("Array.isArray([]);"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("Array.isArray({});"), vm::JsValue::FALSE)
+        }
+    }
+    mod prototypes {
+        use super::compile_repl;
+        use super::compile_repl_error;
+        use super::vm;
+
+        #[test]
+        fn lookup_walks_the_chain() {
+            assert_eq!(compile_repl("var a = {x: 1}; var b = Object.create(a); var c = Object.create(b); c.x;"), vm::JsValue::number(1 as f64))
+        }
+
+        #[test]
+        fn own_property_shadows_prototype() {
+            assert_eq!(compile_repl("var a = {x: 1}; var b = Object.create(a); b.x = 2; a.x + b.x;"), vm::JsValue::number(3 as f64))
+        }
+
+        #[test]
+        fn create_with_null_prototype() {
+            assert_eq!(compile_repl("Object.getPrototypeOf(Object.create(null));"), vm::JsValue::NULL);
+            assert_eq!(compile_repl("Object.create(null).hasOwnProperty;"), vm::JsValue::UNDEFINED)
+        }
+
+        #[test]
+        fn create_with_invalid_prototype() {
+            assert_eq!(compile_repl_error("Object.create(1);").to_string(), "TypeError: Object prototype may only be an Object or null: 1")
+        }
+
+        #[test]
+        fn literals_inherit_from_object_prototype() {
+            assert_eq!(compile_repl("Object.getPrototypeOf({}) == Object.prototype;"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("Object.getPrototypeOf([]) == Array.prototype;"), vm::JsValue::TRUE)
+        }
+
+        #[test]
+        fn set_prototype_of() {
+            assert_eq!(compile_repl("var o = {}; var r = Object.setPrototypeOf(o, {y: 2}); r.y + o.y;"), vm::JsValue::number(4 as f64))
+        }
+
+        #[test]
+        fn set_prototype_of_rejects_cycles() {
+            assert_eq!(compile_repl_error("var a = {}; var b = Object.create(a); Object.setPrototypeOf(a, b);").to_string(), "TypeError: Cyclic __proto__ value")
+        }
+
+        #[test]
+        fn has_own_property() {
+            assert_eq!(compile_repl("var o = Object.create({x: 1}); o.y = 2; '' + o.hasOwnProperty('x') + o.hasOwnProperty('y');"), vm::JsValue::string("falsetrue".to_owned()))
+        }
+
+        #[test]
+        fn method_call_binds_this() {
+            assert_eq!(compile_repl("var o = {x: 3, f: function () { return this.x; }}; o.f() + o['f']();"), vm::JsValue::number(6 as f64))
+        }
+
+        #[test]
+        fn plain_call_has_undefined_this() {
+            assert_eq!(compile_repl("var o = {f: function () { return this; }}; var f = o.f; f();"), vm::JsValue::UNDEFINED)
+        }
+
+        #[test]
+        fn inherited_method_binds_receiver() {
+            assert_eq!(compile_repl("var proto = {get: function () { return this.x; }}; var o = Object.create(proto); o.x = 5; o.get();"), vm::JsValue::number(5 as f64))
+        }
+
+        #[test]
+        fn this_is_restored_after_call() {
+            assert_eq!(compile_repl("var o = {x: 1, f: function () { var p = {x: 2, g: function () { return this.x; }}; return p.g() + this.x; }}; o.f();"), vm::JsValue::number(3 as f64))
+        }
+
+        #[test]
+        fn new_links_to_prototype() {
+            assert_eq!(compile_repl("function P(x) { this.x = x; } P.prototype.double = function () { return this.x * 2; }; var p = new P(4); p.double();"), vm::JsValue::number(8 as f64));
+            assert_eq!(compile_repl("function P() {} Object.getPrototypeOf(new P) == P.prototype;"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("function P() {} new P().constructor == P;"), vm::JsValue::TRUE)
+        }
+
+        #[test]
+        fn new_uses_returned_object() {
+            assert_eq!(compile_repl("function P() { this.x = 1; return {x: 2}; } new P().x;"), vm::JsValue::number(2 as f64));
+            assert_eq!(compile_repl("function P() { this.x = 1; return 2; } new P().x;"), vm::JsValue::number(1 as f64))
+        }
+
+        #[test]
+        fn new_with_built_in() {
+            assert_eq!(compile_repl("new Array(2).length;"), vm::JsValue::number(2 as f64));
+            assert_eq!(compile_repl("Object.getPrototypeOf(new Object()) == Object.prototype;"), vm::JsValue::TRUE)
+        }
+
+        #[test]
+        fn built_in_prototypes_are_constant() {
+            assert_eq!(compile_repl("delete Object.prototype;"), vm::JsValue::FALSE);
+            assert_eq!(compile_repl("delete Array.prototype;"), vm::JsValue::FALSE);
+            assert_eq!(compile_repl("delete Number.prototype;"), vm::JsValue::FALSE);
+            assert_eq!(compile_repl("var p = Object.prototype; delete Object.prototype; Object.prototype == p;"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("var p = Array.prototype; Array.prototype = {}; Array.prototype == p;"), vm::JsValue::TRUE)
+        }
+
+        #[test]
+        fn new_non_function() {
+            assert_eq!(compile_repl_error("var o = {}; new o();").to_string(), "TypeError: [object Object] is not a constructor")
+        }
+
+        #[test]
+        fn callback_gets_this_argument() {
+            assert_eq!(compile_repl("var r = []; [1].forEach(function () { r.push(this.tag); }, {tag: 't'}); r[0];"), vm::JsValue::string("t".to_owned()))
+        }
+    }
+    mod descriptors {
+        use super::compile_repl;
+        use super::compile_repl_error;
+        use super::vm;
+
+        fn string(s: &str) -> vm::JsValue {
+            vm::JsValue::string(s.to_owned())
+        }
+
+        #[test]
+        fn define_property_defaults() {
+            assert_eq!(compile_repl("var o = {}; Object.defineProperty(o, 'x', {value: 1}); var d = Object.getOwnPropertyDescriptor(o, 'x'); '' + d.value + d.writable + d.enumerable + d.configurable;"), string("1falsefalsefalse"))
+        }
+
+        #[test]
+        fn assigned_property_descriptor() {
+            assert_eq!(compile_repl("var d = Object.getOwnPropertyDescriptor({x: 1}, 'x'); '' + d.writable + d.enumerable + d.configurable;"), string("truetruetrue"));
+            assert_eq!(compile_repl("Object.getOwnPropertyDescriptor({}, 'x');"), vm::JsValue::UNDEFINED)
+        }
+
+        #[test]
+        fn read_only_assignment_is_ignored() {
+            assert_eq!(compile_repl("var o = {}; Object.defineProperty(o, 'x', {value: 1}); o.x = 2; o.x;"), vm::JsValue::number(1 as f64))
+        }
+
+        #[test]
+        fn inherited_read_only_blocks_assignment() {
+            assert_eq!(compile_repl("var p = {}; Object.defineProperty(p, 'x', {value: 1}); var o = Object.create(p); o.x = 2; '' + o.x + o.hasOwnProperty('x');"), string("1false"))
+        }
+
+        #[test]
+        fn redefine_non_configurable() {
+            assert_eq!(compile_repl_error("var o = {}; Object.defineProperty(o, 'x', {value: 1}); Object.defineProperty(o, 'x', {value: 2});").to_string(), "TypeError: Cannot redefine property: x");
+            assert_eq!(compile_repl("var o = {}; Object.defineProperty(o, 'x', {value: 1}); Object.defineProperty(o, 'x', {value: 1}); o.x;"), vm::JsValue::number(1 as f64))
+        }
+
+        #[test]
+        fn writable_non_configurable_can_change_value() {
+            assert_eq!(compile_repl("var o = {}; Object.defineProperty(o, 'x', {value: 1, writable: true}); Object.defineProperty(o, 'x', {value: 2}); o.x;"), vm::JsValue::number(2 as f64))
+        }
+
+        #[test]
+        fn invalid_descriptors() {
+            assert_eq!(compile_repl_error("Object.defineProperty({}, 'x', 1);").to_string(), "TypeError: Property description must be an object: 1");
+            assert_eq!(compile_repl_error("Object.defineProperty({}, 'x', {get: 1});").to_string(), "TypeError: Getter must be a function: 1");
+            assert_eq!(compile_repl_error("Object.defineProperty({}, 'x', {value: 1, get: function () {}});").name(), Some("TypeError".to_owned()));
+            assert_eq!(compile_repl_error("Object.defineProperty(1, 'x', {});").to_string(), "TypeError: Object.defineProperty called on non-object")
+        }
+
+        #[test]
+        fn accessor_property() {
+            assert_eq!(compile_repl("var o = {n: 1}; Object.defineProperty(o, 'double', {get: function () { return this.n * 2; }}); o.n = 5; o.double;"), vm::JsValue::number(10 as f64))
+        }
+
+        #[test]
+        fn literal_getter_and_setter() {
+            assert_eq!(compile_repl("var o = {v: 1, get x() { return this.v; }, set x(value) { this.v = value * 10; }}; o.x = 2; o.x + o.v;"), vm::JsValue::number(40 as f64))
+        }
+
+        #[test]
+        fn literal_accessor_descriptor() {
+            assert_eq!(compile_repl("var d = Object.getOwnPropertyDescriptor({get x() { return 1; }}, 'x'); '' + (d.set == undefined) + d.enumerable + d.configurable;"), string("truetruetrue"))
+        }
+
+        #[test]
+        fn getter_without_setter_ignores_assignment() {
+            assert_eq!(compile_repl("var o = {get x() { return 1; }}; o.x = 2; o.x;"), vm::JsValue::number(1 as f64))
+        }
+
+        #[test]
+        fn inherited_setter_runs_on_receiver() {
+            assert_eq!(compile_repl("var p = {set x(v) { this.y = v; }}; var o = Object.create(p); o.x = 3; '' + o.y + o.hasOwnProperty('x');"), string("3false"))
+        }
+
+        #[test]
+        fn getter_error_is_catchable() {
+            assert_eq!(compile_repl("var o = {get x() { throw 'bad'; }}; var r; try { o.x; } catch (e) { r = e; } r;"), string("bad"))
+        }
+
+        #[test]
+        fn prevent_extensions() {
+            assert_eq!(compile_repl("var o = {a: 1}; Object.preventExtensions(o); o.b = 2; o.a = 3; '' + o.b + o.a + Object.isExtensible(o);"), string("undefined3false"));
+            assert_eq!(compile_repl_error("var o = Object.preventExtensions({}); Object.defineProperty(o, 'x', {value: 1});").to_string(), "TypeError: Cannot redefine property: x")
+        }
+
+        #[test]
+        fn seal() {
+            assert_eq!(compile_repl("var o = Object.seal({a: 1}); o.a = 2; o.b = 3; '' + o.a + o.b + Object.isSealed(o) + Object.isFrozen(o);"), string("2undefinedtruefalse"))
+        }
+
+        #[test]
+        fn freeze() {
+            assert_eq!(compile_repl("var o = Object.freeze({a: 1, inner: {b: 1}}); o.a = 2; o.inner.b = 2; '' + o.a + o.inner.b + Object.isFrozen(o);"), string("12true"))
+        }
+
+        #[test]
+        fn integrity_of_primitives() {
+            assert_eq!(compile_repl("'' + Object.freeze(1) + Object.isFrozen(1) + Object.isExtensible(1);"), string("1truefalse"))
+        }
+
+        #[test]
+        fn frozen_array() {
+            assert_eq!(compile_repl("var a = Object.freeze([1, 2]); a[0] = 5; a[2] = 3; a.length = 0; '' + a.join() + Object.isFrozen(a);"), string("1,2true"));
+            assert_eq!(compile_repl_error("Object.freeze([1]).push(2);").name(), Some("TypeError".to_owned()))
+        }
+
+        #[test]
+        fn sealed_array_keeps_elements() {
+            assert_eq!(compile_repl("var a = Object.seal([1, 2]); a[0] = 5; a.length = 0; a.join();"), string("5,2"));
+            assert_eq!(compile_repl_error("Object.seal([1]).pop();").name(), Some("TypeError".to_owned()))
+        }
+
+        #[test]
+        fn array_length_descriptor() {
+            assert_eq!(compile_repl("var d = Object.getOwnPropertyDescriptor([1, 2], 'length'); '' + d.value + d.writable + d.enumerable;"), string("2truefalse"));
+            assert_eq!(compile_repl("var a = [1, 2, 3]; Object.defineProperty(a, 'length', {value: 1, writable: false}); a[5] = 1; '' + a.length + a[5];"), string("1undefined"))
+        }
+
+        #[test]
+        fn array_element_with_own_attributes() {
+            assert_eq!(compile_repl("var a = []; Object.defineProperty(a, '0', {value: 1}); var d = Object.getOwnPropertyDescriptor(a, '0'); '' + a.length + a[0] + d.writable + d.enumerable + d.configurable;"), string("11falsefalsefalse"));
+            assert_eq!(compile_repl("var a = [0, 0]; Object.defineProperty(a, '1', {value: 1, enumerable: false}); var d = Object.getOwnPropertyDescriptor(a, '1'); '' + a[1] + d.writable + d.enumerable + d.configurable;"), string("1truefalsetrue"));
+            assert_eq!(compile_repl("var a = [1, 2]; Object.defineProperty(a, '0', {writable: false}); a[0] = 5; a[1] = 6; '' + a;"), string("1,6"));
+            assert_eq!(compile_repl("var a = [1, 2]; Object.defineProperty(a, '1', {value: 2, enumerable: true, writable: true, configurable: true}); Object.getOwnPropertyDescriptor(a, '1').enumerable;"), vm::JsValue::TRUE)
+        }
+
+        #[test]
+        fn array_element_with_own_attributes_in_methods() {
+            assert_eq!(compile_repl("var a = [1, 2, 3]; Object.defineProperty(a, '1', {value: 5, enumerable: false}); var s = 0; a.forEach(function (x) { s += x; }); '' + s + a.join('-') + a.indexOf(5) + a.slice(1);"), string("91-5-315,3"));
+            assert_eq!(compile_repl("var a = [1, 2]; Object.defineProperty(a, '1', {get: function () { return 7; }}); a.reduce(function (x, y) { return x + y; });"), vm::JsValue::number(8 as f64));
+            assert_eq!(compile_repl("var a = [1, 2]; Object.defineProperty(a, '1', {value: 2, writable: true, configurable: true}); a.splice(0, 1); '' + a.length + a[0];"), string("12"));
+            assert_eq!(compile_repl_error("var a = [2, 1]; Object.defineProperty(a, '0', {writable: false}); a.sort();").name(), Some("TypeError".to_owned()));
+            assert_eq!(compile_repl_error("var a = [1]; Object.defineProperty(a, '0', {configurable: false}); a.pop();").name(), Some("TypeError".to_owned()))
+        }
+
+        #[test]
+        fn array_element_with_own_attributes_stays() {
+            assert_eq!(compile_repl("var a = [1, 2]; Object.defineProperty(a, '0', {configurable: false}); delete a[0];"), vm::JsValue::FALSE);
+            assert_eq!(compile_repl("var a = [1, 2, 3]; Object.defineProperty(a, '1', {configurable: false}); a.length = 0; a.length;"), vm::JsValue::number(2 as f64));
+            assert_eq!(compile_repl("var a = [1, 2]; Object.defineProperty(a, '0', {value: 1, writable: true}); Object.freeze(a); a[0] = 3; '' + a[0] + Object.isFrozen(a);"), string("1true"));
+            assert_eq!(compile_repl("var a = [1]; Object.defineProperty(a, '0', {value: 1, configurable: true}); delete a[0]; '' + a.length + a[0];"), string("1undefined"))
+        }
+
+        #[test]
+        fn built_in_methods_are_not_enumerable() {
+            assert_eq!(compile_repl("Object.getOwnPropertyDescriptor(Array.prototype, 'push').enumerable;"), vm::JsValue::FALSE)
+        }
+    }
+    mod unary_operators {
+        use super::compile_repl;
+        use super::compile_repl_error;
+        use super::vm;
+
+        fn string(s: &str) -> vm::JsValue {
+            vm::JsValue::string(s.to_owned())
+        }
+
+        #[test]
+        fn not() {
+            assert_eq!(compile_repl("!0;"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("!'a';"), vm::JsValue::FALSE);
+            assert_eq!(compile_repl("!!{};"), vm::JsValue::TRUE)
+        }
+
+        #[test]
+        fn negate() {
+            assert_eq!(compile_repl("-1;"), vm::JsValue::number(-1 as f64));
+            assert_eq!(compile_repl("-'2';"), vm::JsValue::number(-2 as f64));
+            assert_eq!(compile_repl("-'a';"), vm::JsValue::NAN);
+            assert_eq!(compile_repl("-null;"), vm::JsValue::number(-0.0))
+        }
+
+        #[test]
+        fn plus() {
+            assert_eq!(compile_repl("+'3';"), vm::JsValue::number(3 as f64));
+            assert_eq!(compile_repl("+true;"), vm::JsValue::number(1 as f64));
+            assert_eq!(compile_repl("+'';"), vm::JsValue::number(0 as f64));
+            assert_eq!(compile_repl("+undefined;"), vm::JsValue::NAN);
+            assert_eq!(compile_repl("+[5];"), vm::JsValue::number(5 as f64));
+            assert_eq!(compile_repl("+{};"), vm::JsValue::NAN)
+        }
+
+        #[test]
+        fn bitwise_not() {
+            assert_eq!(compile_repl("~5;"), vm::JsValue::number(-6 as f64));
+            assert_eq!(compile_repl("~-1;"), vm::JsValue::number(0 as f64));
+            assert_eq!(compile_repl("~4294967296;"), vm::JsValue::number(-1 as f64));
+            assert_eq!(compile_repl("~2147483648;"), vm::JsValue::number(2147483647 as f64));
+            assert_eq!(compile_repl("~'a';"), vm::JsValue::number(-1 as f64))
+        }
+
+        #[test]
+        fn type_of() {
+            assert_eq!(compile_repl("typeof 1;"), string("number"));
+            assert_eq!(compile_repl("typeof 'a';"), string("string"));
+            assert_eq!(compile_repl("typeof true;"), string("boolean"));
+            assert_eq!(compile_repl("typeof undefined;"), string("undefined"));
+            assert_eq!(compile_repl("typeof null;"), string("object"));
+            assert_eq!(compile_repl("typeof [];"), string("object"));
+            assert_eq!(compile_repl("typeof function () {};"), string("function"));
+            assert_eq!(compile_repl("typeof Array.isArray;"), string("function"))
+        }
+
+        #[test]
+        fn type_of_undeclared() {
+            assert_eq!(compile_repl("typeof nothing_here;"), string("undefined"));
+            assert_eq!(compile_repl("function f() { return typeof nothing_here; } f();"), string("undefined"));
+            assert_eq!(compile_repl_error("nothing_here;").name(), Some("ReferenceError".to_owned()))
+        }
+
+        #[test]
+        fn void() {
+            assert_eq!(compile_repl("var x = 1; void (x = 2);"), vm::JsValue::UNDEFINED);
+            assert_eq!(compile_repl("var x = 1; void (x = 2); x;"), vm::JsValue::number(2 as f64))
+        }
+
+        #[test]
+        fn delete_property() {
+            assert_eq!(compile_repl("var o = {a: 1}; var r = delete o.a; '' + r + o.a + o.hasOwnProperty('a');"), string("trueundefinedfalse"));
+            assert_eq!(compile_repl("delete ({}).missing;"), vm::JsValue::TRUE)
+        }
+
+        #[test]
+        fn delete_non_configurable() {
+            assert_eq!(compile_repl("var o = Object.freeze({a: 1}); '' + (delete o.a) + o.a;"), string("false1"))
+        }
+
+        #[test]
+        fn delete_array_element_leaves_hole() {
+            assert_eq!(compile_repl("var a = [1, 2, 3]; delete a[1]; '' + a.length + a[1];"), string("3undefined"));
+            assert_eq!(compile_repl("delete [].length;"), vm::JsValue::FALSE)
+        }
+
+        #[test]
+        fn delete_inherited_keeps_prototype() {
+            assert_eq!(compile_repl("var p = {a: 1}; var o = Object.create(p); '' + (delete o.a) + o.a;"), string("true1"))
+        }
+
+        #[test]
+        fn delete_identifier() {
+            assert_eq!(compile_repl("var x = 1; delete x;"), vm::JsValue::FALSE);
+            assert_eq!(compile_repl("delete nothing_here;"), vm::JsValue::TRUE)
+        }
+
+        #[test]
+        fn delete_other_expression() {
+            assert_eq!(compile_repl("delete 1;"), vm::JsValue::TRUE)
+        }
+
+        #[test]
+        fn delete_from_undefined() {
+            assert_eq!(compile_repl_error("var o; delete o.a;").to_string(), "TypeError: Cannot delete property 'a' of undefined")
+        }
+    }
+    mod binary_operators {
+        use super::compile_repl;
+        use super::compile_repl_error;
+        use super::vm;
+
+        fn number(n: f64) -> vm::JsValue {
+            vm::JsValue::number(n)
+        }
+
+        #[test]
+        fn relational_numbers() {
+            assert_eq!(compile_repl("1 < 2;"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("2 <= 2;"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("1 > 2;"), vm::JsValue::FALSE);
+            assert_eq!(compile_repl("2 >= 3;"), vm::JsValue::FALSE)
+        }
+
+        #[test]
+        fn relational_strings_compare_code_units() {
+            assert_eq!(compile_repl("'a' < 'b';"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("'10' < '9';"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("'Z' < 'a';"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("'ab' > 'a';"), vm::JsValue::TRUE)
+        }
+
+        #[test]
+        fn relational_mixed_types_compare_numbers() {
+            assert_eq!(compile_repl("'10' < 9;"), vm::JsValue::FALSE);
+            assert_eq!(compile_repl("null >= 0;"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("[2] > 1;"), vm::JsValue::TRUE)
+        }
+
+        #[test]
+        fn relational_nan_is_always_false() {
+            assert_eq!(compile_repl("undefined < 1;"), vm::JsValue::FALSE);
+            assert_eq!(compile_repl("undefined >= 1;"), vm::JsValue::FALSE);
+            assert_eq!(compile_repl("'a' <= 1;"), vm::JsValue::FALSE)
+        }
+
+        #[test]
+        fn modulo() {
+            assert_eq!(compile_repl("7 % 3;"), number(1 as f64));
+            assert_eq!(compile_repl("-7 % 3;"), number(-1 as f64));
+            assert_eq!(compile_repl("5.5 % 2;"), number(1.5));
+            assert_eq!(compile_repl("1 % 0;"), vm::JsValue::NAN)
+        }
+
+        #[test]
+        fn shifts() {
+            assert_eq!(compile_repl("1 << 3;"), number(8 as f64));
+            assert_eq!(compile_repl("1 << 31;"), number(-2147483648 as f64));
+            assert_eq!(compile_repl("1 << 32;"), number(1 as f64));
+            assert_eq!(compile_repl("-16 >> 2;"), number(-4 as f64));
+            assert_eq!(compile_repl("-1 >>> 0;"), number(4294967295.0));
+            assert_eq!(compile_repl("-16 >>> 28;"), number(15 as f64))
+        }
+
+        #[test]
+        fn bitwise() {
+            assert_eq!(compile_repl("12 & 10;"), number(8 as f64));
+            assert_eq!(compile_repl("12 | 3;"), number(15 as f64));
+            assert_eq!(compile_repl("12 ^ 10;"), number(6 as f64));
+            assert_eq!(compile_repl("4294967297 | 0;"), number(1 as f64));
+            assert_eq!(compile_repl("1.9 | 0;"), number(1 as f64));
+            assert_eq!(compile_repl("'3' & undefined;"), number(0 as f64))
+        }
+
+        #[test]
+        fn compound_assignments() {
+            assert_eq!(compile_repl("var a = 7; a %= 4; a <<= 2; a >>= 1; a |= 1; a &= 5; a ^= 6; a;"), number(3 as f64));
+            assert_eq!(compile_repl("var a = -1; a >>>= 28; a;"), number(15 as f64))
+        }
+
+        #[test]
+        fn member_compound_assignments() {
+            assert_eq!(compile_repl("var o = {n: 5}; o.n %= 3; o['n'] <<= 4; o.n;"), number(32 as f64))
+        }
+
+        #[test]
+        fn in_operator() {
+            assert_eq!(compile_repl("'a' in {a: undefined};"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("'b' in {a: 1};"), vm::JsValue::FALSE);
+            assert_eq!(compile_repl("'hasOwnProperty' in {};"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("0 in [1];"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("'length' in [];"), vm::JsValue::TRUE)
+        }
+
+        #[test]
+        fn in_requires_object() {
+            assert_eq!(compile_repl_error("'a' in 'abc';").to_string(), "TypeError: Cannot use 'in' operator to search for 'a' in abc")
+        }
+
+        #[test]
+        fn instanceof() {
+            assert_eq!(compile_repl("function F() {} new F() instanceof F;"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("function F() {} function G() {} new F() instanceof G;"), vm::JsValue::FALSE);
+            assert_eq!(compile_repl("[] instanceof Array;"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("[] instanceof Object;"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("1 instanceof Object;"), vm::JsValue::FALSE);
+            assert_eq!(compile_repl("Object.create(null) instanceof Object;"), vm::JsValue::FALSE)
+        }
+
+        #[test]
+        fn instanceof_follows_changed_prototype() {
+            assert_eq!(compile_repl("function F() {} var o = {}; Object.setPrototypeOf(o, F.prototype); o instanceof F;"), vm::JsValue::TRUE)
+        }
+
+        #[test]
+        fn instanceof_requires_callable() {
+            assert_eq!(compile_repl_error("({}) instanceof {};").to_string(), "TypeError: Right-hand side of 'instanceof' is not callable")
+        }
+    }
+    mod logical_operators {
+        use super::compile_repl;
+        use super::vm;
+
+        fn string(s: &str) -> vm::JsValue {
+            vm::JsValue::string(s.to_owned())
+        }
+
+        #[test]
+        fn and_returns_operand() {
+            assert_eq!(compile_repl("1 && 'b';"), string("b"));
+            assert_eq!(compile_repl("0 && 'b';"), vm::JsValue::number(0 as f64));
+            assert_eq!(compile_repl("'' && 'b';"), string(""))
+        }
+
+        #[test]
+        fn or_returns_operand() {
+            assert_eq!(compile_repl("0 || 'b';"), string("b"));
+            assert_eq!(compile_repl("'a' || 'b';"), string("a"));
+            assert_eq!(compile_repl("null || undefined;"), vm::JsValue::UNDEFINED)
+        }
+
+        #[test]
+        fn and_guards_member_access() {
+            assert_eq!(compile_repl("var a; a && a.b;"), vm::JsValue::UNDEFINED);
+            assert_eq!(compile_repl("var a = {b: 2}; a && a.b;"), vm::JsValue::number(2 as f64))
+        }
+
+        #[test]
+        fn short_circuit_skips_right_operand() {
+            assert_eq!(compile_repl("var n = 0; function f() { n += 1; return true; } false && f(); true || f(); n;"), vm::JsValue::number(0 as f64));
+            assert_eq!(compile_repl("var n = 0; function f() { n += 1; return true; } true && f(); false || f(); n;"), vm::JsValue::number(2 as f64))
+        }
+
+        #[test]
+        fn precedence_and_chaining() {
+            assert_eq!(compile_repl("0 || 1 && 2;"), vm::JsValue::number(2 as f64));
+            assert_eq!(compile_repl("null || 0 || 'last';"), string("last"))
+        }
+
+        #[test]
+        fn default_value_idiom() {
+            assert_eq!(compile_repl("function f(x) { x = x || 'default'; return x; } f() + f('given');"), string("defaultgiven"))
+        }
+
+        #[test]
+        fn conditional() {
+            assert_eq!(compile_repl("1 ? 'yes' : 'no';"), string("yes"));
+            assert_eq!(compile_repl("'' ? 'yes' : 'no';"), string("no"))
+        }
+
+        #[test]
+        fn conditional_evaluates_one_branch() {
+            assert_eq!(compile_repl("var a = 0, b = 0; true ? a = 1 : b = 1; '' + a + b;"), string("10"))
+        }
+
+        #[test]
+        fn nested_conditional() {
+            assert_eq!(compile_repl("function sign(x) { return x < 0 ? -1 : x > 0 ? 1 : 0; } '' + sign(-5) + sign(0) + sign(3);"), string("-101"))
+        }
+
+        #[test]
+        fn logical_in_conditions() {
+            assert_eq!(compile_repl("var r = 'none'; var o = {a: 1}; if (o && o.a || false) { r = 'set'; } r;"), string("set"))
+        }
+
+        #[test]
+        fn throwing_operand_is_catchable() {
+            assert_eq!(compile_repl("var r; try { r = 1 && undefined.x; } catch (e) { r = 'caught'; } r;"), string("caught"))
+        }
+    }
+    mod update_operators {
+        use super::compile_repl;
+        use super::vm;
+
+        fn number(n: f64) -> vm::JsValue {
+            vm::JsValue::number(n)
+        }
+
+        #[test]
+        fn prefix_returns_new_value() {
+            assert_eq!(compile_repl("var i = 1; ++i;"), number(2.0));
+            assert_eq!(compile_repl("var i = 1; --i;"), number(0.0))
+        }
+
+        #[test]
+        fn postfix_returns_old_value() {
+            assert_eq!(compile_repl("var i = 1; i++;"), number(1.0));
+            assert_eq!(compile_repl("var i = 1; i--;"), number(1.0));
+            assert_eq!(compile_repl("var i = 1; i++; i;"), number(2.0))
+        }
+
+        #[test]
+        fn coerces_to_number() {
+            assert_eq!(compile_repl("var s = '5'; s++;"), number(5.0));
+            assert_eq!(compile_repl("var s = '5'; s++; s;"), number(6.0));
+            assert_eq!(compile_repl("var b = true; ++b;"), number(2.0));
+            assert_eq!(compile_repl("var n = null; n--;"), number(0.0))
+        }
+
+        #[test]
+        fn nan() {
+            assert_eq!(compile_repl("var u; u++;"), vm::JsValue::NAN);
+            assert_eq!(compile_repl("var s = 'x'; ++s;"), vm::JsValue::NAN)
+        }
+
+        #[test]
+        fn members() {
+            assert_eq!(compile_repl("var o = {a: 1}; o.a++; o.a;"), number(2.0));
+            assert_eq!(compile_repl("var o = {a: 1}; o.a++;"), number(1.0));
+            assert_eq!(compile_repl("var a = [5, 6]; --a[1]; a[1];"), number(5.0));
+            assert_eq!(compile_repl("var o = {}; o.missing++; o.missing;"), vm::JsValue::NAN)
+        }
+
+        #[test]
+        fn operands_see_earlier_updates() {
+            assert_eq!(compile_repl("var i = 0; i++ + i;"), number(1.0));
+            assert_eq!(compile_repl("var i = 0; i + ++i;"), number(1.0));
+            assert_eq!(compile_repl("var o = {a: 1}; o.a++ + o.a;"), number(3.0))
+        }
+
+        #[test]
+        fn member_key_evaluated_once() {
+            assert_eq!(compile_repl("var a = [0, 0], i = 0; a[i++]++; '' + a + i;"), vm::JsValue::string("1,01".to_owned()))
+        }
+
+        #[test]
+        fn member_with_accessor() {
+            assert_eq!(compile_repl("var v = 10; var o = {get x() { return v; }, set x(n) { v = n * 2; }}; o.x++; v;"), number(22.0))
+        }
+
+        #[test]
+        fn loop_counter() {
+            assert_eq!(compile_repl("var sum = 0; for (var i = 0; i < 5; i++) sum += i; sum;"), number(10.0));
+            assert_eq!(compile_repl("var n = 0, i = 3; while (i--) n++; n;"), number(3.0))
+        }
+
+        #[test]
+        fn closure_variable() {
+            assert_eq!(compile_repl("function counter() { var c = 0; return function () { return ++c; }; } var next = counter(); next(); next();"), number(2.0))
+        }
+
+        #[test]
+        fn update_of_null_throws() {
+            let error = super::compile_repl_error("var o = null; o.a++;");
+            assert_eq!(error.name(), Some("TypeError".to_owned()))
+        }
+    }
+    mod to_number {
+        use super::compile_repl;
+        use super::vm;
+
+        fn number(n: f64) -> vm::JsValue {
+            vm::JsValue::number(n)
+        }
+
+        #[test]
+        fn empty_and_whitespace_strings_are_zero() {
+            assert_eq!(compile_repl("10 - '';"), number(10.0));
+            assert_eq!(compile_repl("+' \\t\\n ';"), number(0.0))
+        }
+
+        #[test]
+        fn surrounding_whitespace_is_ignored() {
+            assert_eq!(compile_repl("+' 42 ';"), number(42.0));
+            assert_eq!(compile_repl("'\\n 1.5e3\\t' * 1;"), number(1500.0))
+        }
+
+        #[test]
+        fn decimal_literals() {
+            assert_eq!(compile_repl("+'.5';"), number(0.5));
+            assert_eq!(compile_repl("+'5.';"), number(5.0));
+            assert_eq!(compile_repl("+'-0012';"), number(-12.0));
+            assert_eq!(compile_repl("+'1E-2';"), number(0.01))
+        }
+
+        #[test]
+        fn radix_literals() {
+            assert_eq!(compile_repl("+'0x1F';"), number(31.0));
+            assert_eq!(compile_repl("+'0o17';"), number(15.0));
+            assert_eq!(compile_repl("+'0B101';"), number(5.0))
+        }
+
+        #[test]
+        fn signs_only_lead_the_number_or_exponent() {
+            assert_eq!(compile_repl("+'++1';"), vm::JsValue::NAN);
+            assert_eq!(compile_repl("+'+-1';"), vm::JsValue::NAN);
+            assert_eq!(compile_repl("+'1-';"), vm::JsValue::NAN);
+            assert_eq!(compile_repl("+'-1e-2';"), number(-0.01));
+            assert_eq!(compile_repl("+'1E+2';"), number(100.0))
+        }
+
+        #[test]
+        fn signed_radix_literals_are_nan() {
+            assert_eq!(compile_repl("+'-0x1F';"), vm::JsValue::NAN);
+            assert_eq!(compile_repl("+'0x';"), vm::JsValue::NAN);
+            assert_eq!(compile_repl("+'0b12';"), vm::JsValue::NAN)
+        }
+
+        #[test]
+        fn infinity() {
+            assert_eq!(compile_repl("+'Infinity';"), number(f64::INFINITY));
+            assert_eq!(compile_repl("+' -Infinity';"), number(f64::NEG_INFINITY))
+        }
+
+        #[test]
+        fn rust_only_spellings_are_nan() {
+            assert_eq!(compile_repl("+'inf';"), vm::JsValue::NAN);
+            assert_eq!(compile_repl("+'infinity';"), vm::JsValue::NAN);
+            assert_eq!(compile_repl("+'NaN';"), vm::JsValue::NAN);
+            assert_eq!(compile_repl("+'1_000';"), vm::JsValue::NAN);
+            assert_eq!(compile_repl("+'- 1';"), vm::JsValue::NAN)
+        }
+
+        #[test]
+        fn primitives() {
+            assert_eq!(compile_repl("true * 2;"), number(2.0));
+            assert_eq!(compile_repl("null - 1;"), number(-1.0));
+            assert_eq!(compile_repl("var u; u * 1;"), vm::JsValue::NAN)
+        }
+
+        #[test]
+        fn addition_without_strings_adds_numbers() {
+            assert_eq!(compile_repl("true + 1;"), number(2.0));
+            assert_eq!(compile_repl("null + null;"), number(0.0));
+            assert_eq!(compile_repl("var u; u + 1;"), vm::JsValue::NAN);
+            assert_eq!(compile_repl("'0x10' - 0 + 1;"), number(17.0))
+        }
+
+        #[test]
+        fn addition_with_objects_concatenates() {
+            assert_eq!(compile_repl("[1, 2] + 1;"), vm::JsValue::string("1,21".to_owned()))
+        }
+
+        #[test]
+        fn equality_with_numeric_strings() {
+            assert_eq!(compile_repl("' 1 ' == 1;"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("'0x10' == 16;"), vm::JsValue::TRUE);
+            assert_eq!(compile_repl("'' == 0;"), vm::JsValue::TRUE)
+        }
+    }
+    mod number_formatting {
+        use super::compile_repl;
+        use super::compile_repl_error;
+        use super::vm;
+
+        fn string(s: &str) -> vm::JsValue {
+            vm::JsValue::string(s.to_owned())
+        }
+
+        #[test]
+        fn shortest_round_trip() {
+            assert_eq!(compile_repl("'' + 0.1;"), string("0.1"));
+            assert_eq!(compile_repl("'' + (0.1 + 0.2);"), string("0.30000000000000004"));
+            assert_eq!(compile_repl("'' + 123.456;"), string("123.456"))
+        }
+
+        #[test]
+        fn exponent_thresholds() {
+            assert_eq!(compile_repl("'' + 1e21;"), string("1e+21"));
+            assert_eq!(compile_repl("'' + 1e20;"), string("100000000000000000000"));
+            assert_eq!(compile_repl("'' + 0.000001;"), string("0.000001"));
+            assert_eq!(compile_repl("'' + 1e-7;"), string("1e-7"));
+            assert_eq!(compile_repl("'' + 1.5e-10;"), string("1.5e-10"))
+        }
+
+        #[test]
+        fn special_values() {
+            assert_eq!(compile_repl("'' + -0;"), string("0"));
+            assert_eq!(compile_repl("'' + 1 / 0;"), string("Infinity"));
+            assert_eq!(compile_repl("'' + -1 / 0;"), string("-Infinity"));
+            assert_eq!(compile_repl("'' + -2.5;"), string("-2.5"))
+        }
+
+        #[test]
+        fn to_string_radix() {
+            assert_eq!(compile_repl("(255).toString(16);"), string("ff"));
+            assert_eq!(compile_repl("(255).toString(2);"), string("11111111"));
+            assert_eq!(compile_repl("(-255).toString(36);"), string("-73"));
+            assert_eq!(compile_repl("(0.5).toString(2);"), string("0.1"));
+            assert_eq!(compile_repl("(0.1).toString(2);"), string("0.0001100110011001100110011001100110011001100110011001101"));
+            assert_eq!(compile_repl("(1e21).toString(10);"), string("1e+21"))
+        }
+
+        #[test]
+        fn to_string_radix_out_of_range() {
+            let error = compile_repl_error("(1).toString(37);");
+            assert_eq!(error.name(), Some("RangeError".to_owned()));
+            assert_eq!(error.to_string(), "RangeError: toString() radix must be between 2 and 36")
+        }
+
+        #[test]
+        fn to_fixed() {
+            assert_eq!(compile_repl("(1.005).toFixed(2);"), string("1.00"));
+            assert_eq!(compile_repl("(1.25).toFixed(1);"), string("1.3"));
+            assert_eq!(compile_repl("(0.5).toFixed(0);"), string("1"));
+            assert_eq!(compile_repl("(-1.5).toFixed(0);"), string("-2"));
+            assert_eq!(compile_repl("(0.000001).toFixed(3);"), string("0.000"));
+            assert_eq!(compile_repl("(123.456).toFixed();"), string("123"));
+            assert_eq!(compile_repl("(99.99).toFixed(1);"), string("100.0"));
+            assert_eq!(compile_repl("(1e21).toFixed(2);"), string("1e+21"))
+        }
+
+        #[test]
+        fn to_fixed_out_of_range() {
+            assert_eq!(compile_repl_error("(1).toFixed(101);").name(), Some("RangeError".to_owned()))
+        }
+
+        #[test]
+        fn to_precision() {
+            assert_eq!(compile_repl("(123.456).toPrecision(4);"), string("123.5"));
+            assert_eq!(compile_repl("(0.00001).toPrecision(1);"), string("0.00001"));
+            assert_eq!(compile_repl("(0.0000001).toPrecision(2);"), string("1.0e-7"));
+            assert_eq!(compile_repl("(123456).toPrecision(2);"), string("1.2e+5"));
+            assert_eq!(compile_repl("(0).toPrecision(3);"), string("0.00"));
+            assert_eq!(compile_repl("(99.99).toPrecision(2);"), string("1.0e+2"));
+            assert_eq!(compile_repl("(1.5).toPrecision();"), string("1.5"))
+        }
+
+        #[test]
+        fn to_exponential() {
+            assert_eq!(compile_repl("(123456).toExponential(2);"), string("1.23e+5"));
+            assert_eq!(compile_repl("(123456).toExponential();"), string("1.23456e+5"));
+            assert_eq!(compile_repl("(0).toExponential();"), string("0e+0"));
+            assert_eq!(compile_repl("(0.00015).toExponential(1);"), string("1.5e-4"));
+            assert_eq!(compile_repl("(-9.99).toExponential(1);"), string("-1.0e+1"))
+        }
+
+        #[test]
+        fn non_finite_receivers() {
+            assert_eq!(compile_repl("(1 / 0).toFixed(2);"), string("Infinity"));
+            assert_eq!(compile_repl("(0 / 0).toPrecision(3);"), string("NaN"));
+            assert_eq!(compile_repl("(-1 / 0).toExponential(200);"), string("-Infinity"))
+        }
+
+        #[test]
+        fn methods_require_numbers() {
+            let error = compile_repl_error("var o = {f: Number.prototype.toFixed}; o.f(1);");
+            assert_eq!(error.to_string(), "TypeError: Number.prototype.toFixed requires that 'this' be a Number")
+        }
+
+        #[test]
+        fn number_conversion() {
+            assert_eq!(compile_repl("Number(' 0x10 ');"), vm::JsValue::number(16 as f64));
+            assert_eq!(compile_repl("Number();"), vm::JsValue::number(0 as f64));
+            assert_eq!(compile_repl("var n = 42; n.toString == Number.prototype.toString;"), vm::JsValue::TRUE)
+        }
+
+        #[test]
+        fn array_join_uses_number_formatting() {
+            assert_eq!(compile_repl("[1e21, -0, 0.5].join(' ');"), string("1e+21 0 0.5"))
+        }
+    }
+    mod equality {
+        use super::compile_repl;
+        use super::compile_repl_error;
+        use super::vm;
+
+        fn is_true(code: &str) {
+            assert_eq!(compile_repl(code), vm::JsValue::TRUE, "{}", code)
+        }
+
+        fn is_false(code: &str) {
+            assert_eq!(compile_repl(code), vm::JsValue::FALSE, "{}", code)
+        }
+
+        #[test]
+        fn null_and_undefined() {
+            is_true("null == undefined;");
+            is_true("var u; u == null;");
+            is_false("null == 0;");
+            is_false("undefined == '';");
+            is_false("null === undefined;")
+        }
+
+        #[test]
+        fn nan_equals_nothing() {
+            is_false("var n = 0 / 0; n == n;");
+            is_false("var n = 0 / 0; n === n;");
+            is_true("var n = 0 / 0; n != n;");
+            is_false("'NaN' == 0 / 0;")
+        }
+
+        #[test]
+        fn signed_zero() {
+            is_true("0 === -0;");
+            is_true("0 == -0;")
+        }
+
+        #[test]
+        fn booleans_convert_to_numbers() {
+            is_false("2 == true;");
+            is_true("1 == true;");
+            is_false("'abc' == true;");
+            is_true("'1' == true;");
+            is_false("null == false;");
+            is_true("'\\n' == false;")
+        }
+
+        #[test]
+        fn objects_by_identity() {
+            is_false("({}) == ({});");
+            is_false("[] === [];");
+            is_true("var o = {}; o === o;");
+            is_true("var o = {}; var p = o; o == p;")
+        }
+
+        #[test]
+        fn objects_convert_to_primitives() {
+            is_true("[1] == 1;");
+            is_true("[1, 2] == '1,2';");
+            is_true("({}) == '[object Object]';");
+            is_false("({}) == null;");
+            is_true("[] == false;");
+            is_true("({valueOf: function () { return 7; }}) == 7;");
+            is_true("({toString: function () { return 'x'; }}) == 'x';")
+        }
+
+        #[test]
+        fn strict_equality_does_not_convert() {
+            is_false("1 === '1';");
+            is_false("true === 1;");
+            is_false("[1] === 1;");
+            is_true("'a' === 'a';")
+        }
+
+        #[test]
+        fn to_primitive_in_arithmetic_and_comparison() {
+            assert_eq!(compile_repl("({valueOf: function () { return 2; }}) + 1;"), vm::JsValue::number(3 as f64));
+            assert_eq!(compile_repl("'' + {toString: function () { return 'str'; }};"), vm::JsValue::string("str".to_owned()));
+            is_true("({valueOf: function () { return 1; }}) < 2;")
+        }
+
+        #[test]
+        fn to_primitive_in_numeric_operators() {
+            let five = "var five = {valueOf: function () { return 5; }, toString: function () { return 'x'; }}; ";
+            let number = |code: &str| compile_repl(&format!("{}{}", five, code));
+
+            assert_eq!(number("five * 2;"), vm::JsValue::number(10 as f64));
+            assert_eq!(number("five - 1;"), vm::JsValue::number(4 as f64));
+            assert_eq!(number("five / 2;"), vm::JsValue::number(2.5));
+            assert_eq!(number("five % 3;"), vm::JsValue::number(2 as f64));
+            assert_eq!(number("var a = 7; a -= five; a *= five; a /= five; a;"), vm::JsValue::number(2 as f64))
+        }
+
+        #[test]
+        fn to_primitive_in_bitwise_operators() {
+            let five = "var five = {valueOf: function () { return 5; }}; ";
+            let number = |code: &str| compile_repl(&format!("{}{}", five, code));
+
+            assert_eq!(number("five << 1;"), vm::JsValue::number(10 as f64));
+            assert_eq!(number("five >> 1;"), vm::JsValue::number(2 as f64));
+            assert_eq!(number("five >>> 1;"), vm::JsValue::number(2 as f64));
+            assert_eq!(number("(five & 4) + (five | 2) + (five ^ 1);"), vm::JsValue::number(15 as f64));
+            assert_eq!(number("~five;"), vm::JsValue::number(-6 as f64))
+        }
+
+        #[test]
+        fn to_primitive_in_unary_and_update_operators() {
+            let five = "var five = {valueOf: function () { return 5; }}; ";
+            let number = |code: &str| compile_repl(&format!("{}{}", five, code));
+
+            assert_eq!(number("-five;"), vm::JsValue::number(-5 as f64));
+            assert_eq!(number("+five;"), vm::JsValue::number(5 as f64));
+            assert_eq!(number("var o = five; o++;"), vm::JsValue::number(5 as f64));
+            assert_eq!(number("var o = five; ++o;"), vm::JsValue::number(6 as f64));
+            assert_eq!(number("var o = {p: five}; o.p--; o.p;"), vm::JsValue::number(4 as f64))
+        }
+
+        #[test]
+        fn to_primitive_without_methods_throws() {
+            let error = compile_repl_error("Object.create(null) == 'x';");
+            assert_eq!(error.to_string(), "TypeError: Cannot convert object to primitive value")
+        }
+
+        #[test]
+        fn to_primitive_errors_propagate() {
+            assert_eq!(compile_repl("var r; try { ({valueOf: function () { throw 'no'; }}) == 1; } catch (e) { r = e; } r;"), vm::JsValue::string("no".to_owned()))
+        }
+
+        #[test]
+        fn object_is() {
+            is_true("Object.is(0 / 0, 0 / 0);");
+            is_false("Object.is(0, -0);");
+            is_true("Object.is(-0, -0);");
+            is_false("Object.is(1, '1');");
+            is_true("var o = {}; Object.is(o, o);");
+            is_true("Object.is();")
+        }
+
+        #[test]
+        fn prototype_to_string() {
+            assert_eq!(compile_repl("var o = {f: Object.prototype.toString}; o.f();"), vm::JsValue::string("[object Object]".to_owned()));
+            assert_eq!(compile_repl("var a = [1]; a.f = Object.prototype.toString; a.f();"), vm::JsValue::string("[object Array]".to_owned()));
+            assert_eq!(compile_repl("[1, [2, 3]].toString();"), vm::JsValue::string("1,2,3".to_owned()));
+            assert_eq!(compile_repl("({}).toString();"), vm::JsValue::string("[object Object]".to_owned()))
+        }
+    }
+
+    mod values {
+        use super::compile_repl;
+        use super::vm;
+        use std::mem;
+
+        #[test]
+        fn values_are_eight_bytes() {
+            assert_eq!(mem::size_of::<vm::JsValue>(), 8)
+        }
+
+        #[test]
+        fn there_is_one_nan() {
+            assert_eq!(vm::JsValue::number(f64::NAN), vm::JsValue::NAN);
+            assert_eq!(vm::JsValue::number(-f64::NAN), vm::JsValue::NAN);
+            assert_eq!(compile_repl("0 / 0;"), vm::JsValue::NAN);
+            assert_eq!(vm::JsValue::NAN.as_number().map(f64::is_nan), Some(true))
+        }
+
+        #[test]
+        fn views() {
+            assert!(matches!(vm::JsValue::NULL.view(), vm::Value::Null));
+            assert!(matches!(vm::JsValue::UNDEFINED.view(), vm::Value::Undefined));
+            assert!(matches!(vm::JsValue::TRUE.view(), vm::Value::Boolean(true)));
+            assert!(matches!(vm::JsValue::boolean(false).view(), vm::Value::Boolean(false)));
+            assert!(matches!(vm::JsValue::number(-0.5).view(), vm::Value::Number(n) if n == -0.5));
+            assert!(matches!(vm::JsValue::number(f64::NEG_INFINITY).view(), vm::Value::Number(n) if n == f64::NEG_INFINITY));
+            assert!(matches!(vm::JsValue::string("abc").view(), vm::Value::String("abc")));
+            assert!(compile_repl("({});").is_object())
+        }
+
+        #[test]
+        fn clones_share_strings_and_objects() {
+            let string = vm::JsValue::string("shared");
+            let copy = string.clone();
+            drop(string);
+            assert_eq!(copy, vm::JsValue::string("shared"));
+
+            let object = compile_repl("({a: 1});");
+            let copy = object.clone();
+            drop(object);
+            assert_eq!(copy.as_object().unwrap().get("a"), vm::JsValue::number(1 as f64))
+        }
+    }
+
+    mod garbage_collection {
+        use bytecode::*;
+        use esprit;
+        use vm;
+        use vm::scope::Scope;
+
+        fn compile(code: &str) -> Image {
+            match esprit::script(code) {
+                Err(why) => panic!("Could not compile {:?}", why),
+                Ok(ast) => return compile_to_image(ast.body).unwrap_or_else(|why| panic!("{}", why)),
+            }
+        }
+
+        /// Runs `setup` and then `code` in the same global scope, giving
+        /// how many objects a collection after `code` frees and how many
+        /// objects it leaves compared to after `setup`.
+        fn collect_after(setup: &str, code: &str) -> (usize, isize) {
+            let mut scope = Scope::new_global();
+
+            let mut engine = vm::VM::new(compile(setup), &mut scope);
+            engine.run().unwrap_or_else(|why| panic!("Uncaught {}", why));
+            engine.collect_garbage();
+            let before = engine.live_objects() as isize;
+
+            let mut engine = vm::VM::new(compile(code), &mut scope);
+            engine.run().unwrap_or_else(|why| panic!("Uncaught {}", why));
+            let freed = engine.collect_garbage();
+            (freed, engine.live_objects() as isize - before)
+        }
+
+        #[test]
+        fn frees_cycles() {
+            assert_eq!(collect_after("", "var a = {}; var b = {a: a}; a.b = b; a = b = undefined;"), (2, 0));
+            assert_eq!(collect_after("", "var a = []; a.push(a); a = null;"), (1, 0))
+        }
+
+        #[test]
+        fn frees_closures_over_their_own_scope() {
+            // The function, its prototype and the scope holding it and
+            // `arguments` refer to each other, leaving `f` and its prototype
+            assert_eq!(collect_after("", "function f() { var g = function () { return g; }; } f(); f();"), (6, 2))
+        }
+
+        #[test]
+        fn keeps_what_globals_reach() {
+            assert_eq!(collect_after("", "var keep = {inner: {}}; keep.inner.outer = keep;"), (0, 2));
+
+            let mut scope = Scope::new_global();
+            vm::VM::new(compile("var keep = {inner: {}}; keep.inner.outer = keep;"), &mut scope).run().unwrap();
+            vm::VM::new(compile("var unused = {}; unused = 0;"), &mut scope).collect_garbage();
+            let result = vm::VM::new(compile("keep.inner.outer === keep;"), &mut scope).run().unwrap();
+            assert_eq!(result, vm::JsValue::TRUE)
+        }
+
+        #[test]
+        fn keeps_what_closures_reach() {
+            let setup = "function counter() { var state = {count: 0}; state.self = state; return function () { return ++state.count; }; } var next = counter();";
+            assert_eq!(collect_after(setup, "next(); next();"), (0, 0));
+
+            let mut scope = Scope::new_global();
+            vm::VM::new(compile(setup), &mut scope).run().unwrap();
+            vm::VM::new(compile("1;"), &mut scope).collect_garbage();
+            let result = vm::VM::new(compile("next(); next();"), &mut scope).run().unwrap();
+            assert_eq!(result, vm::JsValue::number(2 as f64))
+        }
+
+        #[test]
+        fn keeps_values_the_host_holds() {
+            let mut scope = Scope::new_global();
+            let mut engine = vm::VM::new(compile("(function () { var o = {}; o.self = o; return o; })();"), &mut scope);
+            let result = engine.run().unwrap();
+
+            // Only the function and its prototype are gone
+            assert_eq!(engine.collect_garbage(), 2);
+            let object = result.as_object().unwrap();
+            assert_eq!(object.get("self"), result)
+        }
+
+        #[test]
+        fn dropping_the_scope_frees_its_objects() {
+            let mut scope = Scope::new_global();
+            let mut engine = vm::VM::new(compile("var o = {}; o.self = o; function f() {} [o, f];"), &mut scope);
+            let result = engine.run().unwrap();
+            let array = result.as_object().unwrap();
+            let objects = [
+                array.get("0").as_object().unwrap().downgrade(),
+                array.get("1").as_object().unwrap().downgrade(),
+                engine.realm.array_prototype.downgrade(),
+                engine.realm.error_prototype(vm::error::ErrorKind::TypeError).downgrade(),
+            ];
+            drop(array);
+            drop(result);
+            drop(engine);
+            assert!(objects.iter().all(|object| object.is_alive()));
+
+            drop(scope);
+            assert!(objects.iter().all(|object| !object.is_alive()))
+        }
+
+        #[test]
+        fn dropping_the_scope_keeps_values_the_host_holds() {
+            let result;
+            {
+                let mut scope = Scope::new_global();
+                result = vm::VM::new(compile("var o = {list: [1, 2]}; o.self = o; o;"), &mut scope).run().unwrap();
+            }
+
+            let object = result.as_object().unwrap();
+            assert_eq!(object.get("self"), result);
+            assert_eq!(object.get("list").as_object().unwrap().get("1"), vm::JsValue::number(2 as f64))
+        }
+
+        #[test]
+        fn collects_while_running() {
+            let mut scope = Scope::new_global();
+            let mut engine = vm::VM::new(compile("function f() { var last; for (var i = 0; i < 20000; i++) { var o = {previous: last}; o.self = o; last = {}; } return 'done'; } f();"), &mut scope);
+
+            assert_eq!(engine.run().unwrap(), vm::JsValue::string("done"));
+            assert!(engine.live_objects() < 10000, "{} objects are alive", engine.live_objects())
+        }
+
+        #[test]
+        fn minor_collections_free_young_cycles() {
+            let mut scope = Scope::new_global();
+            let mut engine = vm::VM::new(compile("for (var i = 0; i < 5000; i++) { var o = {}; o.self = o; }"), &mut scope);
+            engine.run().unwrap();
+
+            assert!(engine.gc_stats().minor_collections >= 4, "{:?}", engine.gc_stats());
+            assert_eq!(engine.gc_stats().major_collections, 0);
+            assert!(engine.live_objects() < 1500, "{} objects are alive", engine.live_objects())
+        }
+
+        #[test]
+        fn minor_collections_copy_survivors() {
+            let mut scope = Scope::new_global();
+            let mut engine = vm::VM::new(compile("
+                var keep = {items: []};
+                for (var i = 0; i < 3000; i++) {
+                    var o = {value: i, inner: {}};
+                    o.self = o;
+                    o.inner.outer = o;
+                    keep.items.push(o);
+                }
+                var total = 0;
+                for (var j = 0; j < keep.items.length; j++) {
+                    var item = keep.items[j];
+                    if (item.self === item && item.inner.outer === item) total += item.value;
+                }
+                total;
+            "), &mut scope);
+
+            assert_eq!(engine.run().unwrap(), vm::JsValue::number(4498500 as f64));
+            let stats = engine.gc_stats();
+            assert!(stats.minor_collections >= 4, "{:?}", stats);
+            assert!(stats.copied_objects > 5000, "{:?}", stats)
+        }
+
+        #[test]
+        fn incremental_marking() {
+            let mut scope = Scope::new_global();
+            let mut engine = vm::VM::new(compile("
+                var kept = [];
+                for (var i = 0; i < 12000; i++) {
+                    var o = {value: i};
+                    o.self = o;
+                    if (i % 2 == 0) kept.push(o);
+                }
+                var total = 0;
+                for (var j = 0; j < kept.length; j++) total += kept[j].self.value;
+                total;
+            "), &mut scope);
+            engine.set_gc_budget(Some(16));
+
+            assert_eq!(engine.run().unwrap(), vm::JsValue::number(35994000 as f64));
+            let stats = engine.gc_stats();
+            assert!(stats.major_collections >= 1, "{:?}", stats);
+            assert!(stats.marking_slices > stats.major_collections * 10, "{:?}", stats)
+        }
+
+        #[test]
+        fn marking_sees_objects_moved_behind_it() {
+            // Nodes keep moving from the end of one list to the front of
+            // another while the lists are being marked
+            let mut scope = Scope::new_global();
+            let mut engine = vm::VM::new(compile("
+                var a = null, b = null;
+                for (var i = 0; i < 6000; i++) { a = {next: a, value: 1}; }
+                for (var round = 0; round < 20000; round++) {
+                    var node = a;
+                    if (node) { a = node.next; node.next = b; b = node; }
+                    else { var t = a; a = b; b = t; }
+                    var garbage = {}; garbage.self = garbage;
+                }
+                var count = 0;
+                for (var n = a; n; n = n.next) count += n.value;
+                for (var m = b; m; m = m.next) count += m.value;
+                // Long lists are freed one node at a time
+                while (a) a = a.next;
+                while (b) b = b.next;
+                count;
+            "), &mut scope);
+            engine.set_gc_budget(Some(4));
+
+            assert_eq!(engine.run().unwrap(), vm::JsValue::number(6000 as f64));
+            assert!(engine.gc_stats().major_collections >= 1, "{:?}", engine.gc_stats())
+        }
+
+        #[test]
+        fn stress_mode_collects_after_every_allocation() {
+            let mut scope = Scope::new_global();
+            let mut engine = vm::VM::new(compile("for (var i = 0; i < 50; i++) { var o = {}; o.self = o; } o = null; [];"), &mut scope);
+            engine.set_gc_stress(true);
+            engine.run().unwrap();
+
+            assert!(engine.gc_stats().major_collections >= 50, "{:?}", engine.gc_stats());
+            assert!(engine.live_objects() < 60, "{} objects are alive", engine.live_objects())
+        }
+
+        #[test]
+        fn scripts_run_the_same_under_stress() {
+            let scripts = [
+                "function counter() { var n = 0; return function () { return ++n; }; } var c = counter(); c(); c(); c();",
+                "function Point(x) { this.x = x; } Point.prototype.double = function () { return this.x * 2; }; new Point(21).double();",
+                "var fs = []; for (let i = 0; i < 5; i++) { fs.push(function () { return i; }); } fs[0]() + fs[4]();",
+                "[3, 1, 2].map(function (x) { return {x: x}; }).sort(function (a, b) { return a.x - b.x; })[0].x;",
+                "var r; try { null.x; } catch (e) { r = e.name; } r;",
+                "var o = {get v() { return [this]; }}; o.v[0] === o;",
+            ];
+
+            for script in scripts.iter() {
+                let mut scope = Scope::new_global();
+                let mut engine = vm::VM::new(compile(script), &mut scope);
+                engine.set_gc_stress(true);
+                assert_eq!(engine.run().unwrap(), super::compile_repl(script), "{}", script);
+            }
+        }
+
+        #[test]
+        fn running_code_survives_collections() {
+            let mut scope = Scope::new_global();
+            let mut engine = vm::VM::new(compile("var list = null; for (var i = 0; i < 10000; i++) { list = {next: list, value: i}; list.self = list; } var total = 0; while (list) { total += list.value; list = list.next; } total;"), &mut scope);
+
+            assert_eq!(engine.run().unwrap(), vm::JsValue::number(49995000 as f64))
+        }
+    }
+
+    mod limits {
+        use bytecode::*;
+        use esprit;
+        use vm;
+        use vm::error::JsError;
+        use vm::error::Termination;
+        use vm::heap::Limits;
+        use vm::scope::Scope;
+
+        fn run(code: &str, limits: Limits, scope: &mut Scope) -> Result<vm::JsValue, JsError> {
+            let image = match esprit::script(code) {
+                Err(why) => panic!("Could not compile {:?}", why),
+                Ok(ast) => compile_to_image(ast.body).unwrap_or_else(|why| panic!("{}", why)),
+            };
+
+            let mut engine = vm::VM::new(image, scope);
+            engine.set_limits(limits);
+            engine.run()
+        }
+
+        fn run_limited(code: &str, limits: Limits) -> Result<vm::JsValue, JsError> {
+            run(code, limits, &mut Scope::new_global())
+        }
+
+        fn heap(bytes: usize) -> Limits {
+            Limits { heap_bytes: Some(bytes), ..Limits::default() }
+        }
+
+        fn string(value: &str) -> vm::JsValue {
+            vm::JsValue::string(value.to_owned())
+        }
+
+        #[test]
+        fn running_out_of_memory_throws() {
+            let code = "var keep = []; var r; try { for (;;) keep.push({}); } catch (e) { keep = null; r = e.name + ': ' + e.message; } r;";
+            assert_eq!(run_limited(code, heap(1 << 20)), Ok(string("RangeError: Out of memory")))
+        }
+
+        #[test]
+        fn running_out_again_while_recovering_terminates() {
+            let mut scope = Scope::new_global();
+            let code = "var log = ''; var keep = []; try { for (;;) keep.push({}); } catch (e) { log += 'c'; for (;;) keep.push({}); } finally { log += 'f'; }";
+            let error = run(code, heap(1 << 20), &mut scope).expect_err("Expected a termination");
+
+            assert_eq!(error.termination, Some(Termination::OutOfMemory));
+            assert_eq!(error.to_string(), "Terminated: Out of memory while handling running out of memory");
+            // Neither `catch` nor `finally` see the termination
+            assert_eq!(run("log;", Limits::default(), &mut scope), Ok(string("c")))
+        }
+
+        #[test]
+        fn scripts_recover_after_freeing_memory() {
+            let code = "var keep; var caught = 0; for (var round = 0; round < 3; round++) { try { keep = []; for (;;) keep.push({}); } catch (e) { keep = null; caught++; } } caught;";
+            assert_eq!(run_limited(code, heap(1 << 20)), Ok(vm::JsValue::number(3 as f64)))
+        }
+
+        #[test]
+        fn strings_are_limited() {
+            let limits = Limits { string_length: Some(1000), ..Limits::default() };
+
+            assert_eq!(run_limited("var s = 'x'; var r; try { for (;;) s = s + s; } catch (e) { r = e.message + ' ' + s.length; } r;", limits), Ok(string("Invalid string length 512")));
+            assert_eq!(run_limited("var a = []; a.length = 2000; a.join('-');", limits).unwrap_err().to_string(), "RangeError: Invalid string length");
+            assert_eq!(run_limited("var inner = []; inner.length = 800; [inner, inner].toString();", limits).unwrap_err().to_string(), "RangeError: Invalid string length");
+            assert_eq!(run_limited("var a = []; a.length = 1000; a.join('').length + a.join().length;", limits), Ok(vm::JsValue::number(999 as f64)))
+        }
+
+        #[test]
+        fn arrays_are_limited() {
+            let limits = Limits { array_length: Some(100), ..Limits::default() };
+            let error = |code: &str| run_limited(code, limits).unwrap_err().to_string();
+
+            assert_eq!(error("var a = []; for (;;) a.push(1);"), "RangeError: Invalid array length");
+            assert_eq!(error("var a = []; a.length = 101;"), "RangeError: Invalid array length");
+            assert_eq!(error("var a = []; a[100] = 1;"), "RangeError: Invalid array length");
+            assert_eq!(error("new Array(101);"), "RangeError: Invalid array length");
+            assert_eq!(error("var a = []; a.length = 100; a.splice(0, 0, 1);"), "RangeError: Invalid array length");
+            assert_eq!(run_limited("var a = []; a[99] = 1; a.length;", limits), Ok(vm::JsValue::number(100 as f64)));
+            assert_eq!(run_limited("var o = {}; o[1000] = 1; o[1000];", limits), Ok(vm::JsValue::number(1 as f64)))
+        }
+    }
+
+    mod termination {
+        use bytecode::*;
+        use esprit;
+        use vm;
+        use vm::error::Termination;
+        use vm::scope::Scope;
+        use std::thread;
+        use std::time::Duration;
+
+        fn compile(code: &str) -> Image {
+            match esprit::script(code) {
+                Err(why) => panic!("Could not compile {:?}", why),
+                Ok(ast) => return compile_to_image(ast.body).unwrap_or_else(|why| panic!("{}", why)),
+            }
+        }
+
+        fn run_with_fuel(code: &str, fuel: u64) -> Option<Termination> {
+            let mut scope = Scope::new_global();
+            let mut engine = vm::VM::new(compile(code), &mut scope);
+            engine.set_fuel(Some(fuel));
+            engine.run().err().and_then(|why| why.termination)
+        }
+
+        #[test]
+        fn infinite_loops_run_out_of_fuel() {
+            assert_eq!(run_with_fuel("for (;;) {}", 10000), Some(Termination::OutOfFuel));
+            assert_eq!(run_with_fuel("var i = 0; do { i++; } while (true);", 10000), Some(Termination::OutOfFuel));
+            assert_eq!(run_with_fuel("function f() { return f(); } f();", 10000), Some(Termination::OutOfFuel));
+            assert_eq!(run_with_fuel("[1].map(function f() { for (;;) {} });", 10000), Some(Termination::OutOfFuel))
+        }
+
+        #[test]
+        fn scripts_within_their_budget_finish() {
+            let mut scope = Scope::new_global();
+            let mut engine = vm::VM::new(compile("var total = 0; for (var i = 0; i < 10; i++) total += i; total;"), &mut scope);
+            engine.set_fuel(Some(10000));
+
+            assert_eq!(engine.run(), Ok(vm::JsValue::number(45 as f64)));
+            let left = engine.fuel().unwrap();
+            assert!(left > 0 && left < 10000, "{} instructions left", left)
+        }
+
+        #[test]
+        fn terminations_skip_catch_and_finally() {
+            let mut scope = Scope::new_global();
+            let mut engine = vm::VM::new(compile("var log = ''; try { for (;;) {} } catch (e) { log += 'c'; } finally { log += 'f'; }"), &mut scope);
+            engine.set_fuel(Some(10000));
+            let error = engine.run().expect_err("Expected a termination");
+            assert_eq!(error.to_string(), "Terminated: Instruction budget exhausted");
+
+            let mut engine = vm::VM::new(compile("log;"), &mut scope);
+            assert_eq!(engine.run(), Ok(vm::JsValue::string("".to_owned())))
+        }
+
+        #[test]
+        fn other_threads_can_interrupt_scripts() {
+            let mut scope = Scope::new_global();
+            let mut engine = vm::VM::new(compile("var n = 0; while (true) { n++; }"), &mut scope);
+            let handle = engine.interrupt_handle();
+            let watchdog = thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                handle.interrupt();
+            });
+
+            assert_eq!(engine.run().err().and_then(|why| why.termination), Some(Termination::Interrupted));
+            watchdog.join().unwrap();
+
+            // The interrupt is used up, so the VM runs scripts again
+            engine.image = compile("n > 0;");
+            assert_eq!(engine.run(), Ok(vm::JsValue::TRUE))
+        }
+
+        #[test]
+        fn built_in_loops_run_out_of_fuel() {
+            let mut scope = Scope::new_global();
+            let mut engine = vm::VM::new(compile("var a = []; for (var i = 0; i < 100000; i++) a[i] = i;"), &mut scope);
+            engine.run().unwrap();
+
+            let loops = ["a.join();", "a.forEach(Array.isArray);", "a.reduce(Array.isArray);", "a.slice();", "a.indexOf(-1);", "a.sort();"];
+            for code in loops.iter() {
+                engine.image = compile(code);
+                engine.set_fuel(Some(1000));
+                assert_eq!(engine.run().err().and_then(|why| why.termination), Some(Termination::OutOfFuel), "{}", code);
+            }
+        }
+
+        #[test]
+        fn built_in_loops_can_be_interrupted() {
+            let mut scope = Scope::new_global();
+            let mut engine = vm::VM::new(compile("var a = []; for (var i = 0; i < 100000; i++) a[i] = i;"), &mut scope);
+            engine.run().unwrap();
+
+            engine.interrupt_handle().interrupt();
+            engine.image = compile("a.indexOf(-1);");
+            assert_eq!(engine.run().err().and_then(|why| why.termination), Some(Termination::Interrupted))
+        }
+    }
+
+    mod suspension {
+        use bytecode::*;
+        use esprit;
+        use vm;
+        use vm::Status;
+        use vm::scope::Scope;
+
+        fn compile(code: &str) -> Image {
+            match esprit::script(code) {
+                Err(why) => panic!("Could not compile {:?}", why),
+                Ok(ast) => return compile_to_image(ast.body).unwrap_or_else(|why| panic!("{}", why)),
+            }
+        }
+
+        /// Runs `code` in slices of `slice` instructions, giving its value
+        /// and how often it was suspended.
+        fn run_in_slices(code: &str, slice: u64) -> (vm::JsValue, usize) {
+            let mut scope = Scope::new_global();
+            let mut engine = vm::VM::new(compile(code), &mut scope);
+
+            let mut status = engine.run_for(slice).unwrap_or_else(|why| panic!("Uncaught {}", why));
+            let mut suspensions = 0;
+            while status == Status::Suspended {
+                suspensions += 1;
+                status = engine.resume(Some(slice)).unwrap_or_else(|why| panic!("Uncaught {}", why));
+            }
+
+            match status {
+                Status::Finished(value) => (value, suspensions),
+                Status::Suspended => unreachable!(),
+            }
+        }
+
+        #[test]
+        fn suspended_scripts_resume_where_they_left_off() {
+            let scripts = [
+                "var total = 0; for (var i = 0; i < 10; i++) total += i; total;",
+                "function fib(n) { return n < 2 ? n : fib(n - 1) + fib(n - 2); } fib(10);",
+                "function counter() { var n = 0; return function () { return ++n; }; } var c = counter(); c(); c(); c();",
+                "var r = ''; for (let i = 0; i < 3; i++) { try { throw i; } catch (e) { r += e; } finally { r += ';'; } } r;",
+                "[1, 2, 3].map(function (x) { return x * 2; }).join();",
+            ];
+
+            for script in scripts.iter() {
+                let (value, suspensions) = run_in_slices(script, 3);
+                assert_eq!(value, super::compile_repl(script), "{}", script);
+                assert!(suspensions > 0, "{} was never suspended", script);
+            }
+        }
+
+        #[test]
+        fn scripts_finish_within_their_slice() {
+            assert_eq!(run_in_slices("1 + 2;", 100), (vm::JsValue::number(3 as f64), 0))
+        }
+
+        #[test]
+        fn resuming_needs_a_suspended_script() {
+            let mut scope = Scope::new_global();
+            let mut engine = vm::VM::new(compile("1; 2;"), &mut scope);
+            assert_eq!(engine.resume(None).unwrap_err().to_string(), "InternalError: No suspended script to resume");
+
+            assert_eq!(engine.run_for(1), Ok(Status::Suspended));
+            assert_eq!(engine.resume(None), Ok(Status::Finished(vm::JsValue::number(2 as f64))));
+            assert!(engine.resume(None).is_err())
+        }
+
+        #[test]
+        fn running_again_abandons_a_suspended_script() {
+            let mut scope = Scope::new_global();
+            let mut engine = vm::VM::new(compile("function f() { for (;;) {} } f();"), &mut scope);
+            assert_eq!(engine.run_for(20), Ok(Status::Suspended));
+
+            engine.image = compile("2;");
+            assert_eq!(engine.run(), Ok(vm::JsValue::number(2 as f64)))
+        }
+    }
 }
@@ -0,0 +1,622 @@
+use super::JsValue;
+use super::Value;
+use super::VM;
+use super::error::JsError;
+use super::function::NativeFunction;
+use super::function::argument;
+use super::heap::ELEMENT_BYTES;
+use super::heap::note_allocation;
+use super::object::ObjectKind;
+use super::operations::is_strictly_equal;
+use super::object::ObjectRef;
+use super::object::Property;
+use super::object::Slot;
+use super::realm::Realm;
+use super::temp::js_value_to_string;
+use super::temp::join_array;
+use super::types::js_value_to_number;
+use super::types::js_value_to_rust_boolean;
+use std::cmp;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+/// The elements of an array. Holes are simply missing from `elements`, so
+/// sparse arrays stay cheap.
+pub struct JsArray {
+    pub elements: BTreeMap<u32, JsValue>,
+    /// Elements given attributes of their own by `Object.defineProperty`,
+    /// including accessors, which are left out of `elements`
+    pub attributed: BTreeMap<u32, Property>,
+    pub length: u32,
+    /// Attributes shared by the elements in `elements`, which
+    /// `Object.freeze` and `Object.seal` take away
+    pub writable: bool,
+    pub configurable: bool,
+    pub length_writable: bool,
+}
+
+impl JsArray {
+    pub fn new(values: Vec<JsValue>) -> JsArray {
+        let length = values.len() as u32;
+        note_allocation(values.len() * ELEMENT_BYTES);
+        let elements = values.into_iter().enumerate().map(|(index, value)| (index as u32, value)).collect();
+
+        JsArray { elements, attributed: BTreeMap::new(), length, writable: true, configurable: true, length_writable: true }
+    }
+
+    /// The value of an element, `None` for holes and accessors.
+    pub fn get(&self, index: u32) -> Option<&JsValue> {
+        match self.attributed.get(&index) {
+            Some(&Property { slot: Slot::Data { ref value, .. }, .. }) => Some(value),
+            Some(_) => None,
+            None => self.elements.get(&index),
+        }
+    }
+
+    /// The first index in `start..end` that is not a hole.
+    pub fn next_index(&self, start: u32, end: u32) -> Option<u32> {
+        if start >= end {
+            return None
+        }
+        let plain = self.elements.range(start..end).next().map(|(&index, _)| index);
+        let attributed = self.attributed.range(start..end).next().map(|(&index, _)| index);
+        match (plain, attributed) {
+            (Some(plain), Some(attributed)) => Some(cmp::min(plain, attributed)),
+            (plain, attributed) => plain.or(attributed),
+        }
+    }
+
+    /// Writes an element with the shared attributes, growing `length` past
+    /// it if needed.
+    pub fn set(&mut self, index: u32, value: JsValue) {
+        let attributed = self.attributed.remove(&index).is_some();
+        if self.elements.insert(index, value).is_none() && !attributed {
+            note_allocation(ELEMENT_BYTES);
+        }
+        self.grow(index);
+    }
+
+    /// Defines an element with attributes of its own, growing `length` past
+    /// it if needed.
+    pub fn set_attributed(&mut self, index: u32, property: Property) {
+        let plain = self.elements.remove(&index).is_some();
+        if self.attributed.insert(index, property).is_none() && !plain {
+            note_allocation(ELEMENT_BYTES);
+        }
+        self.grow(index);
+    }
+
+    fn grow(&mut self, index: u32) {
+        if index >= self.length {
+            self.length = index + 1;
+        }
+    }
+
+    /// Changes `length`, deleting any element that no longer fits.
+    pub fn set_length(&mut self, length: u32) {
+        if length < self.length {
+            self.elements.split_off(&length);
+            self.attributed.split_off(&length);
+        }
+        self.length = length;
+    }
+
+    /// Changes `length` like assigning to it does, where non-configurable
+    /// elements stop the array from shrinking past them.
+    pub fn truncate(&mut self, length: u32) {
+        let last = if self.configurable { None } else { self.elements.keys().next_back().map(|&index| index + 1) };
+        let locked = self.attributed.iter().rev().find(|&(_, property)| !property.configurable).map(|(&index, _)| index + 1);
+        match cmp::max(last, locked) {
+            Some(last) if length < last => self.set_length(last),
+            _ => self.set_length(length),
+        }
+    }
+
+    /// Turns the elements from `start` on that have attributes of their own
+    /// back into plain ones, so that they can be moved around. Fails when
+    /// one of them can't be written or deleted, or is an accessor.
+    fn plain_from(&mut self, start: u32) -> bool {
+        let movable = self.attributed.range(start..).all(|(_, property)| {
+            property.configurable && matches!(property.slot, Slot::Data { writable: true, .. })
+        });
+        if !movable {
+            return false
+        }
+
+        for (index, property) in self.attributed.split_off(&start) {
+            if let Slot::Data { value, .. } = property.slot {
+                self.elements.insert(index, value);
+            }
+        }
+        true
+    }
+}
+
+/// The array index a property key stands for, if any. Only the canonical
+/// form of an integer below 2^32 - 1 is an index, so `"01"` is not.
+pub fn array_index(key: &str) -> Option<u32> {
+    match key.parse::<u32>() {
+        Ok(index) if index != u32::MAX && index.to_string() == key => Some(index),
+        _ => None,
+    }
+}
+
+/// Checks a value assigned to the `length` of an array, which has to be an
+/// integer that fits in 32 bits.
+pub fn to_array_length(value: &JsValue) -> Result<u32, JsError> {
+    let number = js_value_to_number(value);
+
+    if number >= 0.0 && number <= u32::MAX as f64 && number.fract() == 0.0 {
+        return Ok(number as u32)
+    }
+
+    Err(JsError::range_error("Invalid array length".to_owned()))
+}
+
+/// Defines the `Array` constructor and `Array.prototype` methods.
+pub fn install(realm: &Realm) -> ObjectRef {
+    let prototype = &realm.array_prototype;
+    let methods: Vec<(&str, NativeFunction)> = vec![
+        ("push", push),
+        ("pop", pop),
+        ("slice", slice),
+        ("splice", splice),
+        ("indexOf", index_of),
+        ("join", join),
+        ("toString", to_string),
+        ("map", map),
+        ("filter", filter),
+        ("reduce", reduce),
+        ("forEach", for_each),
+        ("sort", sort),
+    ];
+    for (name, method) in methods {
+        prototype.set_hidden(name.to_owned(), JsValue::object(realm.new_native_function(method)));
+    }
+
+    let constructor = realm.new_native_function(array_constructor);
+    constructor.set_constant("prototype".to_owned(), JsValue::object(prototype.clone()));
+    constructor.set_hidden("isArray".to_owned(), JsValue::object(realm.new_native_function(is_array)));
+    prototype.set_hidden("constructor".to_owned(), JsValue::object(constructor.clone()));
+
+    constructor
+}
+
+fn this_array(this: &JsValue, method: &str) -> Result<ObjectRef, JsError> {
+    match this.view() {
+        Value::Object(object) if object.is_array() => Ok(object.clone()),
+        _ => Err(JsError::type_error(format!("Array.prototype.{} called on a value that is not an array", method))),
+    }
+}
+
+/// Mutating methods throw on arrays that were frozen, sealed or made
+/// non-extensible, like they do in strict mode code.
+fn check_mutable(array: &ObjectRef, method: &str, adds: bool, removes: bool) -> Result<(), JsError> {
+    let object = array.borrow();
+    let locked = match object.kind {
+        ObjectKind::Array(ref elements) => {
+            !elements.writable
+                || ((adds || removes) && !elements.length_writable)
+                || (adds && !object.extensible)
+                || (removes && !elements.configurable)
+        },
+        _ => false,
+    };
+
+    if locked {
+        return Err(JsError::type_error(format!("Array.prototype.{} called on a frozen, sealed or non-extensible array", method)))
+    }
+    Ok(())
+}
+
+fn is_function(value: &JsValue) -> bool {
+    value.as_object().is_some_and(|object| object.is_callable())
+}
+
+fn callback(args: &[JsValue], method: &str) -> Result<JsValue, JsError> {
+    match args.first() {
+        Some(function) if is_function(function) => Ok(function.clone()),
+        Some(value) => Err(JsError::type_error(format!("{} is not a function, passed to Array.prototype.{}", js_value_to_string(value), method))),
+        None => Err(JsError::type_error(format!("undefined is not a function, passed to Array.prototype.{}", method))),
+    }
+}
+
+fn length(array: &ObjectRef) -> u32 {
+    match array.borrow().kind {
+        ObjectKind::Array(ref array) => array.length,
+        _ => 0,
+    }
+}
+
+/// The first index in `start..end` that is not a hole. Checked anew for
+/// every element, as callbacks may add and delete elements on the way.
+fn next_index(array: &ObjectRef, start: u32, end: u32) -> Option<u32> {
+    match array.borrow().kind {
+        ObjectKind::Array(ref array) => array.next_index(start, end),
+        _ => None,
+    }
+}
+
+/// Reads an element that is not a hole, running its getter if it has one.
+fn element(vm: &mut VM, array: &ObjectRef, index: u32) -> Result<Option<JsValue>, JsError> {
+    match array.get_own_property(&index.to_string()) {
+        Some(Property { slot: Slot::Data { value, .. }, .. }) => Ok(Some(value)),
+        Some(Property { slot: Slot::Accessor { get, .. }, .. }) if get.is_undefined() => Ok(Some(JsValue::UNDEFINED)),
+        Some(Property { slot: Slot::Accessor { get, .. }, .. }) => vm.call_function(&get, JsValue::object(array.clone()), &[]).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Makes the elements from `start` on plain, for the methods that move
+/// elements around.
+fn check_movable(array: &ObjectRef, method: &str, start: u32) -> Result<(), JsError> {
+    if !with_elements(array, |elements| elements.plain_from(start)) {
+        return Err(JsError::type_error(format!("Array.prototype.{} called on an array with read-only, non-configurable or accessor elements", method)))
+    }
+    Ok(())
+}
+
+fn with_elements<T, F: FnOnce(&mut JsArray) -> T>(array: &ObjectRef, f: F) -> T {
+    match array.borrow_mut().kind {
+        ObjectKind::Array(ref mut array) => f(array),
+        _ => unreachable!("Checked by this_array"),
+    }
+}
+
+/// Resolves a relative index argument like the ones of `slice`, where
+/// negative values count back from the end.
+fn relative_index(value: &JsValue, length: u32, default: u32) -> u32 {
+    if *value == JsValue::UNDEFINED {
+        return default
+    }
+
+    let number = js_value_to_number(value);
+    let number = if number.is_nan() { 0.0 } else { number.trunc() };
+
+    if number < 0.0 {
+        (length as f64 + number).max(0.0) as u32
+    } else {
+        number.min(length as f64) as u32
+    }
+}
+
+fn array_constructor(vm: &mut VM, _this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    // A single number is the length of a new array full of holes
+    if args.len() == 1 && args[0].as_number().is_some() {
+        let length = to_array_length(&args[0])?;
+        vm.realm.heap.check_array_length(length as u64)?;
+        let array = vm.realm.new_array(Vec::new());
+        with_elements(&array, |elements| elements.set_length(length));
+        return Ok(JsValue::object(array))
+    }
+
+    vm.realm.heap.check_array_length(args.len() as u64)?;
+    Ok(JsValue::object(vm.realm.new_array(args.to_vec())))
+}
+
+fn is_array(_vm: &mut VM, _this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    Ok(JsValue::boolean(argument(args, 0).as_object().is_some_and(|object| object.is_array())))
+}
+
+fn push(vm: &mut VM, this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let array = this_array(&this, "push")?;
+    check_mutable(&array, "push", true, false)?;
+    vm.realm.heap.check_array_length(length(&array) as u64 + args.len() as u64)?;
+
+    let length = with_elements(&array, |elements| {
+        for value in args {
+            let index = elements.length;
+            elements.set(index, value.clone());
+        }
+        elements.length
+    });
+    for value in args {
+        vm.realm.heap.write_barrier(&array, value);
+    }
+
+    Ok(JsValue::number(length as f64))
+}
+
+fn pop(vm: &mut VM, this: JsValue, _args: &[JsValue]) -> Result<JsValue, JsError> {
+    let array = this_array(&this, "pop")?;
+    check_mutable(&array, "pop", false, true)?;
+
+    let last = match length(&array) {
+        0 => return Ok(JsValue::UNDEFINED),
+        length => length - 1,
+    };
+    let value = element(vm, &array, last)?;
+    if !array.delete(&last.to_string()) {
+        return Err(JsError::type_error(format!("Cannot delete property '{}' of [object Array]", last)))
+    }
+    with_elements(&array, |elements| elements.set_length(last));
+
+    Ok(value.unwrap_or(JsValue::UNDEFINED))
+}
+
+fn slice(vm: &mut VM, this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let array = this_array(&this, "slice")?;
+    let length = length(&array);
+    let start = relative_index(&argument(args, 0), length, 0);
+    let end = relative_index(&argument(args, 1), length, length);
+
+    let result = vm.realm.new_array(Vec::new());
+    with_elements(&result, |result| {
+        result.set_length(end.saturating_sub(start));
+    });
+    let mut next = start;
+    while let Some(index) = next_index(&array, next, end) {
+        vm.tick()?;
+        if let Some(value) = element(vm, &array, index)? {
+            with_elements(&result, |result| result.set(index - start, value));
+        }
+        next = index + 1;
+    }
+
+    Ok(JsValue::object(result))
+}
+
+fn splice(vm: &mut VM, this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let array = this_array(&this, "splice")?;
+    check_mutable(&array, "splice", true, true)?;
+    let length = length(&array);
+    let start = relative_index(&argument(args, 0), length, 0);
+    let delete_count = match args.len() {
+        0 => 0,
+        1 => length - start,
+        _ => {
+            let count = js_value_to_number(&args[1]);
+            let count = if count.is_nan() { 0.0 } else { count.trunc() };
+            count.max(0.0).min((length - start) as f64) as u32
+        },
+    };
+    let items = if args.len() > 2 { &args[2..] } else { &[] };
+
+    vm.realm.heap.check_array_length(length as u64 - delete_count as u64 + items.len() as u64)?;
+    check_movable(&array, "splice", start)?;
+
+    let removed = vm.realm.new_array(Vec::new());
+    with_elements(&array, |elements| {
+        // Everything from `start` on is taken out and put back shifted
+        let mut tail = elements.elements.split_off(&start);
+        let rest = tail.split_off(&(start + delete_count));
+
+        with_elements(&removed, |removed| {
+            for (index, value) in tail {
+                removed.set(index - start, value);
+            }
+            removed.set_length(delete_count);
+        });
+
+        note_allocation(items.len() * ELEMENT_BYTES);
+        for (offset, value) in items.iter().enumerate() {
+            elements.elements.insert(start + offset as u32, value.clone());
+        }
+        for (index, value) in rest {
+            elements.elements.insert(index - delete_count + items.len() as u32, value);
+        }
+        elements.length = length - delete_count + items.len() as u32;
+    });
+
+    Ok(JsValue::object(removed))
+}
+
+fn index_of(vm: &mut VM, this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let array = this_array(&this, "indexOf")?;
+    let search = argument(args, 0);
+    let length = length(&array);
+    let start = relative_index(&argument(args, 1), length, 0);
+
+    // Accessors are skipped, since their getters can't be strictly equal
+    let mut next = start;
+    while let Some(index) = next_index(&array, next, length) {
+        vm.tick()?;
+        let found = with_elements(&array, |elements| elements.get(index).is_some_and(|value| is_strictly_equal(value, &search)));
+        if found {
+            return Ok(JsValue::number(index as f64))
+        }
+        next = index + 1;
+    }
+
+    Ok(JsValue::number(-1.0))
+}
+
+fn join(vm: &mut VM, this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let array = this_array(&this, "join")?;
+    let separator = argument(args, 0);
+    let separator = if separator.is_undefined() { ",".to_owned() } else { js_value_to_string(&separator) };
+
+    join_limited(vm, &array, &separator)
+}
+
+fn to_string(vm: &mut VM, this: JsValue, _args: &[JsValue]) -> Result<JsValue, JsError> {
+    let array = this_array(&this, "toString")?;
+    join_limited(vm, &array, ",")
+}
+
+/// Joins an array, failing once the result is longer than strings may be.
+fn join_limited(vm: &mut VM, array: &ObjectRef, separator: &str) -> Result<JsValue, JsError> {
+    let max_length = vm.realm.heap.max_string_length();
+    join_array(array, separator, max_length, &mut || vm.tick()).map(JsValue::string)
+}
+
+/// Calls `callback` with every element that is not a hole, along with its
+/// index and the array, like the iteration methods do.
+fn each_element<F>(vm: &mut VM, array: &ObjectRef, args: &[JsValue], method: &str, mut f: F) -> Result<(), JsError>
+    where F: FnMut(&mut VM, u32, JsValue, JsValue) -> Result<(), JsError>
+{
+    let callback = callback(args, method)?;
+    let this = argument(args, 1);
+
+    // Elements added by the callback past the length are not visited
+    let length = length(array);
+    let mut next = 0;
+    while let Some(index) = next_index(array, next, length) {
+        vm.tick()?;
+        if let Some(value) = element(vm, array, index)? {
+            let arguments = [value.clone(), JsValue::number(index as f64), JsValue::object(array.clone())];
+            let result = vm.call_function(&callback, this.clone(), &arguments)?;
+            f(vm, index, value, result)?;
+        }
+        next = index + 1;
+    }
+
+    Ok(())
+}
+
+fn map(vm: &mut VM, this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let array = this_array(&this, "map")?;
+    let result = vm.realm.new_array(Vec::new());
+    let length = length(&array);
+    with_elements(&result, |result| result.set_length(length));
+
+    each_element(vm, &array, args, "map", |_, index, _, mapped| {
+        with_elements(&result, |result| result.set(index, mapped));
+        Ok(())
+    })?;
+
+    Ok(JsValue::object(result))
+}
+
+fn filter(vm: &mut VM, this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let array = this_array(&this, "filter")?;
+    let mut kept = Vec::new();
+
+    each_element(vm, &array, args, "filter", |_, _, value, keep| {
+        if js_value_to_rust_boolean(&keep) {
+            kept.push(value);
+        }
+        Ok(())
+    })?;
+
+    Ok(JsValue::object(vm.realm.new_array(kept)))
+}
+
+fn for_each(vm: &mut VM, this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let array = this_array(&this, "forEach")?;
+    each_element(vm, &array, args, "forEach", |_, _, _, _| Ok(()))?;
+
+    Ok(JsValue::UNDEFINED)
+}
+
+fn reduce(vm: &mut VM, this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let array = this_array(&this, "reduce")?;
+    let callback = callback(args, "reduce")?;
+    let length = length(&array);
+
+    let mut next = 0;
+    let mut accumulator = match args.get(1) {
+        Some(initial) => initial.clone(),
+        None => {
+            // Without an initial value the first element that is not a
+            // hole starts off the reduction
+            loop {
+                let index = match next_index(&array, next, length) {
+                    Some(index) => index,
+                    None => return Err(JsError::type_error("Reduce of empty array with no initial value".to_owned())),
+                };
+                next = index + 1;
+                vm.tick()?;
+                if let Some(value) = element(vm, &array, index)? {
+                    break value
+                }
+            }
+        },
+    };
+
+    while let Some(index) = next_index(&array, next, length) {
+        vm.tick()?;
+        if let Some(value) = element(vm, &array, index)? {
+            let arguments = [accumulator, value, JsValue::number(index as f64), JsValue::object(array.clone())];
+            accumulator = vm.call_function(&callback, JsValue::UNDEFINED, &arguments)?;
+        }
+        next = index + 1;
+    }
+
+    Ok(accumulator)
+}
+
+fn sort(vm: &mut VM, this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let array = this_array(&this, "sort")?;
+    check_mutable(&array, "sort", false, false)?;
+    let comparator = match argument(args, 0) {
+        value if value.is_undefined() => None,
+        function if is_function(&function) => Some(function),
+        value => return Err(JsError::type_error(format!("The comparison function must be either a function or undefined, got {}", js_value_to_string(&value)))),
+    };
+    check_movable(&array, "sort", 0)?;
+
+    // Undefined values go after everything else, and holes after those
+    let (values, undefined_count): (Vec<JsValue>, usize) = with_elements(&array, |elements| {
+        let undefined_count = elements.elements.values().filter(|&value| *value == JsValue::UNDEFINED).count();
+        let values = elements.elements.values().filter(|&value| *value != JsValue::UNDEFINED).cloned().collect();
+        (values, undefined_count)
+    });
+
+    let sorted = merge_sort(vm, values, &comparator)?;
+
+    with_elements(&array, |elements| {
+        let length = elements.length;
+        elements.elements.clear();
+
+        let undefined = (0..undefined_count).map(|_| JsValue::UNDEFINED);
+        for (index, value) in sorted.into_iter().chain(undefined).enumerate() {
+            elements.elements.insert(index as u32, value);
+        }
+        elements.length = length;
+    });
+
+    Ok(this)
+}
+
+/// A stable sort that can bail out when the comparison function throws.
+fn merge_sort(vm: &mut VM, values: Vec<JsValue>, comparator: &Option<JsValue>) -> Result<Vec<JsValue>, JsError> {
+    if values.len() <= 1 {
+        return Ok(values)
+    }
+
+    let mut right = values;
+    let left = right.drain(..right.len() / 2).collect();
+    let left = merge_sort(vm, left, comparator)?;
+    let right = merge_sort(vm, right, comparator)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+
+    loop {
+        let take_right = match (left.peek(), right.peek()) {
+            (Some(a), Some(b)) => compare(vm, a, b, comparator)? == Ordering::Greater,
+            (Some(_), None) => false,
+            (None, Some(_)) => true,
+            (None, None) => break,
+        };
+
+        if take_right {
+            merged.push(right.next().unwrap());
+        } else {
+            merged.push(left.next().unwrap());
+        }
+    }
+
+    Ok(merged)
+}
+
+fn compare(vm: &mut VM, a: &JsValue, b: &JsValue, comparator: &Option<JsValue>) -> Result<Ordering, JsError> {
+    vm.tick()?;
+    match comparator {
+        Some(comparator) => {
+            let result = js_value_to_number(&vm.call_function(comparator, JsValue::UNDEFINED, &[a.clone(), b.clone()])?);
+
+            if result < 0.0 {
+                Ok(Ordering::Less)
+            } else if result > 0.0 {
+                Ok(Ordering::Greater)
+            } else {
+                Ok(Ordering::Equal)
+            }
+        },
+        // Strings compare by their UTF-16 code units
+        None => Ok(js_value_to_string(a).encode_utf16().cmp(js_value_to_string(b).encode_utf16())),
+    }
+}
@@ -0,0 +1,228 @@
+use super::JsValue;
+use super::Value;
+use super::VM;
+use super::function::NativeFunction;
+use super::function::argument;
+use super::object::ObjectRef;
+use super::operations::get_property;
+use super::realm::Realm;
+use super::scope::BindingError;
+use super::temp::js_value_to_string;
+
+use std::fmt;
+
+/// The kinds of error objects, each with a prototype in the realm. All but
+/// `Error` are thrown by the VM on its own.
+// Named after the JS constructors they stand for
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum ErrorKind {
+    Error,
+    TypeError,
+    ReferenceError,
+    RangeError,
+    /// Malformed bytecode, like a stack underflow or a jump out of a block
+    InternalError,
+}
+
+impl ErrorKind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ErrorKind::Error => "Error",
+            ErrorKind::TypeError => "TypeError",
+            ErrorKind::ReferenceError => "ReferenceError",
+            ErrorKind::RangeError => "RangeError",
+            ErrorKind::InternalError => "InternalError",
+        }
+    }
+}
+
+/// Why the VM gave up on a script. Scripts can't catch these, not even with
+/// `finally`, so they always end up with the host.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Termination {
+    /// The heap went past its limit again while the script was still
+    /// recovering from running out of memory
+    OutOfMemory,
+    /// The script used up the instructions it was allowed to run
+    OutOfFuel,
+    /// The host interrupted the script through an `InterruptHandle`
+    Interrupted,
+}
+
+impl Termination {
+    pub fn message(&self) -> &'static str {
+        match *self {
+            Termination::OutOfMemory => "Out of memory while handling running out of memory",
+            Termination::OutOfFuel => "Instruction budget exhausted",
+            Termination::Interrupted => "Interrupted",
+        }
+    }
+}
+
+/// A thrown JS value that nothing caught, or the reason the script was
+/// terminated.
+#[derive(Debug, PartialEq, Clone)]
+pub struct JsError {
+    pub value: JsValue,
+    pub termination: Option<Termination>,
+    /// An error raised where the realm is out of reach, whose error object
+    /// is created once the VM gets to throw it
+    pending: Option<(ErrorKind, String)>,
+}
+
+impl JsError {
+    pub fn thrown(value: JsValue) -> JsError {
+        JsError { value, termination: None, pending: None }
+    }
+
+    pub fn terminated(termination: Termination) -> JsError {
+        JsError { value: JsValue::UNDEFINED, termination: Some(termination), pending: None }
+    }
+
+    /// Raises an error like `new TypeError(message)` would throw.
+    pub fn new(kind: ErrorKind, message: String) -> JsError {
+        JsError { value: JsValue::UNDEFINED, termination: None, pending: Some((kind, message)) }
+    }
+
+    pub fn type_error(message: String) -> JsError {
+        JsError::new(ErrorKind::TypeError, message)
+    }
+
+    pub fn reference_error(message: String) -> JsError {
+        JsError::new(ErrorKind::ReferenceError, message)
+    }
+
+    pub fn range_error(message: String) -> JsError {
+        JsError::new(ErrorKind::RangeError, message)
+    }
+
+    pub fn internal_error(message: &str) -> JsError {
+        JsError::new(ErrorKind::InternalError, message.to_owned())
+    }
+
+    /// Creates the error object of a raised error in `realm`, which is how
+    /// the VM throws it.
+    pub fn realize(mut self, realm: &Realm) -> JsError {
+        if let Some((kind, message)) = self.pending.take() {
+            self.value = JsValue::object(realm.new_error(kind, Some(message)));
+        }
+        self
+    }
+
+    /// The `name` of a thrown error object, if it has one.
+    pub fn name(&self) -> Option<String> {
+        match self.pending {
+            Some((kind, _)) => Some(kind.name().to_owned()),
+            None => self.string_property("name"),
+        }
+    }
+
+    /// The `message` of a thrown error object, if it has one.
+    pub fn message(&self) -> Option<String> {
+        match self.pending {
+            Some((_, ref message)) => Some(message.clone()),
+            None => self.string_property("message"),
+        }
+    }
+
+    fn string_property(&self, key: &str) -> Option<String> {
+        match self.value.view() {
+            Value::Object(object) => match object.get(key).view() {
+                Value::String(string) => Some(string.to_owned()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl From<BindingError> for JsError {
+    fn from(why: BindingError) -> JsError {
+        match why {
+            BindingError::Uninitialized(name) => JsError::reference_error(format!("Cannot access '{}' before initialization", name)),
+            BindingError::AssignToConstant(name) => JsError::type_error(format!("Assignment to constant variable '{}'", name)),
+            BindingError::Undeclared(name) => JsError::internal_error(&format!("Initialized the undeclared binding '{}'", name)),
+        }
+    }
+}
+
+impl fmt::Display for JsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(termination) = self.termination {
+            return write!(f, "Terminated: {}", termination.message())
+        }
+
+        match (self.name(), self.message()) {
+            (Some(name), Some(message)) => write!(f, "{}: {}", name, message),
+            _ => write!(f, "{}", js_value_to_string(&self.value)),
+        }
+    }
+}
+
+/// Defines `Error.prototype.toString` and a constructor for every kind of
+/// error scripts can create, to be bound as globals.
+pub fn install(realm: &Realm) -> Vec<(String, ObjectRef)> {
+    let error_prototype = realm.error_prototype(ErrorKind::Error);
+    error_prototype.set_hidden("toString".to_owned(), JsValue::object(realm.new_native_function(to_string)));
+
+    let kinds: Vec<(ErrorKind, NativeFunction)> = vec![
+        (ErrorKind::Error, error_constructor),
+        (ErrorKind::TypeError, type_error_constructor),
+        (ErrorKind::ReferenceError, reference_error_constructor),
+        (ErrorKind::RangeError, range_error_constructor),
+    ];
+    let mut constructors: Vec<(String, ObjectRef)> = Vec::new();
+    for (kind, constructor) in kinds {
+        let prototype = realm.error_prototype(kind);
+        let constructor = realm.new_native_function(constructor);
+        if kind != ErrorKind::Error {
+            constructor.borrow_mut().prototype = Some(constructors[0].1.clone());
+        }
+        constructor.set_constant("prototype".to_owned(), JsValue::object(prototype.clone()));
+        prototype.set_hidden("constructor".to_owned(), JsValue::object(constructor.clone()));
+        constructors.push((kind.name().to_owned(), constructor));
+    }
+
+    constructors
+}
+
+fn error_constructor(vm: &mut VM, _this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    construct(vm, ErrorKind::Error, args)
+}
+
+fn type_error_constructor(vm: &mut VM, _this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    construct(vm, ErrorKind::TypeError, args)
+}
+
+fn reference_error_constructor(vm: &mut VM, _this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    construct(vm, ErrorKind::ReferenceError, args)
+}
+
+fn range_error_constructor(vm: &mut VM, _this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    construct(vm, ErrorKind::RangeError, args)
+}
+
+/// Creates an error whether or not the constructor was called with `new`.
+fn construct(vm: &mut VM, kind: ErrorKind, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let message = argument(args, 0);
+    let message = if message.is_undefined() { None } else { Some(js_value_to_string(&message)) };
+    Ok(JsValue::object(vm.realm.new_error(kind, message)))
+}
+
+fn to_string(vm: &mut VM, this: JsValue, _args: &[JsValue]) -> Result<JsValue, JsError> {
+    if !this.is_object() {
+        return Err(JsError::type_error("Error.prototype.toString called on a value that is not an object".to_owned()))
+    }
+
+    let name = get_property(vm, &this, &JsValue::string("name"))?;
+    let name = if name.is_undefined() { "Error".to_owned() } else { js_value_to_string(&name) };
+    let message = get_property(vm, &this, &JsValue::string("message"))?;
+    let message = if message.is_undefined() { String::new() } else { js_value_to_string(&message) };
+
+    match (name.is_empty(), message.is_empty()) {
+        (true, _) => Ok(JsValue::string(message)),
+        (false, true) => Ok(JsValue::string(name)),
+        (false, false) => Ok(JsValue::string(format!("{}: {}", name, message))),
+    }
+}
@@ -0,0 +1,38 @@
+use super::super::bytecode::Block;
+use super::scope::ScopeRef;
+use super::error::JsError;
+use super::JsValue;
+use super::Value;
+use super::VM;
+use super::realm::Realm;
+use super::temp::js_value_to_string;
+use std::rc::Rc;
+
+/// A built-in function implemented in Rust. It gets the `this` value and the
+/// arguments of the call.
+pub type NativeFunction = fn(&mut VM, JsValue, &[JsValue]) -> Result<JsValue, JsError>;
+
+/// The callable part of a function object.
+pub enum Function {
+    /// A compiled function, pointing at the block holding its body and the
+    /// scope it was defined in
+    Bytecode { code: Rc<Block>, scope: Option<ScopeRef> },
+    Native(NativeFunction),
+}
+
+/// The argument at `index` of a native call, `undefined` when it is missing.
+pub fn argument(args: &[JsValue], index: usize) -> JsValue {
+    args.get(index).cloned().unwrap_or(JsValue::UNDEFINED)
+}
+
+pub fn install(realm: &Realm) {
+    let to_string = realm.new_native_function(to_string);
+    realm.function_prototype.set_hidden("toString".to_owned(), JsValue::object(to_string));
+}
+
+fn to_string(_vm: &mut VM, this: JsValue, _args: &[JsValue]) -> Result<JsValue, JsError> {
+    match this.view() {
+        Value::Object(object) if object.is_callable() => Ok(JsValue::string(js_value_to_string(&this))),
+        _ => Err(JsError::type_error("Function.prototype.toString requires that 'this' be a Function".to_owned())),
+    }
+}
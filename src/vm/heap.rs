@@ -0,0 +1,820 @@
+use super::JsValue;
+use super::Value;
+use super::error::JsError;
+use super::error::Termination;
+use super::function::Function;
+use super::object::JsObject;
+use super::object::ObjectKind;
+use super::object::ObjectRef;
+use super::object::Property;
+use super::object::Slot;
+use super::object::WeakObjectRef;
+use super::realm::Realm;
+use super::scope::Scope;
+use super::scope::ScopeRef;
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::mem;
+use std::rc::Rc;
+
+/// A minor collection runs once this many objects were allocated since the
+/// last one.
+const NURSERY_SIZE: usize = 1024;
+
+/// A major collection runs once the old generation holds this many objects,
+/// or twice as many as the last one left alive if that is more.
+const MIN_MAJOR_THRESHOLD: usize = 4096;
+
+/// Rough sizes of what the heap holds, for measuring it against a limit.
+/// Strings and property keys add their length on top.
+pub const OBJECT_BYTES: usize = 128;
+pub const PROPERTY_BYTES: usize = 64;
+pub const ELEMENT_BYTES: usize = 32;
+pub const BINDING_BYTES: usize = 64;
+pub const STRING_BYTES: usize = 32;
+const VALUE_BYTES: usize = 8;
+
+/// The longest string, in bytes, scripts can create even without a limit.
+pub const MAX_STRING_LENGTH: usize = (1 << 29) - 24;
+
+thread_local! {
+    /// Bytes allocated on this thread, by every VM running on it. Objects and
+    /// strings are created in plenty of places that can't reach the heap,
+    /// so they report here instead.
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Records an allocation, which brings the next check of the heap limit
+/// closer.
+pub fn note_allocation(bytes: usize) {
+    ALLOCATED.with(|allocated| allocated.set(allocated.get().wrapping_add(bytes)));
+}
+
+type ObjectPtr = *const RefCell<JsObject>;
+type ScopePtr = *const RefCell<Scope>;
+
+/// The objects of a realm. Objects are reference counted, which frees most
+/// of them as soon as they become unreachable. Objects referencing each
+/// other, directly or through the scopes of closures, never run out of
+/// references though, so a tracing collector frees those.
+///
+/// New objects start out in the nursery, which minor collections look at
+/// on their own. Objects that survive one move to the old generation, which
+/// major collections mark either all at once or in slices of a configured
+/// budget while scripts keep running. Minor collections copy the objects
+/// that survive into new objects of the old generation and point whatever
+/// referred to them at the copies. Only references from the nursery and
+/// from old objects the write barrier remembered can be rewritten like that
+/// though, so survivors that the VM or the host hold are pinned and
+/// promoted in place, like a mostly-copying collector does.
+pub struct Heap {
+    old: RefCell<HashMap<ObjectPtr, WeakObjectRef>>,
+    nursery: RefCell<HashMap<ObjectPtr, WeakObjectRef>>,
+    /// Old objects that young objects were stored in since the last minor
+    /// collection, whose references to them it can rewrite
+    remembered: RefCell<HashMap<ObjectPtr, WeakObjectRef>>,
+    /// The major collection in progress, when marking incrementally
+    marking: RefCell<Option<Marker>>,
+    major_threshold: Cell<usize>,
+    /// How many objects and scopes a slice of marking may scan, `None`
+    /// marks everything at once
+    budget: Cell<Option<usize>>,
+    /// Collect everything after every allocation, which shakes out objects
+    /// that are not rooted properly
+    stress: Cell<bool>,
+    stats: Cell<GcStats>,
+    limits: Cell<Limits>,
+    /// Bytes reachable when the heap was last measured
+    measured: Cell<usize>,
+    /// The thread's `ALLOCATED` count when the heap was last measured.
+    /// Allocations of other VMs on the thread count towards this heap as
+    /// well, which only makes it get measured sooner.
+    allocated_at: Cell<usize>,
+    /// Whether scripts are still recovering from running out of memory
+    exhausted: Cell<bool>,
+}
+
+/// Caps on the memory scripts may use, `None` leaves one out.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    /// Estimated bytes of what scripts can reach, in objects, scopes and
+    /// strings. Going past it throws a RangeError, after which scripts
+    /// get another quarter of the limit to recover. Going past that as
+    /// well terminates them.
+    pub heap_bytes: Option<usize>,
+    /// Longest string, in bytes, that concatenating and joining may create
+    pub string_length: Option<usize>,
+    /// Longest array, in elements
+    pub array_length: Option<u32>,
+}
+
+/// Counts of the work the collector did.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GcStats {
+    pub minor_collections: usize,
+    pub major_collections: usize,
+    /// Slices of incremental marking, across all major collections
+    pub marking_slices: usize,
+    /// Objects minor collections copied out of the nursery rather than
+    /// promoting them in place
+    pub copied_objects: usize,
+}
+
+/// A reference held by an object or a scope.
+enum Edge<'a> {
+    Object(&'a ObjectRef),
+    Scope(&'a ScopeRef),
+}
+
+/// Marks everything reachable from the roots it is given.
+#[derive(Default)]
+pub struct Marker {
+    objects: HashSet<ObjectPtr>,
+    scopes: HashSet<ScopePtr>,
+    pending_objects: Vec<ObjectRef>,
+    pending_scopes: Vec<ScopeRef>,
+    /// The objects and scopes a minor collection is limited to
+    limit: Option<(HashSet<ObjectPtr>, HashSet<ScopePtr>)>,
+    /// Adds up what is marked, when measuring the heap
+    measure: Option<Measure>,
+}
+
+/// The estimated size of what a marker visits. Strings are counted once,
+/// however often they are referenced.
+#[derive(Default)]
+struct Measure {
+    bytes: usize,
+    strings: HashSet<*const u8>,
+}
+
+/// How often a set of objects, and the scopes they reach, are referenced
+/// from within that set. Anything referenced more often is held from
+/// outside of it, by older objects, the VM, a built-in or the host.
+#[derive(Default)]
+struct Census {
+    objects: HashMap<ObjectPtr, usize>,
+    scopes: HashMap<ScopePtr, (ScopeRef, usize)>,
+    pending: Vec<ScopeRef>,
+}
+
+impl Heap {
+    pub fn new() -> Heap {
+        Heap {
+            old: RefCell::new(HashMap::new()),
+            nursery: RefCell::new(HashMap::new()),
+            remembered: RefCell::new(HashMap::new()),
+            marking: RefCell::new(None),
+            major_threshold: Cell::new(MIN_MAJOR_THRESHOLD),
+            budget: Cell::new(None),
+            stress: Cell::new(false),
+            stats: Cell::new(GcStats::default()),
+            limits: Cell::new(Limits::default()),
+            measured: Cell::new(0),
+            allocated_at: Cell::new(ALLOCATED.with(Cell::get)),
+            exhausted: Cell::new(false),
+        }
+    }
+
+    pub fn allocate(&self, kind: ObjectKind, prototype: Option<ObjectRef>) -> ObjectRef {
+        let object = ObjectRef::with_kind(kind, prototype);
+        self.nursery.borrow_mut().insert(object.as_ptr(), object.downgrade());
+        object
+    }
+
+    pub fn set_budget(&self, budget: Option<usize>) {
+        self.budget.set(budget);
+    }
+
+    pub fn set_stress(&self, stress: bool) {
+        self.stress.set(stress);
+    }
+
+    pub fn stats(&self) -> GcStats {
+        self.stats.get()
+    }
+
+    pub fn set_limits(&self, limits: Limits) {
+        self.limits.set(limits);
+    }
+
+    /// Whether the heap may have grown past its limit since it was last
+    /// measured, which only allocating makes it do. Scripts recovering from
+    /// running out of memory are also measured after every eighth of the
+    /// limit they allocate, to notice once they are back under it.
+    pub fn over_limit(&self) -> bool {
+        let allocated = ALLOCATED.with(Cell::get).wrapping_sub(self.allocated_at.get());
+        let (limit, raised) = match (self.limits.get().heap_bytes, self.heap_limit()) {
+            (Some(limit), Some(raised)) => (limit, raised),
+            _ => return false,
+        };
+        if self.exhausted.get() && allocated > limit / 8 {
+            return true
+        }
+        self.measured.get().saturating_add(allocated) > raised
+    }
+
+    /// The limit on heap bytes, raised by the room scripts get to recover
+    /// after running out of memory.
+    fn heap_limit(&self) -> Option<usize> {
+        let limit = self.limits.get().heap_bytes?;
+        if self.exhausted.get() {
+            return Some(limit.saturating_add(limit / 4))
+        }
+        Some(limit)
+    }
+
+    /// Measures what `roots` reach against the heap limit, failing with a
+    /// RangeError the first time it is exceeded and terminating the script
+    /// when it is exceeded again before the script recovered.
+    pub fn check_limit(&self, roots: &dyn Fn(&mut Marker)) -> Result<(), JsError> {
+        let mut marker = Marker::new();
+        marker.measure = Some(Measure::default());
+        roots(&mut marker);
+        marker.drain(None);
+        let bytes = marker.measure.map_or(0, |measure| measure.bytes);
+
+        self.measured.set(bytes);
+        self.allocated_at.set(ALLOCATED.with(Cell::get));
+
+        let limit = match self.limits.get().heap_bytes {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        if bytes <= limit {
+            self.exhausted.set(false);
+            Ok(())
+        } else if !self.exhausted.get() {
+            self.exhausted.set(true);
+            Err(JsError::range_error("Out of memory".to_owned()))
+        } else if bytes > self.heap_limit().unwrap_or(limit) {
+            Err(JsError::terminated(Termination::OutOfMemory))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The longest string scripts may create, in bytes.
+    pub fn max_string_length(&self) -> usize {
+        self.limits.get().string_length.unwrap_or(MAX_STRING_LENGTH)
+    }
+
+    pub fn check_string_length(&self, length: usize) -> Result<(), JsError> {
+        if length > self.max_string_length() {
+            return Err(JsError::range_error("Invalid string length".to_owned()))
+        }
+        Ok(())
+    }
+
+    /// Fails with a RangeError for arrays longer than the limit, or than
+    /// any array can be.
+    pub fn check_array_length(&self, length: u64) -> Result<(), JsError> {
+        let limit = self.limits.get().array_length.unwrap_or(u32::MAX);
+        if length > limit as u64 {
+            return Err(JsError::range_error("Invalid array length".to_owned()))
+        }
+        Ok(())
+    }
+
+    pub fn live_objects(&self) -> usize {
+        let old = self.old.borrow().values().filter(|object| object.is_alive()).count();
+        old + self.nursery.borrow().values().filter(|object| object.is_alive()).count()
+    }
+
+    /// Whether `collect_step` has work to do.
+    pub fn needs_collection(&self) -> bool {
+        let young = self.nursery.borrow().len();
+        self.marking.borrow().is_some() || young >= NURSERY_SIZE || (self.stress.get() && young > 0)
+    }
+
+    /// Does the collection work that is due. `roots` marks what the VM
+    /// holds on to.
+    pub fn collect_step(&self, roots: &dyn Fn(&mut Marker)) {
+        if self.marking.borrow().is_some() {
+            return self.mark_slice(roots)
+        }
+
+        let stress = self.stress.get();
+        if stress || self.nursery.borrow().len() >= NURSERY_SIZE {
+            self.minor();
+        }
+        if stress || self.old.borrow().len() >= self.major_threshold.get() {
+            match self.budget.get() {
+                Some(_) if !stress => {
+                    let mut marker = Marker::new();
+                    roots(&mut marker);
+                    *self.marking.borrow_mut() = Some(marker);
+                },
+                _ => {
+                    self.major(roots);
+                },
+            }
+        }
+    }
+
+    /// Collects both generations all at once, finishing any incremental
+    /// marking first, and returns how many objects were freed.
+    pub fn collect(&self, roots: &dyn Fn(&mut Marker)) -> usize {
+        let freed = match self.marking.borrow_mut().take() {
+            Some(marker) => self.finish_marking(marker, roots),
+            None => 0,
+        };
+
+        freed + self.minor() + self.major(roots)
+    }
+
+    /// Called when `value` is stored in `object`. While marking, an object
+    /// that was marked already hides what is stored in it afterwards, so
+    /// the value is marked as well. An old object that a young one is
+    /// stored in is remembered, so the young one can still be copied.
+    pub fn write_barrier(&self, object: &ObjectRef, value: &JsValue) {
+        if let Some(ref mut marker) = *self.marking.borrow_mut() {
+            if marker.objects.contains(&object.as_ptr()) {
+                marker.value(value);
+            }
+        }
+
+        if let Value::Object(stored) = value.view() {
+            let nursery = self.nursery.borrow();
+            if nursery.contains_key(&stored.as_ptr()) && !nursery.contains_key(&object.as_ptr()) {
+                self.remembered.borrow_mut().insert(object.as_ptr(), object.downgrade());
+            }
+        }
+    }
+
+    /// Like `write_barrier`, for a value bound in a scope.
+    pub fn scope_write_barrier(&self, value: &JsValue) {
+        if let Some(ref mut marker) = *self.marking.borrow_mut() {
+            marker.value(value);
+        }
+    }
+
+    /// Collects the nursery, copying or promoting the objects that survive.
+    fn minor(&self) -> usize {
+        let young: Vec<ObjectRef> = mem::take(&mut *self.nursery.borrow_mut()).values().filter_map(WeakObjectRef::upgrade).collect();
+
+        let census = Census::take(&young);
+        let (objects, scopes) = census.external(&young);
+
+        let mut marker = Marker::new();
+        marker.limit = Some((young.iter().map(ObjectRef::as_ptr).collect(), census.scopes.keys().cloned().collect()));
+        for object in objects.iter() {
+            marker.object(object);
+        }
+        for scope in scopes.iter() {
+            marker.scope(scope);
+        }
+        marker.drain(None);
+
+        let (survivors, garbage): (Vec<ObjectRef>, Vec<ObjectRef>) = young.into_iter().partition(|object| marker.is_marked(object));
+        drop(marker);
+        drop(objects);
+        drop(scopes);
+
+        // Survivors referred to from anywhere but the nursery, the scopes
+        // it reaches and the remembered objects are pinned
+        let remembered: Vec<ObjectRef> = mem::take(&mut *self.remembered.borrow_mut()).values().filter_map(WeakObjectRef::upgrade).collect();
+        let mut references = census.objects;
+        for object in remembered.iter() {
+            object_edges(&object.borrow(), &mut |edge| if let Edge::Object(object) = edge {
+                *references.entry(object.as_ptr()).or_insert(0) += 1;
+            });
+        }
+        let pinned: HashSet<ObjectPtr> = survivors.iter()
+            .filter(|object| object.reference_count() > references.get(&object.as_ptr()).cloned().unwrap_or(0) + 1)
+            .map(ObjectRef::as_ptr)
+            .collect();
+        let freed = empty(garbage);
+
+        let scopes: Vec<ScopeRef> = census.scopes.into_values().map(|(scope, _)| scope).collect();
+        let (survivors, copied) = evacuate(survivors, &pinned, &remembered, &scopes);
+        let mut old = self.old.borrow_mut();
+        for object in survivors.iter() {
+            old.insert(object.as_ptr(), object.downgrade());
+        }
+        drop(old);
+
+        self.update_stats(|stats| {
+            stats.minor_collections += 1;
+            stats.copied_objects += copied;
+        });
+        freed
+    }
+
+    /// Marks and sweeps the old generation all at once.
+    fn major(&self, roots: &dyn Fn(&mut Marker)) -> usize {
+        let mut marker = Marker::new();
+        roots(&mut marker);
+        self.finish_marking(marker, roots)
+    }
+
+    /// Marks one slice of the major collection in progress, finishing it
+    /// once there is nothing left to mark.
+    fn mark_slice(&self, roots: &dyn Fn(&mut Marker)) {
+        let budget = self.budget.get();
+        let done = match *self.marking.borrow_mut() {
+            Some(ref mut marker) => marker.drain(budget),
+            None => return,
+        };
+        self.update_stats(|stats| stats.marking_slices += 1);
+
+        if done {
+            let marker = self.marking.borrow_mut().take().unwrap();
+            self.finish_marking(marker, roots);
+        }
+    }
+
+    /// Marks the roots once more, since they changed while marking went
+    /// on, and frees the unmarked objects of the old generation.
+    fn finish_marking(&self, mut marker: Marker, roots: &dyn Fn(&mut Marker)) -> usize {
+        roots(&mut marker);
+        marker.drain(None);
+
+        // Unmarked objects may still be held from outside, or stored in a
+        // marked object by a built-in, which the write barrier doesn't see
+        let candidates: Vec<ObjectRef> = self.old.borrow().values()
+            .filter_map(WeakObjectRef::upgrade)
+            .filter(|object| !marker.is_marked(object))
+            .collect();
+        let census = Census::take(&candidates);
+        let (objects, scopes) = census.external(&candidates);
+        drop(census);
+        for object in objects.iter() {
+            marker.object(object);
+        }
+        for scope in scopes.iter() {
+            marker.rescan_scope(scope);
+        }
+        marker.drain(None);
+
+        let garbage: Vec<ObjectRef> = candidates.into_iter().filter(|object| !marker.is_marked(object)).collect();
+        drop(marker);
+        let freed = empty(garbage);
+
+        let mut old = self.old.borrow_mut();
+        old.retain(|_, object| object.is_alive());
+        self.major_threshold.set(cmp::max(MIN_MAJOR_THRESHOLD, old.len() * 2));
+        drop(old);
+
+        self.update_stats(|stats| stats.major_collections += 1);
+        freed
+    }
+
+    fn update_stats<F: FnOnce(&mut GcStats)>(&self, update: F) {
+        let mut stats = self.stats.get();
+        update(&mut stats);
+        self.stats.set(stats);
+    }
+}
+
+impl Default for Heap {
+    fn default() -> Heap {
+        Heap::new()
+    }
+}
+
+/// Built-ins refer to each other in cycles, like constructors and their
+/// prototypes do, so the objects of a realm would outlive it. Dropping the
+/// heap collects with nothing rooted, which empties every object but those
+/// the host still holds and what they reach.
+impl Drop for Heap {
+    fn drop(&mut self) {
+        self.marking.borrow_mut().take();
+        self.minor();
+        self.major(&|_| {});
+    }
+}
+
+/// Empties unreachable objects, which breaks their cycles so that
+/// reference counting frees them, and returns how many there were.
+fn empty(garbage: Vec<ObjectRef>) -> usize {
+    let mut contents = Vec::new();
+    for object in garbage.iter() {
+        let mut object = object.borrow_mut();
+        let kind = mem::replace(&mut object.kind, ObjectKind::Ordinary);
+        contents.push((kind, object.prototype.take(), mem::take(&mut object.properties)));
+    }
+
+    garbage.len()
+}
+
+/// Copies the survivors of a minor collection that are not `pinned` into
+/// new objects, and points the survivors, the `remembered` old objects and
+/// the `scopes` they reach at the copies. Everything else referring to them
+/// was emptied as garbage by now. Nothing is copied while any of them is
+/// borrowed, since those references can't be rewritten then. Returns the
+/// survivors, copied or not, and how many were copied.
+fn evacuate(survivors: Vec<ObjectRef>, pinned: &HashSet<ObjectPtr>, remembered: &[ObjectRef], scopes: &[ScopeRef]) -> (Vec<ObjectRef>, usize) {
+    let borrowed = survivors.iter().chain(remembered).any(|object| !object.is_free()) || scopes.iter().any(|scope| scope.try_borrow_mut().is_err());
+    if borrowed {
+        return (survivors, 0)
+    }
+
+    let mut copies = HashMap::new();
+    let mut originals = Vec::new();
+    let survivors: Vec<ObjectRef> = survivors.into_iter().map(|object| {
+        if pinned.contains(&object.as_ptr()) {
+            return object
+        }
+        let copy = object.relocate();
+        copies.insert(object.as_ptr(), copy.clone());
+        originals.push(object);
+        copy
+    }).collect();
+
+    for object in survivors.iter().chain(remembered) {
+        forward_object(&mut object.borrow_mut(), &copies);
+    }
+    for scope in scopes.iter() {
+        for value in scope.borrow_mut().values_mut() {
+            forward_value(value, &copies);
+        }
+    }
+
+    debug_assert!(originals.iter().all(|object| object.reference_count() == 1), "a copied object is still referred to");
+    (survivors, originals.len())
+}
+
+/// Points the references of `object` to copied objects at their copies.
+/// Visits the same references as `object_edges`.
+fn forward_object(object: &mut JsObject, copies: &HashMap<ObjectPtr, ObjectRef>) {
+    let copy = object.prototype.as_ref().and_then(|prototype| copies.get(&prototype.as_ptr()));
+    if let Some(copy) = copy {
+        object.prototype = Some(copy.clone());
+    }
+
+    for property in object.properties.values_mut() {
+        forward_property(property, copies);
+    }
+
+    if let ObjectKind::Array(ref mut array) = object.kind {
+        for value in array.elements.values_mut() {
+            forward_value(value, copies);
+        }
+        for property in array.attributed.values_mut() {
+            forward_property(property, copies);
+        }
+    }
+}
+
+fn forward_property(property: &mut Property, copies: &HashMap<ObjectPtr, ObjectRef>) {
+    match property.slot {
+        Slot::Data { ref mut value, .. } => forward_value(value, copies),
+        Slot::Accessor { ref mut get, ref mut set } => {
+            forward_value(get, copies);
+            forward_value(set, copies);
+        },
+    }
+}
+
+fn forward_value(value: &mut JsValue, copies: &HashMap<ObjectPtr, ObjectRef>) {
+    let copy = match value.view() {
+        Value::Object(object) => copies.get(&object.as_ptr()).cloned(),
+        _ => None,
+    };
+    if let Some(copy) = copy {
+        *value = JsValue::object(copy);
+    }
+}
+
+impl Marker {
+    pub fn new() -> Marker {
+        Marker::default()
+    }
+
+    pub fn value(&mut self, value: &JsValue) {
+        if let Some(ref mut measure) = self.measure {
+            measure.bytes += VALUE_BYTES;
+            measure.value(value);
+        }
+        if let Value::Object(object) = value.view() {
+            self.object(&object);
+        }
+    }
+
+    pub fn object(&mut self, object: &ObjectRef) {
+        if let Some((ref objects, _)) = self.limit {
+            if !objects.contains(&object.as_ptr()) {
+                return
+            }
+        }
+        if self.objects.insert(object.as_ptr()) {
+            self.pending_objects.push(object.clone());
+        }
+    }
+
+    pub fn scope(&mut self, scope: &ScopeRef) {
+        if let Some((_, ref scopes)) = self.limit {
+            if !scopes.contains(&Rc::as_ptr(scope)) {
+                return
+            }
+        }
+        if self.scopes.insert(Rc::as_ptr(scope)) {
+            self.pending_scopes.push(scope.clone());
+        }
+    }
+
+    /// Marks the bindings of a scope nothing shares, like the global one.
+    pub fn scope_contents(&mut self, scope: &Scope) {
+        if let Some(ref mut measure) = self.measure {
+            measure.scope(scope);
+        }
+        scope_edges(scope, &mut |edge| self.edge(edge));
+    }
+
+    pub fn realm(&mut self, realm: &Realm) {
+        self.object(&realm.object_prototype);
+        self.object(&realm.function_prototype);
+        self.object(&realm.array_prototype);
+        self.object(&realm.number_prototype);
+        for prototype in realm.error_prototypes.values() {
+            self.object(prototype);
+        }
+        for (_, value) in realm.globals.iter() {
+            self.value(value);
+        }
+    }
+
+    fn is_marked(&self, object: &ObjectRef) -> bool {
+        self.objects.contains(&object.as_ptr())
+    }
+
+    /// Scans a scope again, which may have been given new bindings since
+    /// it was marked.
+    fn rescan_scope(&mut self, scope: &ScopeRef) {
+        self.scopes.insert(Rc::as_ptr(scope));
+        self.pending_scopes.push(scope.clone());
+    }
+
+    fn edge(&mut self, edge: Edge) {
+        match edge {
+            Edge::Object(object) => self.object(object),
+            Edge::Scope(scope) => self.scope(scope),
+        }
+    }
+
+    /// Marks what is reachable from what was marked so far, scanning at
+    /// most `budget` objects and scopes. Returns whether it got through
+    /// everything.
+    fn drain(&mut self, budget: Option<usize>) -> bool {
+        let mut scanned = 0;
+
+        while budget.is_none_or(|budget| scanned < budget) {
+            if let Some(object) = self.pending_objects.pop() {
+                if let Some(ref mut measure) = self.measure {
+                    measure.object(&object.borrow());
+                }
+                object_edges(&object.borrow(), &mut |edge| self.edge(edge));
+            } else if let Some(scope) = self.pending_scopes.pop() {
+                if let Some(ref mut measure) = self.measure {
+                    measure.scope(&scope.borrow());
+                }
+                scope_edges(&scope.borrow(), &mut |edge| self.edge(edge));
+            } else {
+                return true
+            }
+            scanned += 1;
+        }
+
+        self.pending_objects.is_empty() && self.pending_scopes.is_empty()
+    }
+}
+
+impl Measure {
+    fn value(&mut self, value: &JsValue) {
+        if let Value::String(string) = value.view() {
+            if self.strings.insert(string.as_ptr()) {
+                self.bytes += STRING_BYTES + string.len();
+            }
+        }
+    }
+
+    fn object(&mut self, object: &JsObject) {
+        self.bytes += OBJECT_BYTES;
+
+        for (key, property) in object.properties.iter() {
+            self.bytes += PROPERTY_BYTES + key.len();
+            match property.slot {
+                Slot::Data { ref value, .. } => self.value(value),
+                Slot::Accessor { .. } => {},
+            }
+        }
+
+        if let ObjectKind::Array(ref array) = object.kind {
+            for value in array.elements.values() {
+                self.bytes += ELEMENT_BYTES;
+                self.value(value);
+            }
+            for property in array.attributed.values() {
+                self.bytes += ELEMENT_BYTES;
+                if let Slot::Data { ref value, .. } = property.slot {
+                    self.value(value);
+                }
+            }
+        }
+    }
+
+    fn scope(&mut self, scope: &Scope) {
+        for value in scope.values() {
+            self.bytes += BINDING_BYTES;
+            self.value(value);
+        }
+    }
+}
+
+impl Census {
+    fn take(objects: &[ObjectRef]) -> Census {
+        let mut census = Census::default();
+        for object in objects.iter() {
+            object_edges(&object.borrow(), &mut |edge| census.count(edge));
+        }
+        while let Some(scope) = census.pending.pop() {
+            scope_edges(&scope.borrow(), &mut |edge| census.count(edge));
+        }
+
+        census
+    }
+
+    fn count(&mut self, edge: Edge) {
+        match edge {
+            Edge::Object(object) => *self.objects.entry(object.as_ptr()).or_insert(0) += 1,
+            Edge::Scope(scope) => {
+                let pending = &mut self.pending;
+                let entry = self.scopes.entry(Rc::as_ptr(scope)).or_insert_with(|| {
+                    pending.push(scope.clone());
+                    (scope.clone(), 0)
+                });
+                entry.1 += 1;
+            },
+        }
+    }
+
+    /// The objects and scopes referenced from outside of `objects`, which
+    /// the census was taken of and which holds one reference to each of
+    /// them itself. The census holds one more to each of its scopes.
+    fn external(&self, objects: &[ObjectRef]) -> (Vec<ObjectRef>, Vec<ScopeRef>) {
+        let objects = objects.iter()
+            .filter(|object| object.reference_count() > self.objects.get(&object.as_ptr()).cloned().unwrap_or(0) + 1)
+            .cloned()
+            .collect();
+        let scopes = self.scopes.values()
+            .filter(|&&(ref scope, internal)| Rc::strong_count(scope) > internal + 1)
+            .map(|(scope, _)| scope.clone())
+            .collect();
+
+        (objects, scopes)
+    }
+}
+
+fn value_edges(value: &JsValue, visit: &mut dyn FnMut(Edge)) {
+    if let Value::Object(object) = value.view() {
+        visit(Edge::Object(&object));
+    }
+}
+
+/// Visits every reference an object holds. The collector relies on this
+/// seeing all of them.
+fn object_edges(object: &JsObject, visit: &mut dyn FnMut(Edge)) {
+    if let Some(ref prototype) = object.prototype {
+        visit(Edge::Object(prototype));
+    }
+
+    for property in object.properties.values() {
+        property_edges(property, visit);
+    }
+
+    match object.kind {
+        ObjectKind::Array(ref array) => {
+            for value in array.elements.values() {
+                value_edges(value, visit);
+            }
+            for property in array.attributed.values() {
+                property_edges(property, visit);
+            }
+        },
+        ObjectKind::Function(Function::Bytecode { scope: Some(ref scope), .. }) => visit(Edge::Scope(scope)),
+        _ => {},
+    }
+}
+
+fn property_edges(property: &Property, visit: &mut dyn FnMut(Edge)) {
+    match property.slot {
+        Slot::Data { ref value, .. } => value_edges(value, visit),
+        Slot::Accessor { ref get, ref set } => {
+            value_edges(get, visit);
+            value_edges(set, visit);
+        },
+    }
+}
+
+fn scope_edges(scope: &Scope, visit: &mut dyn FnMut(Edge)) {
+    if let Some(parent) = scope.parent() {
+        visit(Edge::Scope(&parent));
+    }
+
+    for value in scope.values() {
+        value_edges(value, visit);
+    }
+}
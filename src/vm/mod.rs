@@ -1,150 +1,1128 @@
 mod operations;
 mod temp;
 pub mod types;
+// Formats values for the REPL, which test builds leave out
+#[cfg(not(test))]
 pub mod repl;
 pub mod scope;
+pub mod object;
+pub mod function;
+pub mod error;
+pub mod array;
+pub mod number;
+pub mod realm;
+pub mod heap;
+pub mod value;
+
+pub use self::value::JsValue;
+pub use self::value::Value;
 
 use self::scope::Scope;
+use self::scope::ScopeRef;
+use self::error::JsError;
+use self::error::Termination;
+use std::cell::RefCell;
+use self::object::ObjectKind;
+use self::object::ObjectRef;
+use self::object::PropertyDescriptor;
+use self::function::Function;
+use self::function::NativeFunction;
+use self::realm::Realm;
+use self::heap::GcStats;
+use self::heap::Limits;
+use self::heap::Marker;
 use super::bytecode;
+use super::bytecode::Block;
 use super::bytecode::Instruction;
+use std::mem;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+/// Calls nested deeper than this are assumed to be runaway recursion.
+const MAX_CALL_DEPTH: usize = 10000;
+
+/// Built-ins calling back into scripts nest on the native stack, so the
+/// native stack they may use is limited as well. This leaves room to spare
+/// on threads with 2MB of stack.
+const MAX_NATIVE_STACK: usize = 1024 * 1024;
+
+/// How many steps loops in built-ins take between checks for interrupts.
+const TICKS_PER_CHECK: u32 = 1024;
 
-#[derive(Debug, PartialEq, Clone)]
-pub enum JsValue {
-    JsNull,
-    JsUndefined,
-    JsNan,
-    JsNumber(f64),
-    JsString(String),
-    JsTrue,
-    JsFalse,
+/// A running function call, holding the state of its caller to restore
+/// once it returns.
+struct Frame {
+    code: Rc<Block>,
+    cp: usize,
+    sp: usize,
+    env: Option<ScopeRef>,
+    scope_depth: usize,
+    /// Height the stack is cut back to when the call returns
+    base: usize,
+    argc: usize,
+    function: ObjectRef,
+    this: JsValue,
+    /// Whether a built-in made the call, which gets the returned value
+    returns_to_host: bool,
+    /// Whether the call came from `new`, which gets the new object back
+    /// unless the function returns another one
+    constructs: bool,
+}
+
+/// Lets another thread stop a running script, which then ends with
+/// `Termination::Interrupted` at its next backward jump or call.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether an interrupt is pending, clearing it so the VM can run
+    /// scripts again afterwards.
+    fn take(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
 }
 
 pub struct VM<'a> {
     pub image: bytecode::Image,
     pub stack: Vec<JsValue>,
     pub scope: &'a mut Scope,
+    pub realm: Realm,
+    /// Index in `stack` of the first argument of the running function
     pub sp: usize,
-    pub cp: usize
+    pub cp: usize,
+    code: Rc<Block>,
+    blocks: Vec<Rc<Block>>,
+    frames: Vec<Frame>,
+    /// The innermost scope of the running code, `None` at the top level
+    env: Option<ScopeRef>,
+    /// Number of block scopes entered by the running code
+    scope_depth: usize,
+    /// The `this` value of the running code, `undefined` at the top level
+    this: JsValue,
+    scope_count: i32,
+    /// Position of the native stack when the script started
+    stack_base: usize,
+    /// Instructions left to run, `None` for no limit
+    fuel: Option<u64>,
+    interrupt: InterruptHandle,
+    /// Instructions left until the script is suspended
+    slice: Option<u64>,
+    /// Whether the script was suspended and can be resumed
+    suspended: bool,
+    /// Steps built-ins took since the interrupt was last checked
+    ticks: u32,
+}
+
+/// Where a script that may be suspended got to.
+#[derive(Debug, PartialEq)]
+pub enum Status {
+    /// The script ended with this value
+    Finished(JsValue),
+    /// The script ran the instructions it was given and waits to be resumed
+    Suspended,
 }
 
 impl<'a> VM<'a> {
     pub fn new(img: bytecode::Image, scope: &'a mut Scope) -> VM<'a> {
-        VM::<'a> { image: img, stack: Vec::new(), scope: scope, sp: 0, cp: 0 }
+        let code = Rc::new(Block::new());
+        let realm = scope.realm().cloned().unwrap_or_else(Realm::new);
+
+        VM::<'a> { image: img, stack: Vec::new(), scope, realm, sp: 0, cp: 0, code, blocks: Vec::new(), frames: Vec::new(), env: None, scope_depth: 0, this: JsValue::UNDEFINED, scope_count: 0, stack_base: 0, fuel: None, interrupt: InterruptHandle::default(), slice: None, suspended: false, ticks: 0 }
     }
 
     pub fn read_stack_end(&mut self) -> JsValue {
-        if self.stack.len() < 1 {
-            return JsValue::JsUndefined
+        if self.stack.is_empty() {
+            return JsValue::UNDEFINED
         }
 
-        return self.stack[self.stack.len() - 1].clone()
+        self.stack[self.stack.len() - 1].clone()
     }
 
     pub fn push_stack(&mut self, val: JsValue) {
         self.stack.push(val);
     }
 
-    pub fn pop_stack(&mut self) -> JsValue {
-        self.stack.pop().unwrap()
+    pub fn pop_stack(&mut self) -> Result<JsValue, JsError> {
+        if self.stack.len() <= self.stack_floor() {
+            return Err(JsError::internal_error("Stack underflow"))
+        }
+
+        Ok(self.stack.pop().unwrap())
     }
 
-    pub fn run (&mut self) {
-        let script = self.image.script.instructions.clone();
+    /// The running code may only pop what it pushed itself, so the callee
+    /// and arguments of a function are out of reach of its instructions.
+    fn stack_floor(&self) -> usize {
+        self.frames.last().map_or(0, |frame| self.sp + frame.argc)
+    }
 
-        for instruction in script {
-            match instruction {
-                Instruction::PUSHNUM(num) => {
-                    self.push_stack(JsValue::JsNumber(num))
-                },
-                Instruction::ADD => {
-                    let a: JsValue = self.pop_stack();
-                    let b: JsValue = self.pop_stack();
-                    self.push_stack(operations::add(&a, &b))
-                },
-                Instruction::SUB => {
-                    let a: JsValue = self.pop_stack();
-                    let b: JsValue = self.pop_stack();
-                    self.push_stack(operations::sub(&a, &b))
-                },
-                Instruction::MLP => {
-                    let a: JsValue = self.pop_stack();
-                    let b: JsValue = self.pop_stack();
-                    self.push_stack(operations::mlp(&a, &b))
-                },
-                Instruction::DIV => {
-                    let a: JsValue = self.pop_stack();
-                    let b: JsValue = self.pop_stack();
-                    self.push_stack(operations::div(&a, &b))
-                },
-                Instruction::EQ => {
-                    let a: JsValue = self.pop_stack();
-                    let b: JsValue = self.pop_stack();
-                    self.push_stack(operations::eq(&a, &b))
-                },
-                Instruction::NEQ => {
-                    let a: JsValue = self.pop_stack();
-                    let b: JsValue = self.pop_stack();
-                    self.push_stack(operations::neq(&a, &b))
-                },
-                Instruction::SEQ => {
-                    let a: JsValue = self.pop_stack();
-                    let b: JsValue = self.pop_stack();
-                    self.push_stack(operations::strict_eq(&a, &b))
-                },
-                Instruction::SNEQ => {
-                    let a: JsValue = self.pop_stack();
-                    let b: JsValue = self.pop_stack();
-                    self.push_stack(operations::strict_neq(&a, &b))
-                },
-                Instruction::PUSHSTRLIT(ref string) => {
-                    self.push_stack(JsValue::JsString(string.clone()))
+    fn new_scope(&mut self, parent: Option<ScopeRef>) -> ScopeRef {
+        self.scope_count += 1;
+        Rc::new(RefCell::new(Scope::new(self.scope_count, parent)))
+    }
+
+    fn has_own_var(&self, name: &str) -> bool {
+        match self.env {
+            Some(ref env) => env.borrow().has_var(name),
+            None => self.scope.has_var(name),
+        }
+    }
+
+    /// Whether a name resolves to a binding in any scope around the running
+    /// code.
+    fn is_declared(&self, name: &str) -> bool {
+        let mut env = self.env.clone();
+        while let Some(scope) = env {
+            if scope.borrow().has_var(name) {
+                return true
+            }
+            env = scope.borrow().parent();
+        }
+
+        self.scope.has_var(name)
+    }
+
+    /// Declares a variable in the innermost scope of the running code.
+    fn declare_var(&mut self, name: String, value: JsValue) {
+        self.realm.heap.scope_write_barrier(&value);
+        match self.env {
+            Some(ref env) => env.borrow_mut().set_var(name, value),
+            None => self.scope.set_var(name, value),
+        }
+    }
+
+    fn get_var(&self, name: &str) -> Result<JsValue, JsError> {
+        match self.lookup_var(name)? {
+            Some(value) => Ok(value),
+            None => Err(JsError::reference_error(format!("{} is not defined", name))),
+        }
+    }
+
+    /// Finds the value of a variable, `None` when no scope declares it.
+    fn lookup_var(&self, name: &str) -> Result<Option<JsValue>, JsError> {
+        let found = match self.env {
+            Some(ref env) => env.borrow().lookup(name),
+            None => Ok(None),
+        };
+
+        let found = match found {
+            Ok(None) => self.scope.lookup(name),
+            found => found,
+        };
+
+        found.map_err(JsError::from)
+    }
+
+    /// Assigns to the closest declaration of a variable. Names that are not
+    /// declared anywhere end up as globals, like they do in sloppy mode.
+    fn set_var(&mut self, name: String, value: JsValue) -> Result<(), JsError> {
+        self.realm.heap.scope_write_barrier(&value);
+        let unresolved = match self.env {
+            Some(ref env) => env.borrow_mut().assign(name.clone(), value),
+            None => Ok(Some(value)),
+        };
+
+        let unresolved = match unresolved {
+            Ok(Some(value)) => self.scope.assign(name.clone(), value),
+            unresolved => unresolved,
+        };
+
+        match unresolved {
+            Ok(Some(value)) => self.scope.set_var(name, value),
+            Ok(None) => {},
+            Err(why) => return Err(JsError::from(why)),
+        }
+
+        Ok(())
+    }
+
+    fn declare_lexical(&mut self, name: String, mutable: bool) {
+        match self.env {
+            Some(ref env) => env.borrow_mut().declare_lexical(name, mutable),
+            None => self.scope.declare_lexical(name, mutable),
+        }
+    }
+
+    fn initialize_binding(&mut self, name: String, value: JsValue) -> Result<(), JsError> {
+        self.realm.heap.scope_write_barrier(&value);
+        let initialized = match self.env {
+            Some(ref env) => env.borrow_mut().initialize(name, value),
+            None => self.scope.initialize(name, value),
+        };
+        initialized.map_err(JsError::from)
+    }
+
+    /// Calls the function below the `argc` arguments on top of the stack. A
+    /// method call has its receiver right below the function.
+    fn call(&mut self, argc: usize, is_method: bool) -> Result<(), JsError> {
+        let receiver = if is_method { 1 } else { 0 };
+        if self.stack.len() < self.stack_floor() + receiver + argc + 1 {
+            return Err(JsError::internal_error("Stack underflow"))
+        }
+        let callee_index = self.stack.len() - argc - 1;
+        let base = callee_index - receiver;
+
+        let function = match self.stack[callee_index].as_object() {
+            Some(function) if function.is_callable() => function,
+            _ => return Err(JsError::type_error(format!("{} is not a function", temp::js_value_to_string(&self.stack[callee_index])))),
+        };
+
+        let this = if is_method { self.stack[base].clone() } else { JsValue::UNDEFINED };
+
+        if let Some(native) = native_function(&function) {
+            let args = self.stack.split_off(callee_index + 1);
+            self.stack.truncate(base);
+
+            let result = native(self, this, &args)?;
+            self.push_stack(result);
+            return Ok(())
+        }
+
+        self.enter_function(function, this, base, argc, false, false)
+    }
+
+    /// Calls the function below the `argc` arguments on top of the stack as
+    /// a constructor, with a new object linked to its `prototype` as `this`.
+    fn construct(&mut self, argc: usize) -> Result<(), JsError> {
+        if self.stack.len() < self.stack_floor() + argc + 1 {
+            return Err(JsError::internal_error("Stack underflow"))
+        }
+        let callee_index = self.stack.len() - argc - 1;
+
+        let function = match self.stack[callee_index].as_object() {
+            Some(function) if function.is_callable() => function,
+            _ => return Err(JsError::type_error(format!("{} is not a constructor", temp::js_value_to_string(&self.stack[callee_index])))),
+        };
+
+        let prototype = match function.get("prototype").as_object() {
+            Some(prototype) => prototype,
+            None => self.realm.object_prototype.clone(),
+        };
+        let this = JsValue::object(self.realm.allocate(ObjectKind::Ordinary, Some(prototype)));
+
+        if let Some(native) = native_function(&function) {
+            let args = self.stack.split_off(callee_index + 1);
+            self.stack.truncate(callee_index);
+
+            let result = native(self, this.clone(), &args)?;
+            let result = if result.is_object() { result } else { this };
+            self.push_stack(result);
+            return Ok(())
+        }
+
+        self.enter_function(function, this, callee_index, argc, false, true)
+    }
+
+    /// Calls a function from a built-in, running it to completion.
+    pub fn call_function(&mut self, function: &JsValue, this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+        let function = match function.as_object() {
+            Some(object) if object.is_callable() => object,
+            _ => return Err(JsError::type_error(format!("{} is not a function", temp::js_value_to_string(function)))),
+        };
+
+        if let Some(native) = native_function(&function) {
+            return native(self, this, args)
+        }
+
+        if self.stack_base.abs_diff(stack_position()) > MAX_NATIVE_STACK {
+            return Err(JsError::range_error("Maximum call stack size exceeded".to_owned()))
+        }
+
+        let base = self.stack.len();
+        self.push_stack(JsValue::object(function.clone()));
+        self.stack.extend_from_slice(args);
+        if let Err(why) = self.enter_function(function, this, base, args.len(), true, false) {
+            self.stack.truncate(base);
+            return Err(why)
+        }
+
+        self.execute_call()?;
+        self.pop_stack()
+    }
+
+    /// Runs the function call that was just entered until it returns.
+    fn execute_call(&mut self) -> Result<(), JsError> {
+        let depth = self.frames.len();
+
+        while self.frames.len() >= depth {
+            let code = self.code.clone();
+            let error = match code.instructions.get(self.cp) {
+                Some(instruction) => {
+                    self.cp += 1;
+                    self.step(instruction).err()
                 },
-                Instruction::PUSHTRUE => {
-                    self.push_stack(JsValue::JsTrue)
+                None => Some(JsError::internal_error("Function ended without returning")),
+            };
+
+            if let Some(error) = error {
+                self.throw(error)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pushes a frame for a call to the compiled `function`, whose `argc`
+    /// arguments are on top of the stack.
+    fn enter_function(&mut self, function: ObjectRef, this: JsValue, base: usize, argc: usize, returns_to_host: bool, constructs: bool) -> Result<(), JsError> {
+        self.check_termination()?;
+        if self.frames.len() >= MAX_CALL_DEPTH {
+            return Err(JsError::range_error("Maximum call stack size exceeded".to_owned()))
+        }
+
+        let (code, scope) = match function.borrow().kind {
+            ObjectKind::Function(Function::Bytecode { ref code, ref scope }) => (code.clone(), scope.clone()),
+            _ => return Err(JsError::internal_error("Entered a function that is not compiled")),
+        };
+
+        let code = mem::replace(&mut self.code, code);
+        let env = self.new_scope(scope);
+
+        self.frames.push(Frame {
+            code,
+            cp: self.cp,
+            sp: self.sp,
+            env: self.env.replace(env),
+            scope_depth: self.scope_depth,
+            base,
+            argc,
+            function,
+            this: mem::replace(&mut self.this, this),
+            returns_to_host,
+            constructs,
+        });
+
+        self.sp = self.stack.len() - argc;
+        self.cp = 0;
+        self.scope_depth = 0;
+
+        Ok(())
+    }
+
+    fn ret(&mut self) -> Result<(), JsError> {
+        let mut value = self.pop_stack()?;
+        let constructs = match self.frames.last() {
+            Some(frame) => frame.constructs,
+            None => return Err(JsError::internal_error("Return outside of a function")),
+        };
+
+        if constructs && !value.is_object() {
+            value = self.this.clone();
+        }
+
+        self.leave_frame();
+        self.push_stack(value);
+        Ok(())
+    }
+
+    /// Drops the running function call and continues in its caller.
+    fn leave_frame(&mut self) {
+        let frame = self.frames.pop().unwrap();
+
+        // Drop the arguments and the callee itself
+        self.stack.truncate(frame.base);
+        self.code = frame.code;
+        self.cp = frame.cp;
+        self.sp = frame.sp;
+        self.env = frame.env;
+        self.scope_depth = frame.scope_depth;
+        self.this = frame.this;
+    }
+
+    /// Continues at the closest handler of the thrown `error`, leaving any
+    /// function calls in between. Fails with the error if nothing catches it,
+    /// which is always the case for terminations.
+    fn throw(&mut self, error: JsError) -> Result<(), JsError> {
+        let error = error.realize(&self.realm);
+        loop {
+            // `cp` is already past the instruction that threw, or the call
+            // that is being unwound
+            let at = self.cp - 1;
+            let handler = self.code.handlers.iter()
+                .find(|handler| handler.start <= at && at < handler.end && error.termination.is_none())
+                .cloned();
+
+            if let Some(handler) = handler {
+                let depth = self.stack_floor() + handler.stack_depth;
+                if self.stack.len() < depth || self.scope_depth < handler.scope_depth {
+                    return Err(JsError::internal_error("Handler is out of reach"))
                 }
-                Instruction::PUSHFALSE => {
-                    self.push_stack(JsValue::JsFalse)
-                },
-                Instruction::PUSHVAR(ref string) => {
-                    let a = self.pop_stack();
-                    self.scope.set_var(string.clone(), a);
-                },
-                Instruction::READIDENT(ref string) => {
-                    let a = self.scope.get_var(string.clone());
-                    self.push_stack(a);
-                },
-                Instruction::ASSIGNEQ(ref string) => {
-                    let a = self.pop_stack();
-                    self.scope.set_var(string.clone(), a);
-                },
-                Instruction::ASSIGNPLUSEQ(ref string) => {
-                    let a = self.scope.get_var(string.clone());
-                    let b = self.pop_stack();
-                    self.scope.set_var(string.clone(), operations::add(&a, &b));
-                    self.push_stack(operations::add(&a, &b));
-                },
-                Instruction::ASSIGNSUBEQ(ref string) => {
-                    let a = self.scope.get_var(string.clone());
-                    let b = self.pop_stack();
-                    self.scope.set_var(string.clone(), operations::sub(&a, &b));
-                    self.push_stack(operations::sub(&a, &b));
-                },
-                Instruction::ASSIGNMLPEQ(ref string) => {
-                    let a = self.scope.get_var(string.clone());
-                    let b = self.pop_stack();
-                    self.scope.set_var(string.clone(), operations::mlp(&a, &b));
-                    self.push_stack(operations::mlp(&a, &b));
-                },
-                Instruction::ASSIGNDIVEQ(ref string) => {
-                    let a = self.scope.get_var(string.clone());
-                    let b = self.pop_stack();
-                    self.scope.set_var(string.clone(), operations::div(&a, &b));
-                    self.push_stack(operations::div(&a, &b));
-                },
-                _ => panic!("Unknown instruction in bytecode"),
+
+                self.stack.truncate(depth);
+                while self.scope_depth > handler.scope_depth {
+                    self.pop_scope()?;
+                }
+
+                self.push_stack(error.value);
+                self.cp = handler.target;
+                return Ok(())
+            }
+
+            let returns_to_host = match self.frames.last() {
+                Some(frame) => frame.returns_to_host,
+                None => return Err(error),
             };
 
-            // println!("{:?} => {:?}", self.sp, self.stack);
+            // The built-in that made the call gets to handle the error
+            self.leave_frame();
+            if returns_to_host {
+                return Err(error)
+            }
+        }
+    }
+
+    fn make_arguments(&self) -> JsValue {
+        let argc = self.frames.last().map_or(0, |frame| frame.argc);
+        let arguments = self.realm.new_object();
+
+        for index in 0..argc {
+            arguments.set(index.to_string(), self.stack[self.sp + index].clone());
+        }
+        arguments.set("length".to_owned(), JsValue::number(argc as f64));
+
+        JsValue::object(arguments)
+    }
+
+    fn push_scope(&mut self) {
+        let parent = self.env.take();
+        self.env = Some(self.new_scope(parent));
+        self.scope_depth += 1;
+    }
+
+    fn pop_scope(&mut self) -> Result<(), JsError> {
+        let parent = match self.env {
+            Some(ref env) => env.borrow().parent(),
+            None => return Err(JsError::internal_error("No block scope to leave")),
+        };
+        self.env = parent;
+        self.scope_depth = self.scope_depth.saturating_sub(1);
+
+        Ok(())
+    }
+
+    fn copy_scope(&mut self) -> Result<(), JsError> {
+        self.scope_count += 1;
+        let copy = match self.env {
+            Some(ref env) => env.borrow().copy(self.scope_count),
+            None => return Err(JsError::internal_error("No block scope to copy")),
+        };
+        self.env = Some(Rc::new(RefCell::new(copy)));
+
+        Ok(())
+    }
+
+    /// Applies a binary operator to the left and right operand on top of
+    /// the stack.
+    fn binary(&mut self, operator: fn(&JsValue, &JsValue) -> JsValue) -> Result<(), JsError> {
+        let b = self.pop_stack()?;
+        let a = self.pop_stack()?;
+        self.push_stack(operator(&a, &b));
+        Ok(())
+    }
+
+    /// Like `binary`, for operators that may call back into scripts to
+    /// convert objects.
+    fn converting_binary(&mut self, operator: fn(&mut VM, &JsValue, &JsValue) -> Result<JsValue, JsError>) -> Result<(), JsError> {
+        let b = self.pop_stack()?;
+        let a = self.pop_stack()?;
+        let result = operator(self, &a, &b)?;
+        self.push_stack(result);
+        Ok(())
+    }
+
+    /// Like `binary`, for the numeric operators, which convert objects to
+    /// primitives first, the left operand before the right one.
+    fn numeric_binary(&mut self, operator: fn(&JsValue, &JsValue) -> JsValue) -> Result<(), JsError> {
+        let b = self.pop_stack()?;
+        let a = self.pop_stack()?;
+        let a = operations::to_primitive(self, &a)?;
+        let b = operations::to_primitive(self, &b)?;
+        self.push_stack(operator(&a, &b));
+        Ok(())
+    }
+
+    fn unary(&mut self, operator: fn(&JsValue) -> JsValue) -> Result<(), JsError> {
+        let a = self.pop_stack()?;
+        self.push_stack(operator(&a));
+        Ok(())
+    }
+
+    /// Like `unary`, for the numeric operators, which convert objects to
+    /// primitives first.
+    fn numeric_unary(&mut self, operator: fn(&JsValue) -> JsValue) -> Result<(), JsError> {
+        let a = self.pop_stack()?;
+        let a = operations::to_primitive(self, &a)?;
+        self.push_stack(operator(&a));
+        Ok(())
+    }
+
+    /// Applies a numeric operator to a variable and the value on top of the
+    /// stack, assigning and leaving the result.
+    fn assign_numeric(&mut self, name: &str, operator: fn(&JsValue, &JsValue) -> JsValue) -> Result<(), JsError> {
+        let a = self.get_var(name)?;
+        let b = self.pop_stack()?;
+        let a = operations::to_primitive(self, &a)?;
+        let b = operations::to_primitive(self, &b)?;
+        let result = operator(&a, &b);
+        self.set_var(name.to_owned(), result.clone())?;
+        self.push_stack(result);
+        Ok(())
+    }
+
+    /// Adds `delta` to a variable for `++` and `--`. The result is the new
+    /// value for the prefix forms and the old one, as a number, otherwise.
+    fn update_var(&mut self, name: &str, delta: f64, prefix: bool) -> Result<(), JsError> {
+        let old = self.get_var(name)?;
+        let old = types::js_value_to_number(&operations::to_primitive(self, &old)?);
+        self.set_var(name.to_owned(), JsValue::number(old + delta))?;
+        self.push_stack(JsValue::number(if prefix { old + delta } else { old }));
+        Ok(())
+    }
+
+    /// Like `update_var`, for the property whose object and key are on the
+    /// stack.
+    fn update_property(&mut self, delta: f64, prefix: bool) -> Result<(), JsError> {
+        let key = self.pop_stack()?;
+        let object = self.pop_stack()?;
+        let value = operations::get_property(self, &object, &key)?;
+        let old = types::js_value_to_number(&operations::to_primitive(self, &value)?);
+        operations::set_property(self, &object, &key, JsValue::number(old + delta))?;
+        self.push_stack(JsValue::number(if prefix { old + delta } else { old }));
+        Ok(())
+    }
+
+    /// Runs the image and returns its completion value, or the error that
+    /// was thrown out of it.
+    pub fn run(&mut self) -> Result<JsValue, JsError> {
+        self.enter_script();
+        self.continue_script(None)?;
+        Ok(self.read_stack_end())
+    }
+
+    fn enter_script(&mut self) {
+        // A script that is still suspended is abandoned
+        if self.suspended {
+            self.frames.clear();
+            self.stack.clear();
+            self.suspended = false;
+        }
+        self.code = Rc::new(self.image.script.clone());
+        self.blocks = self.image.blocks.iter().map(|block| Rc::new(block.clone())).collect();
+        self.cp = 0;
+        self.sp = 0;
+        self.env = None;
+        self.scope_depth = 0;
+        self.this = JsValue::UNDEFINED;
+    }
+
+    /// Runs the entered script from `cp` on, suspending it once it ran
+    /// `slice` instructions.
+    fn continue_script(&mut self, slice: Option<u64>) -> Result<Status, JsError> {
+        self.slice = slice;
+        self.suspended = false;
+        self.stack_base = stack_position();
+
+        match self.execute() {
+            Ok(true) => Ok(Status::Finished(self.read_stack_end())),
+            Ok(false) => {
+                self.suspended = true;
+                Ok(Status::Suspended)
+            },
+            Err(why) => {
+                // Leave the VM as if the script had never been entered
+                self.frames.clear();
+                self.stack.clear();
+                self.env = None;
+                self.sp = 0;
+                self.scope_depth = 0;
+                self.this = JsValue::UNDEFINED;
+                Err(why.realize(&self.realm))
+            },
+        }
+    }
+
+    /// Ends the script when it ran out of fuel or was interrupted. Only
+    /// loops and calls can keep a script running, so this is checked at
+    /// backward jumps, calls and the loops of built-ins rather than at
+    /// every instruction.
+    fn check_termination(&mut self) -> Result<(), JsError> {
+        if self.interrupt.take() {
+            return Err(JsError::terminated(Termination::Interrupted))
+        }
+        if self.fuel == Some(0) {
+            return Err(JsError::terminated(Termination::OutOfFuel))
+        }
+        Ok(())
+    }
+
+    /// Charges a step of a loop inside a built-in, such as joining or
+    /// sorting, as an instruction. The interrupt is only checked every
+    /// `TICKS_PER_CHECK` steps to keep those loops cheap.
+    pub fn tick(&mut self) -> Result<(), JsError> {
+        if let Some(ref mut fuel) = self.fuel {
+            *fuel = fuel.saturating_sub(1);
+        }
+        self.ticks += 1;
+        if self.ticks >= TICKS_PER_CHECK || self.fuel == Some(0) {
+            self.ticks = 0;
+            return self.check_termination()
+        }
+        Ok(())
+    }
+
+    /// Jumps to `target`, checking for termination when that loops back.
+    fn jump(&mut self, target: usize) -> Result<(), JsError> {
+        if target < self.cp {
+            self.check_termination()?;
+        }
+        self.cp = target;
+        Ok(())
+    }
+
+    /// Marks what the running code holds on to.
+    fn mark_roots(&self, marker: &mut Marker) {
+        for value in self.stack.iter() {
+            marker.value(value);
+        }
+        for frame in self.frames.iter() {
+            marker.object(&frame.function);
+            marker.value(&frame.this);
+            if let Some(ref env) = frame.env {
+                marker.scope(env);
+            }
+        }
+        if let Some(ref env) = self.env {
+            marker.scope(env);
+        }
+        marker.value(&self.this);
+        marker.scope_contents(self.scope);
+        marker.realm(&self.realm);
+    }
+
+    /// Runs until the script ends, giving whether it did, or until its
+    /// slice is used up.
+    fn execute(&mut self) -> Result<bool, JsError> {
+        while self.cp < self.code.instructions.len() {
+            if self.slice == Some(0) {
+                return Ok(false)
+            }
+
+            let code = self.code.clone();
+            let instruction = &code.instructions[self.cp];
+            self.cp += 1;
+
+            if let Err(error) = self.step(instruction) {
+                self.throw(error)?;
+            }
+        }
+
+        if !self.frames.is_empty() {
+            return Err(JsError::internal_error("Function ended without returning"))
+        }
+
+        Ok(true)
+    }
+
+    fn step(&mut self, instruction: &Instruction) -> Result<(), JsError> {
+        if let Some(ref mut fuel) = self.fuel {
+            *fuel = fuel.saturating_sub(1);
+        }
+        if let Some(ref mut slice) = self.slice {
+            *slice = slice.saturating_sub(1);
+        }
+        if self.realm.heap.needs_collection() {
+            self.realm.heap.collect_step(&|marker| self.mark_roots(marker));
+        }
+        if self.realm.heap.over_limit() {
+            self.realm.heap.check_limit(&|marker| self.mark_roots(marker))?;
+        }
+
+        match *instruction {
+            Instruction::PUSHNUM(num) => {
+                self.push_stack(JsValue::number(num))
+            },
+            Instruction::ADD => self.converting_binary(operations::add)?,
+            Instruction::SUB => self.numeric_binary(operations::sub)?,
+            Instruction::MLP => self.numeric_binary(operations::mlp)?,
+            Instruction::DIV => self.numeric_binary(operations::div)?,
+            Instruction::LT => self.converting_binary(operations::lt)?,
+            Instruction::LTE => self.converting_binary(operations::lte)?,
+            Instruction::GT => self.converting_binary(operations::gt)?,
+            Instruction::GTE => self.converting_binary(operations::gte)?,
+            Instruction::MOD => self.numeric_binary(operations::modulo)?,
+            Instruction::SHL => self.numeric_binary(operations::shl)?,
+            Instruction::SHR => self.numeric_binary(operations::shr)?,
+            Instruction::USHR => self.numeric_binary(operations::ushr)?,
+            Instruction::BITAND => self.numeric_binary(operations::bit_and)?,
+            Instruction::BITOR => self.numeric_binary(operations::bit_or)?,
+            Instruction::BITXOR => self.numeric_binary(operations::bit_xor)?,
+            Instruction::IN => {
+                let b: JsValue = self.pop_stack()?;
+                let a: JsValue = self.pop_stack()?;
+                self.push_stack(operations::has_property(&a, &b)?)
+            },
+            Instruction::INSTANCEOF => {
+                let b: JsValue = self.pop_stack()?;
+                let a: JsValue = self.pop_stack()?;
+                let result = operations::instance_of(self, &a, &b)?;
+                self.push_stack(result)
+            },
+            Instruction::EQ => self.converting_binary(operations::eq)?,
+            Instruction::NEQ => self.converting_binary(operations::neq)?,
+            Instruction::SEQ => self.binary(operations::strict_eq)?,
+            Instruction::SNEQ => self.binary(operations::strict_neq)?,
+            Instruction::PUSHSTRLIT(ref string) => {
+                self.push_stack(JsValue::string(string.clone()))
+            },
+            Instruction::PUSHTRUE => {
+                self.push_stack(JsValue::TRUE)
+            }
+            Instruction::PUSHFALSE => {
+                self.push_stack(JsValue::FALSE)
+            },
+            Instruction::PUSHNULL => {
+                self.push_stack(JsValue::NULL)
+            },
+            Instruction::PUSHVAR(ref string) => {
+                let a = self.pop_stack()?;
+                self.declare_var(string.clone(), a);
+            },
+            Instruction::DECLAREVAR(ref string) => {
+                if !self.has_own_var(string) {
+                    self.declare_var(string.clone(), JsValue::UNDEFINED);
+                }
+            },
+            Instruction::DECLARELET(ref string) => {
+                self.declare_lexical(string.clone(), true);
+            },
+            Instruction::DECLARECONST(ref string) => {
+                self.declare_lexical(string.clone(), false);
+            },
+            Instruction::INITBINDING(ref string) => {
+                let a = self.pop_stack()?;
+                self.initialize_binding(string.clone(), a)?;
+            },
+            Instruction::PUSHSCOPE => {
+                self.push_scope();
+            },
+            Instruction::POPSCOPE => {
+                self.pop_scope()?;
+            },
+            Instruction::COPYSCOPE => {
+                self.copy_scope()?;
+            },
+            Instruction::READIDENT(ref string) => {
+                let a = self.get_var(string)?;
+                self.push_stack(a);
+            },
+            Instruction::ASSIGNEQ(ref string) => {
+                let a = self.pop_stack()?;
+                self.set_var(string.clone(), a.clone())?;
+                self.push_stack(a);
+            },
+            Instruction::ASSIGNPLUSEQ(ref string) => {
+                let a = self.get_var(string)?;
+                let b = self.pop_stack()?;
+                let result = operations::add(self, &a, &b)?;
+                self.set_var(string.clone(), result.clone())?;
+                self.push_stack(result);
+            },
+            Instruction::ASSIGNSUBEQ(ref string) => self.assign_numeric(string, operations::sub)?,
+            Instruction::ASSIGNMLPEQ(ref string) => self.assign_numeric(string, operations::mlp)?,
+            Instruction::ASSIGNDIVEQ(ref string) => self.assign_numeric(string, operations::div)?,
+            Instruction::PREINC(ref name) => self.update_var(name, 1.0, true)?,
+            Instruction::PREDEC(ref name) => self.update_var(name, -1.0, true)?,
+            Instruction::POSTINC(ref name) => self.update_var(name, 1.0, false)?,
+            Instruction::POSTDEC(ref name) => self.update_var(name, -1.0, false)?,
+            Instruction::PREINCPROP => self.update_property(1.0, true)?,
+            Instruction::PREDECPROP => self.update_property(-1.0, true)?,
+            Instruction::POSTINCPROP => self.update_property(1.0, false)?,
+            Instruction::POSTDECPROP => self.update_property(-1.0, false)?,
+            Instruction::UNDEFINED => {
+                self.push_stack(JsValue::UNDEFINED)
+            },
+            Instruction::POP => {
+                self.pop_stack()?;
+            },
+            Instruction::JUMP(target) => {
+                self.jump(target)?;
+            },
+            Instruction::JUMPIFFALSE(target) => {
+                let a = self.pop_stack()?;
+                if !types::js_value_to_rust_boolean(&a) {
+                    self.jump(target)?;
+                }
+            },
+            Instruction::JUMPIFTRUE(target) => {
+                let a = self.pop_stack()?;
+                if types::js_value_to_rust_boolean(&a) {
+                    self.jump(target)?;
+                }
+            },
+            Instruction::MAKEFUNCTION(block) => {
+                let code = match self.blocks.get(block) {
+                    Some(code) => code.clone(),
+                    None => return Err(JsError::internal_error("Function refers to a missing block")),
+                };
+                let function = self.realm.new_function(code, self.env.clone());
+                self.push_stack(JsValue::object(function));
+            },
+            Instruction::CALL(argc) => {
+                self.call(argc, false)?;
+            },
+            Instruction::CALLMETHOD(argc) => {
+                self.call(argc, true)?;
+            },
+            Instruction::NEW(argc) => {
+                self.construct(argc)?;
+            },
+            Instruction::THIS => {
+                let this = self.this.clone();
+                self.push_stack(this);
+            },
+            Instruction::RETURN => {
+                self.ret()?;
+            },
+            Instruction::ARG(index) => {
+                let argc = self.frames.last().map_or(0, |frame| frame.argc);
+                let a = if index < argc { self.stack[self.sp + index].clone() } else { JsValue::UNDEFINED };
+                self.push_stack(a);
+            },
+            Instruction::ARGUMENTS => {
+                let a = self.make_arguments();
+                self.push_stack(a);
+            },
+            Instruction::CALLEE => {
+                let function = match self.frames.last() {
+                    Some(frame) => frame.function.clone(),
+                    None => return Err(JsError::internal_error("No function is running")),
+                };
+                self.push_stack(JsValue::object(function));
+            },
+            Instruction::GETPROP => {
+                let key = self.pop_stack()?;
+                let object = self.pop_stack()?;
+                let value = operations::get_property(self, &object, &key)?;
+                self.push_stack(value);
+            },
+            Instruction::NEWOBJECT => {
+                let object = self.realm.new_object();
+                self.push_stack(JsValue::object(object));
+            },
+            Instruction::NEWARRAY => {
+                let array = self.realm.new_array(Vec::new());
+                self.push_stack(JsValue::object(array));
+            },
+            Instruction::ARRAYPUSH | Instruction::ARRAYHOLE => {
+                let value = match *instruction {
+                    Instruction::ARRAYPUSH => Some(self.pop_stack()?),
+                    _ => None,
+                };
+
+                if let (Some(array), Some(ref value)) = (self.stack.last().and_then(JsValue::as_object), &value) {
+                    self.realm.heap.write_barrier(&array, value);
+                }
+
+                match self.stack.last().and_then(JsValue::as_object) {
+                    Some(array) => match array.borrow_mut().kind {
+                        ObjectKind::Array(ref mut elements) => {
+                            let index = elements.length;
+                            self.realm.heap.check_array_length(index as u64 + 1)?;
+                            match value {
+                                Some(value) => elements.set(index, value),
+                                None => elements.set_length(index + 1),
+                            }
+                        },
+                        _ => return Err(JsError::internal_error("Element added outside of an array literal")),
+                    },
+                    _ => return Err(JsError::internal_error("Element added outside of an array literal")),
+                }
+            },
+            Instruction::INITPROP => {
+                let value = self.pop_stack()?;
+                let key = self.pop_stack()?;
+                match self.stack.last().and_then(JsValue::as_object) {
+                    Some(object) => {
+                        self.realm.heap.write_barrier(&object, &value);
+                        object.set(temp::js_value_to_string(&key), value)
+                    },
+                    _ => return Err(JsError::internal_error("Property initialized outside of an object literal")),
+                }
+            },
+            Instruction::INITGETTER | Instruction::INITSETTER => {
+                let function = self.pop_stack()?;
+                let key = self.pop_stack()?;
+                if let Some(object) = self.stack.last().and_then(JsValue::as_object) {
+                    self.realm.heap.write_barrier(&object, &function);
+                }
+
+                // The other half of an accessor pair is kept
+                let mut descriptor = PropertyDescriptor { enumerable: Some(true), configurable: Some(true), ..PropertyDescriptor::default() };
+                match *instruction {
+                    Instruction::INITGETTER => descriptor.get = Some(function),
+                    _ => descriptor.set = Some(function),
+                }
+
+                match self.stack.last().and_then(JsValue::as_object) {
+                    Some(object) => object.define_own_property(temp::js_value_to_string(&key), descriptor)?,
+                    _ => return Err(JsError::internal_error("Property initialized outside of an object literal")),
+                };
+            },
+            Instruction::SETPROP => {
+                let value = self.pop_stack()?;
+                let key = self.pop_stack()?;
+                let object = self.pop_stack()?;
+                operations::set_property(self, &object, &key, value.clone())?;
+                self.push_stack(value);
+            },
+            Instruction::DUP2 => {
+                let b = self.pop_stack()?;
+                let a = self.pop_stack()?;
+                self.push_stack(a.clone());
+                self.push_stack(b.clone());
+                self.push_stack(a);
+                self.push_stack(b);
+            },
+            Instruction::NOT => self.unary(operations::not)?,
+            Instruction::NEG => self.numeric_unary(operations::neg)?,
+            Instruction::TONUMBER => self.numeric_unary(operations::to_number)?,
+            Instruction::BITNOT => self.numeric_unary(operations::bit_not)?,
+            Instruction::TYPEOF => self.unary(operations::type_of)?,
+            Instruction::TYPEOFIDENT(ref name) => {
+                let a = self.lookup_var(name)?.unwrap_or(JsValue::UNDEFINED);
+                self.push_stack(operations::type_of(&a));
+            },
+            Instruction::DELETEPROP => {
+                let key = self.pop_stack()?;
+                let object = self.pop_stack()?;
+                self.push_stack(operations::delete_property(&object, &key)?);
+            },
+            Instruction::DELETEIDENT(ref name) => {
+                // Bindings can't be deleted, so only names that resolve to
+                // nothing are deleted successfully
+                let deleted = !self.is_declared(name);
+                self.push_stack(JsValue::boolean(deleted));
+            },
+            Instruction::DUP => {
+                let a = self.pop_stack()?;
+                self.push_stack(a.clone());
+                self.push_stack(a);
+            },
+            Instruction::THROW => {
+                let value = self.pop_stack()?;
+                return Err(JsError::thrown(value));
+            },
+            Instruction::PUSHRESUME(target) => {
+                self.push_stack(JsValue::number(target as f64));
+            },
+            Instruction::RESUME => {
+                match self.pop_stack()?.as_number() {
+                    Some(target) => self.cp = target as usize,
+                    None => return Err(JsError::internal_error("Resumed at a value that is not an address")),
+                }
+            },
+            Instruction::POPBELOW(count) => {
+                let a = self.pop_stack()?;
+                for _ in 0..count {
+                    self.pop_stack()?;
+                }
+                self.push_stack(a);
+            },
+        };
+
+        Ok(())
+    }
+}
+
+/// What hosts use to tune the collector, limit scripts and run them in
+/// slices.
+impl<'a> VM<'a> {
+    /// Frees the objects nothing can reach anymore, cycles included, and
+    /// returns how many there were. Collections also run on their own as
+    /// scripts allocate objects.
+    pub fn collect_garbage(&mut self) -> usize {
+        self.realm.heap.collect(&|marker| self.mark_roots(marker))
+    }
+
+    /// How many objects of the realm are alive.
+    pub fn live_objects(&self) -> usize {
+        self.realm.heap.live_objects()
+    }
+
+    /// Makes major collections mark at most `budget` objects and scopes
+    /// between two instructions, bounding their pauses, or everything at
+    /// once with `None`.
+    pub fn set_gc_budget(&mut self, budget: Option<usize>) {
+        self.realm.heap.set_budget(budget);
+    }
+
+    /// Collects everything after every instruction that allocated, which
+    /// is slow but finds objects the collector should have kept.
+    pub fn set_gc_stress(&mut self, stress: bool) {
+        self.realm.heap.set_stress(stress);
+    }
+
+    pub fn gc_stats(&self) -> GcStats {
+        self.realm.heap.stats()
+    }
+
+    /// Caps the memory scripts may use, see `Limits`.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.realm.heap.set_limits(limits);
+    }
+
+    /// Limits how many more instructions scripts may run, `None` lifts the
+    /// limit. Scripts that use them up end with `Termination::OutOfFuel`.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// How many instructions scripts may still run.
+    #[cfg(test)]
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// A handle to interrupt scripts run by this VM from another thread.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Starts the script like `run`, but suspends it after `instructions`
+    /// instructions for `resume` to continue. Built-ins calling back into
+    /// the script can't be suspended halfway, so those run to completion
+    /// first.
+    pub fn run_for(&mut self, instructions: u64) -> Result<Status, JsError> {
+        self.enter_script();
+        self.continue_script(Some(instructions))
+    }
+
+    /// Continues a suspended script where it left off, suspending it again
+    /// after `instructions` instructions unless that is `None`.
+    pub fn resume(&mut self, instructions: Option<u64>) -> Result<Status, JsError> {
+        if !self.suspended {
+            return Err(JsError::internal_error("No suspended script to resume"))
         }
+        self.continue_script(instructions)
     }
 }
+
+fn native_function(function: &ObjectRef) -> Option<NativeFunction> {
+    match function.borrow().kind {
+        ObjectKind::Function(Function::Native(native)) => Some(native),
+        _ => None,
+    }
+}
+
+/// An address on the native stack, to measure how much of it is in use.
+#[inline(never)]
+fn stack_position() -> usize {
+    let marker = 0u8;
+    &marker as *const u8 as usize
+}
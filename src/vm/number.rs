@@ -0,0 +1,324 @@
+use super::JsValue;
+use super::Value;
+use super::VM;
+use super::error::JsError;
+use super::function::NativeFunction;
+use super::function::argument;
+use super::object::ObjectRef;
+use super::realm::Realm;
+use super::types::js_value_to_number;
+
+const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+pub fn install(realm: &Realm) -> ObjectRef {
+    let prototype = &realm.number_prototype;
+    let methods: Vec<(&str, NativeFunction)> = vec![
+        ("toString", to_string),
+        ("toFixed", to_fixed),
+        ("toPrecision", to_precision),
+        ("toExponential", to_exponential),
+    ];
+    for (name, method) in methods {
+        prototype.set_hidden(name.to_owned(), JsValue::object(realm.new_native_function(method)));
+    }
+
+    let constructor = realm.new_native_function(number_constructor);
+    constructor.set_constant("prototype".to_owned(), JsValue::object(prototype.clone()));
+    prototype.set_hidden("constructor".to_owned(), JsValue::object(constructor.clone()));
+
+    constructor
+}
+
+/// The Number::toString conversion, printing the shortest digits that read
+/// back as the same number and switching to exponent notation outside of
+/// 1e-7 to 1e21.
+pub fn number_to_string(number: f64) -> String {
+    if number.is_nan() {
+        return "NaN".to_owned()
+    }
+    if number == 0.0 {
+        return "0".to_owned()
+    }
+    if number.is_infinite() {
+        return if number < 0.0 { "-Infinity" } else { "Infinity" }.to_owned()
+    }
+    if number < 0.0 {
+        return format!("-{}", number_to_string(-number))
+    }
+
+    let (digits, exponent) = shortest_digits(number);
+    let k = digits.len() as i32;
+    // The position of the decimal point, counted from the first digit
+    let n = exponent + 1;
+
+    if k <= n && n <= 21 {
+        format!("{}{}", digits, "0".repeat((n - k) as usize))
+    } else if 0 < n && n <= 21 {
+        format!("{}.{}", &digits[..n as usize], &digits[n as usize..])
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{}", "0".repeat(-n as usize), digits)
+    } else {
+        exponential(&digits, exponent)
+    }
+}
+
+/// The shortest digits that round-trip, along with the exponent of the
+/// first one.
+fn shortest_digits(number: f64) -> (String, i32) {
+    let formatted = format!("{:e}", number);
+    let (mantissa, exponent) = formatted.split_at(formatted.find('e').unwrap());
+    (mantissa.replace('.', ""), exponent[1..].parse().unwrap())
+}
+
+/// All decimal digits of a finite, non-negative number, which a double has
+/// at most 767 of, along with the exponent of the first one.
+fn exact_digits(number: f64) -> (Vec<u8>, i32) {
+    let formatted = format!("{:.800e}", number);
+    let (mantissa, exponent) = formatted.split_at(formatted.find('e').unwrap());
+    let digits = mantissa.bytes().filter(|c| *c != b'.').map(|c| c - b'0').collect();
+    (digits, exponent[1..].parse().unwrap())
+}
+
+/// Keeps the first `count` digits, rounding half up like the spec asks for
+/// when two results are equally close. The result has one more digit when
+/// the rounding carries all the way.
+fn round_digits(digits: &[u8], count: usize) -> Vec<u8> {
+    let mut kept: Vec<u8> = (0..count).map(|index| digits.get(index).cloned().unwrap_or(0)).collect();
+    if digits.get(count).is_none_or(|digit| *digit < 5) {
+        return kept
+    }
+
+    for digit in kept.iter_mut().rev() {
+        if *digit == 9 {
+            *digit = 0;
+        } else {
+            *digit += 1;
+            return kept
+        }
+    }
+    kept.insert(0, 1);
+    kept
+}
+
+fn digits_to_string(digits: &[u8]) -> String {
+    digits.iter().map(|digit| DIGITS[*digit as usize] as char).collect()
+}
+
+/// Writes `d.ddde+x` for digits whose first one is at 10^exponent.
+fn exponential(digits: &str, exponent: i32) -> String {
+    let sign = if exponent < 0 { '-' } else { '+' };
+    if digits.len() == 1 {
+        format!("{}e{}{}", digits, sign, exponent.abs())
+    } else {
+        format!("{}.{}e{}{}", &digits[..1], &digits[1..], sign, exponent.abs())
+    }
+}
+
+/// The ToIntegerOrInfinity conversion for the digit arguments.
+fn to_integer(value: &JsValue) -> f64 {
+    let number = js_value_to_number(value);
+    if number.is_nan() { 0.0 } else { number.trunc() }
+}
+
+fn this_number(this: &JsValue, method: &str) -> Result<f64, JsError> {
+    match this.view() {
+        Value::Number(number) => Ok(number),
+        _ => Err(JsError::type_error(format!("Number.prototype.{} requires that 'this' be a Number", method))),
+    }
+}
+
+fn number_constructor(_vm: &mut VM, _this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let number = if args.is_empty() { 0.0 } else { js_value_to_number(&args[0]) };
+    Ok(JsValue::number(number))
+}
+
+fn to_string(_vm: &mut VM, this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let number = this_number(&this, "toString")?;
+    let radix = argument(args, 0);
+    let radix = if radix.is_undefined() { 10.0 } else { to_integer(&radix) };
+
+    if !(2.0..=36.0).contains(&radix) {
+        return Err(JsError::range_error("toString() radix must be between 2 and 36".to_owned()))
+    }
+    if radix == 10.0 || !number.is_finite() {
+        return Ok(JsValue::string(number_to_string(number)))
+    }
+
+    Ok(JsValue::string(radix_to_string(number, radix as u32)))
+}
+
+/// Prints a finite number in another radix, with just enough fraction
+/// digits to tell it apart from its neighbouring doubles.
+fn radix_to_string(number: f64, radix: u32) -> String {
+    let negative = number < 0.0;
+    let number = number.abs();
+    let radix_f = radix as f64;
+
+    let mut integer = number.trunc();
+    let mut fraction = number - integer;
+    let mut fraction_digits: Vec<u8> = Vec::new();
+
+    // Half the distance to the next double, below which digits say nothing
+    let mut delta = (0.5 * (number.next_up() - number)).max(0.0f64.next_up());
+    if fraction >= delta {
+        loop {
+            fraction *= radix_f;
+            delta *= radix_f;
+            let digit = fraction.trunc() as u8;
+            fraction_digits.push(digit);
+            fraction -= digit as f64;
+
+            if (fraction > 0.5 || (fraction == 0.5 && digit & 1 == 1)) && fraction + delta > 1.0 {
+                // Round up, which may carry into the integer part
+                loop {
+                    match fraction_digits.pop() {
+                        None => {
+                            integer += 1.0;
+                            break
+                        },
+                        Some(last) if (last as u32) + 1 < radix => {
+                            fraction_digits.push(last + 1);
+                            break
+                        },
+                        Some(_) => {},
+                    }
+                }
+                break
+            }
+
+            if fraction < delta {
+                break
+            }
+        }
+    }
+
+    let mut integer_digits: Vec<u8> = Vec::new();
+    while integer >= 1.0 {
+        let remainder = integer % radix_f;
+        integer_digits.push(remainder as u8);
+        integer = (integer - remainder) / radix_f;
+    }
+    if integer_digits.is_empty() {
+        integer_digits.push(0);
+    }
+    integer_digits.reverse();
+
+    let mut result = String::new();
+    if negative {
+        result.push('-');
+    }
+    result.push_str(&digits_to_string(&integer_digits));
+    if !fraction_digits.is_empty() {
+        result.push('.');
+        result.push_str(&digits_to_string(&fraction_digits));
+    }
+    result
+}
+
+fn to_fixed(_vm: &mut VM, this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let number = this_number(&this, "toFixed")?;
+    let fraction_digits = to_integer(&argument(args, 0));
+
+    if !(0.0..=100.0).contains(&fraction_digits) {
+        return Err(JsError::range_error("toFixed() digits argument must be between 0 and 100".to_owned()))
+    }
+    if !number.is_finite() || number.abs() >= 1e21 {
+        return Ok(JsValue::string(number_to_string(number)))
+    }
+
+    let fraction_digits = fraction_digits as usize;
+    let sign = if number < 0.0 { "-" } else { "" };
+
+    // The digits of the magnitude times 10^fraction_digits, rounded to an
+    // integer
+    let (digits, exponent) = exact_digits(number.abs());
+    let count = exponent + 1 + fraction_digits as i32;
+    let mut integer = if count < 0 { vec![0] } else { round_digits(&digits, count as usize) };
+    if integer.is_empty() {
+        integer.push(0);
+    }
+
+    let mut integer = digits_to_string(&integer);
+    if fraction_digits == 0 {
+        return Ok(JsValue::string(format!("{}{}", sign, integer)))
+    }
+    if integer.len() <= fraction_digits {
+        integer = format!("{}{}", "0".repeat(fraction_digits + 1 - integer.len()), integer);
+    }
+
+    let point = integer.len() - fraction_digits;
+    Ok(JsValue::string(format!("{}{}.{}", sign, &integer[..point], &integer[point..])))
+}
+
+/// Rounds a finite, non-negative number to `count` significant digits,
+/// along with the exponent of the first one.
+fn significant_digits(number: f64, count: usize) -> (String, i32) {
+    if number == 0.0 {
+        return ("0".repeat(count), 0)
+    }
+
+    let (digits, exponent) = exact_digits(number);
+    let mut rounded = round_digits(&digits, count);
+    if rounded.len() > count {
+        rounded.truncate(count);
+        return (digits_to_string(&rounded), exponent + 1)
+    }
+    (digits_to_string(&rounded), exponent)
+}
+
+fn to_exponential(_vm: &mut VM, this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let number = this_number(&this, "toExponential")?;
+    let fraction_digits = argument(args, 0);
+    let count = to_integer(&fraction_digits);
+
+    if !number.is_finite() {
+        return Ok(JsValue::string(number_to_string(number)))
+    }
+    if !(0.0..=100.0).contains(&count) {
+        return Err(JsError::range_error("toExponential() argument must be between 0 and 100".to_owned()))
+    }
+
+    let sign = if number < 0.0 { "-" } else { "" };
+    let (digits, exponent) = if !fraction_digits.is_undefined() {
+        significant_digits(number.abs(), count as usize + 1)
+    } else if number == 0.0 {
+        ("0".to_owned(), 0)
+    } else {
+        shortest_digits(number.abs())
+    };
+
+    Ok(JsValue::string(format!("{}{}", sign, exponential(&digits, exponent))))
+}
+
+fn to_precision(_vm: &mut VM, this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let number = this_number(&this, "toPrecision")?;
+    let precision = argument(args, 0);
+    if precision == JsValue::UNDEFINED {
+        return Ok(JsValue::string(number_to_string(number)))
+    }
+
+    let precision = to_integer(&precision);
+    if !number.is_finite() {
+        return Ok(JsValue::string(number_to_string(number)))
+    }
+    if !(1.0..=100.0).contains(&precision) {
+        return Err(JsError::range_error("toPrecision() argument must be between 1 and 100".to_owned()))
+    }
+
+    let precision = precision as i32;
+    let sign = if number < 0.0 { "-" } else { "" };
+    let (digits, exponent) = significant_digits(number.abs(), precision as usize);
+
+    let result = if exponent < -6 || exponent >= precision {
+        exponential(&digits, exponent)
+    } else if exponent == precision - 1 {
+        digits
+    } else if exponent >= 0 {
+        let point = exponent as usize + 1;
+        format!("{}.{}", &digits[..point], &digits[point..])
+    } else {
+        format!("0.{}{}", "0".repeat((-exponent - 1) as usize), digits)
+    };
+
+    Ok(JsValue::string(format!("{}{}", sign, result)))
+}
@@ -0,0 +1,846 @@
+use super::JsValue;
+use super::Value;
+use super::VM;
+use super::array::JsArray;
+use super::array::array_index;
+use super::error::JsError;
+use super::function::Function;
+use super::function::NativeFunction;
+use super::function::argument;
+use super::heap::OBJECT_BYTES;
+use super::heap::PROPERTY_BYTES;
+use super::heap::note_allocation;
+use super::realm::Realm;
+use super::array::to_array_length;
+use super::operations::get_property;
+use super::temp::js_value_to_string;
+use super::types::js_value_to_rust_boolean;
+use super::types::same_value;
+use std::cell::Ref;
+use std::cell::RefCell;
+use std::cell::RefMut;
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+use std::rc::Weak;
+
+/// What kind of object this is, along with the internal state that comes
+/// with it.
+pub enum ObjectKind {
+    Ordinary,
+    Array(JsArray),
+    Function(Function),
+}
+
+/// An own property of an object.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Property {
+    pub slot: Slot,
+    pub enumerable: bool,
+    pub configurable: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Slot {
+    Data { value: JsValue, writable: bool },
+    /// Computed by calling `get` and `set`, either of which may be
+    /// `undefined`
+    Accessor { get: JsValue, set: JsValue },
+}
+
+impl Property {
+    /// A property like the ones assignments create.
+    pub fn data(value: JsValue) -> Property {
+        Property { slot: Slot::Data { value, writable: true }, enumerable: true, configurable: true }
+    }
+}
+
+/// The attributes given to `Object.defineProperty`. Missing ones keep their
+/// current value, or get a default when the property is new.
+#[derive(Default)]
+pub struct PropertyDescriptor {
+    pub value: Option<JsValue>,
+    pub writable: Option<bool>,
+    pub get: Option<JsValue>,
+    pub set: Option<JsValue>,
+    pub enumerable: Option<bool>,
+    pub configurable: Option<bool>,
+}
+
+impl PropertyDescriptor {
+    pub fn is_accessor(&self) -> bool {
+        self.get.is_some() || self.set.is_some()
+    }
+
+    pub fn is_data(&self) -> bool {
+        self.value.is_some() || self.writable.is_some()
+    }
+}
+
+pub struct JsObject {
+    pub kind: ObjectKind,
+    pub prototype: Option<ObjectRef>,
+    pub properties: HashMap<String, Property>,
+    /// Whether new properties can be added
+    pub extensible: bool,
+}
+
+impl JsObject {
+    /// Adds or replaces an own property, noting the memory a new one takes.
+    fn insert_property(&mut self, key: String, property: Property) {
+        let bytes = PROPERTY_BYTES + key.len();
+        if self.properties.insert(key, property).is_none() {
+            note_allocation(bytes);
+        }
+    }
+}
+
+/// A shared reference to a heap allocated object. Objects are compared by
+/// identity, like they are in javascript.
+#[derive(Clone)]
+pub struct ObjectRef(Rc<RefCell<JsObject>>);
+
+/// A reference that does not keep its object alive, which is how the heap
+/// keeps track of objects.
+pub struct WeakObjectRef(Weak<RefCell<JsObject>>);
+
+impl WeakObjectRef {
+    pub fn upgrade(&self) -> Option<ObjectRef> {
+        self.0.upgrade().map(ObjectRef)
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.0.strong_count() > 0
+    }
+}
+
+impl ObjectRef {
+    pub fn with_kind(kind: ObjectKind, prototype: Option<ObjectRef>) -> ObjectRef {
+        note_allocation(OBJECT_BYTES);
+        ObjectRef(Rc::new(RefCell::new(JsObject { kind, prototype, properties: HashMap::new(), extensible: true })))
+    }
+
+    /// Gives up the reference for a pointer that `from_raw` turns back into
+    /// one, which is how values hold objects.
+    pub fn into_raw(self) -> *const RefCell<JsObject> {
+        Rc::into_raw(self.0)
+    }
+
+    /// # Safety
+    ///
+    /// The pointer must come from `into_raw`, and the reference it stood
+    /// for is taken over.
+    pub unsafe fn from_raw(pointer: *const RefCell<JsObject>) -> ObjectRef {
+        ObjectRef(Rc::from_raw(pointer))
+    }
+
+    /// Moves what the object holds into a new object, leaving this one
+    /// empty, which is how the heap copies objects out of the nursery.
+    pub fn relocate(&self) -> ObjectRef {
+        let empty = JsObject { kind: ObjectKind::Ordinary, prototype: None, properties: HashMap::new(), extensible: true };
+        ObjectRef(Rc::new(RefCell::new(mem::replace(&mut *self.borrow_mut(), empty))))
+    }
+
+    /// Whether the object can be changed right now, as it is not borrowed.
+    pub fn is_free(&self) -> bool {
+        self.0.try_borrow_mut().is_ok()
+    }
+
+    pub fn downgrade(&self) -> WeakObjectRef {
+        WeakObjectRef(Rc::downgrade(&self.0))
+    }
+
+    /// The address of the object, which identifies it.
+    pub fn as_ptr(&self) -> *const RefCell<JsObject> {
+        Rc::as_ptr(&self.0)
+    }
+
+    /// How many references to the object exist.
+    pub fn reference_count(&self) -> usize {
+        Rc::strong_count(&self.0)
+    }
+
+    pub fn borrow(&self) -> Ref<'_, JsObject> {
+        self.0.borrow()
+    }
+
+    pub fn borrow_mut(&self) -> RefMut<'_, JsObject> {
+        self.0.borrow_mut()
+    }
+
+    pub fn is_array(&self) -> bool {
+        matches!(self.borrow().kind, ObjectKind::Array(_))
+    }
+
+    pub fn is_callable(&self) -> bool {
+        matches!(self.borrow().kind, ObjectKind::Function(_))
+    }
+
+    /// Looks up a property on the object itself, without its prototypes.
+    /// Array elements without attributes of their own share those of their
+    /// array.
+    pub fn get_own_property(&self, key: &str) -> Option<Property> {
+        let object = self.borrow();
+
+        if let ObjectKind::Array(ref array) = object.kind {
+            if key == "length" {
+                let slot = Slot::Data { value: JsValue::number(array.length as f64), writable: array.length_writable };
+                return Some(Property { slot, enumerable: false, configurable: false })
+            }
+            if let Some(index) = array_index(key) {
+                if let Some(value) = array.elements.get(&index) {
+                    let slot = Slot::Data { value: value.clone(), writable: array.writable };
+                    return Some(Property { slot, enumerable: true, configurable: array.configurable })
+                }
+                return array.attributed.get(&index).cloned()
+            }
+        }
+
+        object.properties.get(key).cloned()
+    }
+
+    /// Looks up a property on the object and then along its prototype chain.
+    pub fn find_property(&self, key: &str) -> Option<Property> {
+        let mut object = self.clone();
+
+        loop {
+            if let Some(property) = object.get_own_property(key) {
+                return Some(property)
+            }
+
+            let prototype = object.borrow().prototype.clone();
+            match prototype {
+                Some(prototype) => object = prototype,
+                None => return None,
+            }
+        }
+    }
+
+    /// Reads a property without running getters, which come out as
+    /// `undefined`. Scripts read properties through `get_property`.
+    pub fn get(&self, key: &str) -> JsValue {
+        match self.find_property(key) {
+            Some(Property { slot: Slot::Data { value, .. }, .. }) => value,
+            _ => JsValue::UNDEFINED,
+        }
+    }
+
+    /// Creates or replaces an own data property, ignoring attributes. Array
+    /// indices and `length` go to the array elements; `length` is expected
+    /// to be a valid array length already.
+    pub fn set(&self, key: String, value: JsValue) {
+        self.define(key, Property::data(value));
+    }
+
+    /// Creates or replaces an own data property that is left out of
+    /// enumeration, like the methods of built-ins.
+    pub fn set_hidden(&self, key: String, value: JsValue) {
+        let mut property = Property::data(value);
+        property.enumerable = false;
+        self.define(key, property);
+    }
+
+    /// Creates or replaces an own data property that can't be written,
+    /// deleted or enumerated, like the `prototype` of built-in
+    /// constructors.
+    pub fn set_constant(&self, key: String, value: JsValue) {
+        let property = Property { slot: Slot::Data { value, writable: false }, enumerable: false, configurable: false };
+        self.define(key, property);
+    }
+
+    fn define(&self, key: String, property: Property) {
+        let mut object = self.borrow_mut();
+
+        if let ObjectKind::Array(ref mut array) = object.kind {
+            if let Slot::Data { ref value, .. } = property.slot {
+                if key == "length" {
+                    if let Some(length) = value.as_number() {
+                        array.set_length(length as u32);
+                    }
+                    return
+                }
+                if let Some(index) = array_index(&key) {
+                    array.set(index, value.clone());
+                    return
+                }
+            }
+        }
+
+        object.insert_property(key, property);
+    }
+
+    /// Assigns to an own data property, adding it when it does not exist yet.
+    /// Returns false if the attributes of the object forbid it.
+    pub fn write(&self, key: String, value: JsValue) -> bool {
+        let mut object = self.borrow_mut();
+        let extensible = object.extensible;
+
+        if let ObjectKind::Array(ref mut array) = object.kind {
+            if key == "length" {
+                if !array.length_writable {
+                    return false
+                }
+                if let Some(length) = value.as_number() {
+                    array.truncate(length as u32);
+                }
+                return true
+            }
+            if let Some(index) = array_index(&key) {
+                if let Some(property) = array.attributed.get_mut(&index) {
+                    return match property.slot {
+                        Slot::Data { value: ref mut current, writable: true } => {
+                            *current = value;
+                            true
+                        },
+                        _ => false,
+                    }
+                }
+
+                let exists = array.elements.contains_key(&index);
+                if (exists && !array.writable) || (!exists && !extensible) || (index >= array.length && !array.length_writable) {
+                    return false
+                }
+
+                array.set(index, value);
+                return true
+            }
+        }
+
+        match object.properties.get_mut(&key) {
+            Some(&mut Property { slot: Slot::Data { value: ref mut current, writable: true }, .. }) => {
+                *current = value;
+                return true
+            },
+            Some(_) => return false,
+            None => {},
+        }
+
+        if !extensible {
+            return false
+        }
+        object.insert_property(key, Property::data(value));
+        true
+    }
+
+    /// Changes or creates an own property the way `Object.defineProperty`
+    /// does. Returns false when the current attributes forbid the change.
+    pub fn define_own_property(&self, key: String, mut descriptor: PropertyDescriptor) -> Result<bool, JsError> {
+        let is_array = self.is_array();
+        if is_array && key == "length" {
+            if let Some(ref value) = descriptor.value {
+                let length = to_array_length(value)?;
+                descriptor.value = Some(JsValue::number(length as f64));
+            }
+        }
+
+        let current = self.get_own_property(&key);
+        let extensible = self.borrow().extensible;
+        let property = match apply_descriptor(current, &descriptor, extensible) {
+            Some(property) => property,
+            None => return Ok(false),
+        };
+
+        if is_array {
+            if let Some(defined) = self.define_array_property(&key, property.clone()) {
+                return Ok(defined)
+            }
+        }
+
+        self.borrow_mut().insert_property(key, property);
+        Ok(true)
+    }
+
+    /// Defines `length` or an element of an array. Elements that don't take
+    /// the attributes all elements of the array share keep their own.
+    fn define_array_property(&self, key: &str, property: Property) -> Option<bool> {
+        let mut object = self.borrow_mut();
+        let array = match object.kind {
+            ObjectKind::Array(ref mut array) => array,
+            _ => return None,
+        };
+
+        if key == "length" {
+            if let Slot::Data { ref value, writable } = property.slot {
+                if let Some(length) = value.as_number() {
+                    array.truncate(length as u32);
+                }
+                array.length_writable = writable;
+                return Some(true)
+            }
+        }
+
+        let index = array_index(key)?;
+        if index >= array.length && !array.length_writable {
+            return Some(false)
+        }
+
+        let shared = property.enumerable && property.configurable == array.configurable;
+        match property.slot {
+            Slot::Data { value, writable } if shared && writable == array.writable => array.set(index, value),
+            _ => array.set_attributed(index, property),
+        }
+        Some(true)
+    }
+
+    /// Removes an own property unless it is non-configurable, returning
+    /// whether it is gone.
+    pub fn delete(&self, key: &str) -> bool {
+        let mut object = self.borrow_mut();
+
+        if let ObjectKind::Array(ref mut array) = object.kind {
+            if key == "length" {
+                return false
+            }
+            if let Some(index) = array_index(key) {
+                if let Some(property) = array.attributed.get(&index) {
+                    if !property.configurable {
+                        return false
+                    }
+                    array.attributed.remove(&index);
+                    return true
+                }
+                if array.configurable {
+                    array.elements.remove(&index);
+                }
+                return array.configurable || array.get(index).is_none()
+            }
+        }
+
+        match object.properties.get(key) {
+            Some(property) if !property.configurable => false,
+            _ => {
+                object.properties.remove(key);
+                true
+            },
+        }
+    }
+
+    pub fn is_extensible(&self) -> bool {
+        self.borrow().extensible
+    }
+
+    pub fn prevent_extensions(&self) {
+        self.borrow_mut().extensible = false;
+    }
+
+    /// Makes every own property non-configurable, and with `frozen` also
+    /// read-only, after which no properties can be added.
+    pub fn set_integrity(&self, frozen: bool) {
+        let mut object = self.borrow_mut();
+        object.extensible = false;
+
+        let attributed = match object.kind {
+            ObjectKind::Array(ref mut array) => {
+                array.configurable = false;
+                if frozen {
+                    array.writable = false;
+                    array.length_writable = false;
+                }
+                array.attributed.values_mut().collect()
+            },
+            _ => Vec::new(),
+        };
+        for property in attributed {
+            lock(property, frozen);
+        }
+
+        for property in object.properties.values_mut() {
+            lock(property, frozen);
+        }
+    }
+
+    /// Whether the object is sealed, or frozen with `frozen`.
+    pub fn test_integrity(&self, frozen: bool) -> bool {
+        let object = self.borrow();
+        if object.extensible {
+            return false
+        }
+
+        if let ObjectKind::Array(ref array) = object.kind {
+            let has_elements = !array.elements.is_empty();
+            if (has_elements && array.configurable) || (frozen && ((has_elements && array.writable) || array.length_writable)) {
+                return false
+            }
+            if !array.attributed.values().all(|property| is_locked(property, frozen)) {
+                return false
+            }
+        }
+
+        object.properties.values().all(|property| is_locked(property, frozen))
+    }
+}
+
+/// Makes a property non-configurable, and with `frozen` also read-only.
+fn lock(property: &mut Property, frozen: bool) {
+    property.configurable = false;
+    if let Slot::Data { ref mut writable, .. } = property.slot {
+        *writable = *writable && !frozen;
+    }
+}
+
+/// Whether `lock` was applied to a property.
+fn is_locked(property: &Property, frozen: bool) -> bool {
+    let writable = match property.slot {
+        Slot::Data { writable, .. } => writable,
+        Slot::Accessor { .. } => false,
+    };
+    !property.configurable && (!frozen || !writable)
+}
+
+/// Validates a descriptor against the current property, giving the property
+/// that results from applying it.
+fn apply_descriptor(current: Option<Property>, descriptor: &PropertyDescriptor, extensible: bool) -> Option<Property> {
+    let (mut property, is_new) = match current {
+        Some(property) => (property, false),
+        None if !extensible => return None,
+        None => {
+            let slot = if descriptor.is_accessor() {
+                Slot::Accessor { get: JsValue::UNDEFINED, set: JsValue::UNDEFINED }
+            } else {
+                Slot::Data { value: JsValue::UNDEFINED, writable: false }
+            };
+            (Property { slot, enumerable: false, configurable: false }, true)
+        },
+    };
+
+    if !is_new && !property.configurable {
+        if descriptor.configurable == Some(true) || descriptor.enumerable.is_some_and(|enumerable| enumerable != property.enumerable) {
+            return None
+        }
+
+        match property.slot {
+            Slot::Data { .. } if descriptor.is_accessor() => return None,
+            Slot::Data { ref value, writable: false } => {
+                if descriptor.writable == Some(true) || descriptor.value.as_ref().is_some_and(|new| !same_value(new, value)) {
+                    return None
+                }
+            },
+            Slot::Data { .. } => {},
+            Slot::Accessor { .. } if descriptor.is_data() => return None,
+            Slot::Accessor { ref get, ref set } => {
+                if descriptor.get.as_ref().is_some_and(|new| new != get) || descriptor.set.as_ref().is_some_and(|new| new != set) {
+                    return None
+                }
+            },
+        }
+    }
+
+    // Switching between a data and an accessor property starts afresh
+    let slot = match property.slot {
+        Slot::Data { .. } if descriptor.is_accessor() => Slot::Accessor { get: JsValue::UNDEFINED, set: JsValue::UNDEFINED },
+        Slot::Accessor { .. } if descriptor.is_data() => Slot::Data { value: JsValue::UNDEFINED, writable: false },
+        slot => slot,
+    };
+
+    property.slot = match slot {
+        Slot::Data { value, writable } => Slot::Data {
+            value: descriptor.value.clone().unwrap_or(value),
+            writable: descriptor.writable.unwrap_or(writable),
+        },
+        Slot::Accessor { get, set } => Slot::Accessor {
+            get: descriptor.get.clone().unwrap_or(get),
+            set: descriptor.set.clone().unwrap_or(set),
+        },
+    };
+    property.enumerable = descriptor.enumerable.unwrap_or(property.enumerable);
+    property.configurable = descriptor.configurable.unwrap_or(property.configurable);
+
+    Some(property)
+}
+
+impl PartialEq for ObjectRef {
+    fn eq(&self, other: &ObjectRef) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for ObjectRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "JsObject({:p})", Rc::as_ptr(&self.0))
+    }
+}
+
+/// Defines the `Object` constructor and `Object.prototype` methods.
+pub fn install(realm: &Realm) -> ObjectRef {
+    let prototype = &realm.object_prototype;
+    let methods: Vec<(&str, NativeFunction)> = vec![
+        ("hasOwnProperty", has_own_property),
+        ("toString", to_string),
+        ("valueOf", value_of),
+    ];
+    for (name, method) in methods {
+        prototype.set_hidden(name.to_owned(), JsValue::object(realm.new_native_function(method)));
+    }
+
+    let constructor = realm.new_native_function(object_constructor);
+    let functions: Vec<(&str, NativeFunction)> = vec![
+        ("create", create),
+        ("is", is),
+        ("getPrototypeOf", get_prototype_of),
+        ("setPrototypeOf", set_prototype_of),
+        ("defineProperty", define_property),
+        ("getOwnPropertyDescriptor", get_own_property_descriptor),
+        ("preventExtensions", prevent_extensions),
+        ("seal", seal),
+        ("freeze", freeze),
+        ("isExtensible", is_extensible),
+        ("isSealed", is_sealed),
+        ("isFrozen", is_frozen),
+    ];
+    for (name, function) in functions {
+        constructor.set_hidden(name.to_owned(), JsValue::object(realm.new_native_function(function)));
+    }
+    constructor.set_constant("prototype".to_owned(), JsValue::object(prototype.clone()));
+    prototype.set_hidden("constructor".to_owned(), JsValue::object(constructor.clone()));
+
+    constructor
+}
+
+/// Checks a value that is about to become a `[[Prototype]]`.
+fn to_prototype(value: &JsValue) -> Result<Option<ObjectRef>, JsError> {
+    match value.view() {
+        Value::Object(prototype) => Ok(Some(prototype.clone())),
+        Value::Null => Ok(None),
+        _ => Err(JsError::type_error(format!("Object prototype may only be an Object or null: {}", js_value_to_string(value)))),
+    }
+}
+
+fn object_constructor(vm: &mut VM, _this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    match argument(args, 0) {
+        object if object.is_object() => Ok(object),
+        _ => Ok(JsValue::object(vm.realm.new_object())),
+    }
+}
+
+fn create(vm: &mut VM, _this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let prototype = to_prototype(&argument(args, 0))?;
+
+    Ok(JsValue::object(vm.realm.allocate(ObjectKind::Ordinary, prototype)))
+}
+
+// Primitives have no prototype objects yet, so they are rejected like ES5 did
+fn get_prototype_of(_vm: &mut VM, _this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    match argument(args, 0).as_object() {
+        Some(object) => match object.borrow().prototype {
+            Some(ref prototype) => Ok(JsValue::object(prototype.clone())),
+            None => Ok(JsValue::NULL),
+        },
+        _ => Err(JsError::type_error("Object.getPrototypeOf called on non-object".to_owned())),
+    }
+}
+
+fn set_prototype_of(_vm: &mut VM, _this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let target = argument(args, 0);
+    if matches!(target.view(), Value::Null | Value::Undefined) {
+        return Err(JsError::type_error("Object.setPrototypeOf called on null or undefined".to_owned()))
+    }
+
+    let prototype = to_prototype(&argument(args, 1))?;
+    let object = match target.as_object() {
+        Some(object) => object,
+        None => return Ok(target),
+    };
+
+    if object.borrow().prototype == prototype {
+        return Ok(target)
+    }
+    if !object.is_extensible() {
+        return Err(JsError::type_error(format!("{} is not extensible", js_value_to_string(&target))))
+    }
+
+    // Lookups walk the chain until it ends, so it must not loop
+    let mut link = prototype.clone();
+    while let Some(ancestor) = link {
+        if ancestor == object {
+            return Err(JsError::type_error("Cyclic __proto__ value".to_owned()))
+        }
+        link = ancestor.borrow().prototype.clone();
+    }
+
+    object.borrow_mut().prototype = prototype;
+    Ok(target)
+}
+
+fn has_own_property(_vm: &mut VM, this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let key = js_value_to_string(&argument(args, 0));
+
+    match this.view() {
+        Value::Object(object) => Ok(JsValue::boolean(object.get_own_property(&key).is_some())),
+        Value::Null | Value::Undefined => Err(JsError::type_error("Cannot convert undefined or null to object".to_owned())),
+        _ => Ok(JsValue::FALSE),
+    }
+}
+
+fn to_string(_vm: &mut VM, this: JsValue, _args: &[JsValue]) -> Result<JsValue, JsError> {
+    let tag = match this.view() {
+        Value::Undefined => "Undefined",
+        Value::Null => "Null",
+        Value::Number(_) => "Number",
+        Value::String(_) => "String",
+        Value::Boolean(_) => "Boolean",
+        Value::Object(object) => match object.borrow().kind {
+            ObjectKind::Array(_) => "Array",
+            ObjectKind::Function(_) => "Function",
+            ObjectKind::Ordinary => "Object",
+        },
+    };
+
+    Ok(JsValue::string(format!("[object {}]", tag)))
+}
+
+fn value_of(_vm: &mut VM, this: JsValue, _args: &[JsValue]) -> Result<JsValue, JsError> {
+    if matches!(this.view(), Value::Null | Value::Undefined) {
+        return Err(JsError::type_error("Cannot convert undefined or null to object".to_owned()))
+    }
+
+    Ok(this)
+}
+
+/// `Object.is`, which compares with SameValue, so NaN is itself and 0 is
+/// not -0.
+fn is(_vm: &mut VM, _this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    Ok(JsValue::boolean(same_value(&argument(args, 0), &argument(args, 1))))
+}
+
+/// Reads the attributes out of a descriptor object given to
+/// `Object.defineProperty`.
+fn to_property_descriptor(vm: &mut VM, value: &JsValue) -> Result<PropertyDescriptor, JsError> {
+    let object = match value.as_object() {
+        Some(object) => object,
+        None => return Err(JsError::type_error(format!("Property description must be an object: {}", js_value_to_string(value)))),
+    };
+
+    let descriptor = PropertyDescriptor {
+        enumerable: descriptor_field(vm, &object, "enumerable")?.map(|field| js_value_to_rust_boolean(&field)),
+        configurable: descriptor_field(vm, &object, "configurable")?.map(|field| js_value_to_rust_boolean(&field)),
+        value: descriptor_field(vm, &object, "value")?,
+        writable: descriptor_field(vm, &object, "writable")?.map(|field| js_value_to_rust_boolean(&field)),
+        get: accessor_field(descriptor_field(vm, &object, "get")?, "Getter")?,
+        set: accessor_field(descriptor_field(vm, &object, "set")?, "Setter")?,
+    };
+
+    if descriptor.is_accessor() && descriptor.is_data() {
+        return Err(JsError::type_error("Invalid property descriptor. Cannot both specify accessors and a value or writable attribute".to_owned()))
+    }
+
+    Ok(descriptor)
+}
+
+/// A field of a descriptor object, which may be inherited or computed.
+fn descriptor_field(vm: &mut VM, object: &ObjectRef, name: &str) -> Result<Option<JsValue>, JsError> {
+    if object.find_property(name).is_none() {
+        return Ok(None)
+    }
+
+    get_property(vm, &JsValue::object(object.clone()), &JsValue::string(name.to_owned())).map(Some)
+}
+
+fn accessor_field(field: Option<JsValue>, kind: &str) -> Result<Option<JsValue>, JsError> {
+    match field {
+        Some(ref function) if function.as_object().is_some_and(|function| function.is_callable()) => {},
+        Some(ref field) if field.is_undefined() => {},
+        None => {},
+        Some(ref field) => return Err(JsError::type_error(format!("{} must be a function: {}", kind, js_value_to_string(field)))),
+    }
+
+    Ok(field)
+}
+
+fn from_property(vm: &mut VM, property: Property) -> JsValue {
+    let object = vm.realm.new_object();
+
+    match property.slot {
+        Slot::Data { value, writable } => {
+            object.set("value".to_owned(), value);
+            object.set("writable".to_owned(), JsValue::boolean(writable));
+        },
+        Slot::Accessor { get, set } => {
+            object.set("get".to_owned(), get);
+            object.set("set".to_owned(), set);
+        },
+    }
+    object.set("enumerable".to_owned(), JsValue::boolean(property.enumerable));
+    object.set("configurable".to_owned(), JsValue::boolean(property.configurable));
+
+    JsValue::object(object)
+}
+
+fn define_property(vm: &mut VM, _this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let target = argument(args, 0);
+    let object = match target.as_object() {
+        Some(object) => object,
+        None => return Err(JsError::type_error("Object.defineProperty called on non-object".to_owned())),
+    };
+    let key = js_value_to_string(&argument(args, 1));
+    let descriptor = to_property_descriptor(vm, &argument(args, 2))?;
+
+    if !object.define_own_property(key.clone(), descriptor)? {
+        return Err(JsError::type_error(format!("Cannot redefine property: {}", key)))
+    }
+
+    Ok(target)
+}
+
+fn get_own_property_descriptor(vm: &mut VM, _this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let object = match argument(args, 0).as_object() {
+        Some(object) => object,
+        None => return Err(JsError::type_error("Object.getOwnPropertyDescriptor called on non-object".to_owned())),
+    };
+    let key = js_value_to_string(&argument(args, 1));
+
+    match object.get_own_property(&key) {
+        Some(property) => Ok(from_property(vm, property)),
+        None => Ok(JsValue::UNDEFINED),
+    }
+}
+
+// The integrity functions leave primitives alone, they have nothing to lock
+
+fn prevent_extensions(_vm: &mut VM, _this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let target = argument(args, 0);
+    if let Some(object) = target.as_object() {
+        object.prevent_extensions();
+    }
+
+    Ok(target)
+}
+
+fn seal(_vm: &mut VM, _this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let target = argument(args, 0);
+    if let Some(object) = target.as_object() {
+        object.set_integrity(false);
+    }
+
+    Ok(target)
+}
+
+fn freeze(_vm: &mut VM, _this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    let target = argument(args, 0);
+    if let Some(object) = target.as_object() {
+        object.set_integrity(true);
+    }
+
+    Ok(target)
+}
+
+fn is_extensible(_vm: &mut VM, _this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    match argument(args, 0).as_object() {
+        Some(object) => Ok(JsValue::boolean(object.is_extensible())),
+        None => Ok(JsValue::FALSE),
+    }
+}
+
+fn is_sealed(_vm: &mut VM, _this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    match argument(args, 0).as_object() {
+        Some(object) => Ok(JsValue::boolean(object.test_integrity(false))),
+        None => Ok(JsValue::TRUE),
+    }
+}
+
+fn is_frozen(_vm: &mut VM, _this: JsValue, args: &[JsValue]) -> Result<JsValue, JsError> {
+    match argument(args, 0).as_object() {
+        Some(object) => Ok(JsValue::boolean(object.test_integrity(true))),
+        None => Ok(JsValue::TRUE),
+    }
+}
@@ -1,137 +1,366 @@
 use super::JsValue;
+use super::Value;
+use super::VM;
+use super::array::array_index;
+use super::array::to_array_length;
+use super::error::JsError;
+use super::object::Property;
+use super::object::Slot;
 use super::temp::js_value_to_string;
-use super::types::rust_to_js_boolean;
-use super::types::flip_js_bool;
-use super::types::try_js_value_to_js_number;
-
-pub fn add(a: &JsValue, b: &JsValue) -> JsValue {
-    match (a, b) {
-        (&JsValue::JsNumber(x), &JsValue::JsNumber(y)) => {
-            return JsValue::JsNumber(x + y)
-        },
-        _ => return JsValue::JsString(format!("{}{}", js_value_to_string(a), js_value_to_string(b)))
-    }
-}
+use super::object::ObjectKind;
+use super::types::js_value_to_int32;
+use super::types::js_value_to_uint32;
+use super::types::js_value_to_number;
+use super::types::js_value_to_rust_boolean;
+use std::mem;
 
-pub fn mlp(a: &JsValue, b: &JsValue) -> JsValue {
-    let parsed_a = try_js_value_to_js_number(a);
-    let parsed_b = try_js_value_to_js_number(b);
+/// Concatenates when either side is a string after objects are converted,
+/// and adds the numbers otherwise.
+pub fn add(vm: &mut VM, a: &JsValue, b: &JsValue) -> Result<JsValue, JsError> {
+    let a = to_primitive(vm, a)?;
+    let b = to_primitive(vm, b)?;
 
-    match (parsed_a, parsed_b) {
-        (JsValue::JsNumber(x), JsValue::JsNumber(y)) => {
-            return JsValue::JsNumber(x * y)
+    match (a.view(), b.view()) {
+        (Value::String(_), _) | (_, Value::String(_)) => {
+            let a = js_value_to_string(&a);
+            let b = js_value_to_string(&b);
+            vm.realm.heap.check_string_length(a.len() + b.len())?;
+            Ok(JsValue::string(a + &b))
         },
-        _ => return JsValue::JsNan
+        _ => Ok(JsValue::number(js_value_to_number(&a) + js_value_to_number(&b)))
     }
 }
 
-pub fn div(a: &JsValue, b: &JsValue) -> JsValue {
-    let parsed_a = try_js_value_to_js_number(a);
-    let parsed_b = try_js_value_to_js_number(b);
+/// Reads a property, running its getter if it has one.
+pub fn get_property(vm: &mut VM, object: &JsValue, key: &JsValue) -> Result<JsValue, JsError> {
+    let key = js_value_to_string(key);
 
-    match (parsed_a, parsed_b) {
-        (JsValue::JsNumber(x), JsValue::JsNumber(y)) => {
-            return JsValue::JsNumber(x / y)
-        },
-        _ => return JsValue::JsNan
+    let holder = match object.view() {
+        Value::Object(target) => target.clone(),
+        // Numbers find their methods on `Number.prototype`
+        Value::Number(_) => vm.realm.number_prototype.clone(),
+        _ => return get_primitive_property(object, key),
+    };
+
+    match holder.find_property(&key) {
+        Some(Property { slot: Slot::Data { value, .. }, .. }) => Ok(value),
+        Some(Property { slot: Slot::Accessor { get, .. }, .. }) if !get.is_undefined() => vm.call_function(&get, object.clone(), &[]),
+        _ => Ok(JsValue::UNDEFINED),
     }
 }
 
-pub fn sub(a: &JsValue, b: &JsValue) -> JsValue {
-    let parsed_a = try_js_value_to_js_number(a);
-    let parsed_b = try_js_value_to_js_number(b);
+/// Reads a property of a primitive that has no prototype to look in.
+fn get_primitive_property(object: &JsValue, key: String) -> Result<JsValue, JsError> {
+    match object.view() {
+        Value::String(string) => {
+            if key == "length" {
+                return Ok(JsValue::number(string.chars().count() as f64))
+            }
 
-    match (parsed_a, parsed_b) {
-        (JsValue::JsNumber(x), JsValue::JsNumber(y)) => {
-            return JsValue::JsNumber(x - y)
+            match key.parse::<usize>() {
+                Ok(index) => match string.chars().nth(index) {
+                    Some(c) => Ok(JsValue::string(c.to_string())),
+                    None => Ok(JsValue::UNDEFINED),
+                },
+                Err(_) => Ok(JsValue::UNDEFINED),
+            }
         },
-        _ => return JsValue::JsNan
+        Value::Null | Value::Undefined => {
+            Err(JsError::type_error(format!("Cannot read property '{}' of {}", key, js_value_to_string(object))))
+        },
+        _ => Ok(JsValue::UNDEFINED),
     }
 }
 
-fn handle_eq_with_bool(boolValue: &JsValue, comparedValue: &JsValue) -> JsValue {
-    match comparedValue {
-        &JsValue::JsString(ref x) => {
-            if !x.is_empty() {
-                match x.clone().parse::<f64>() {
-                    Ok(parsed) => return rust_to_js_boolean(parsed == 0 as f64),
-                    Err(_) => return rust_to_js_boolean(boolValue != (&JsValue::JsFalse)),
-                }
+/// Assigns to a property, running its setter if it has one. Assignments
+/// that the attributes of the property or object forbid are dropped
+/// silently, like they are outside strict mode.
+pub fn set_property(vm: &mut VM, object: &JsValue, key: &JsValue, value: JsValue) -> Result<(), JsError> {
+    let key = js_value_to_string(key);
+
+    match object.view() {
+        Value::Object(target) => {
+            let value = if key == "length" && target.is_array() {
+                let length = to_array_length(&value)?;
+                vm.realm.heap.check_array_length(length as u64)?;
+                JsValue::number(length as f64)
             } else {
-                return rust_to_js_boolean(boolValue == (&JsValue::JsFalse))
+                value
+            };
+            // Writing past the end of an array makes it longer
+            if let Some(index) = array_index(&key).filter(|_| target.is_array()) {
+                vm.realm.heap.check_array_length(index as u64 + 1)?;
             }
-        },
-        &JsValue::JsNumber(x) => {
-            if x != 0 as f64 {
-                return rust_to_js_boolean(boolValue != (&JsValue::JsFalse))
-            } else {
-                return rust_to_js_boolean(boolValue == (&JsValue::JsFalse))
+
+            match target.find_property(&key) {
+                Some(Property { slot: Slot::Accessor { set, .. }, .. }) if set.is_undefined() => {},
+                Some(Property { slot: Slot::Accessor { set, .. }, .. }) => {
+                    vm.call_function(&set, object.clone(), &[value])?;
+                },
+                Some(Property { slot: Slot::Data { writable: false, .. }, .. }) => {},
+                _ => {
+                    vm.realm.heap.write_barrier(&target, &value);
+                    target.write(key, value);
+                },
             }
+            Ok(())
         },
-        _ => {
-            return rust_to_js_boolean(boolValue != &JsValue::JsFalse)
-        }
+        Value::Null | Value::Undefined => {
+            Err(JsError::type_error(format!("Cannot set property '{}' of {}", key, js_value_to_string(object))))
+        },
+        // Properties of primitives are dropped silently outside strict mode
+        _ => Ok(()),
     }
 }
 
-pub fn eq(a: &JsValue, b: &JsValue) -> JsValue {
-    if a == b {
-        return rust_to_js_boolean(true)
+pub fn not(a: &JsValue) -> JsValue {
+    JsValue::boolean(!js_value_to_rust_boolean(a))
+}
+
+pub fn neg(a: &JsValue) -> JsValue {
+    JsValue::number(-js_value_to_number(a))
+}
+
+pub fn to_number(a: &JsValue) -> JsValue {
+    JsValue::number(js_value_to_number(a))
+}
+
+pub fn bit_not(a: &JsValue) -> JsValue {
+    JsValue::number(!js_value_to_int32(a) as f64)
+}
+
+pub fn type_of(a: &JsValue) -> JsValue {
+    let name = match a.view() {
+        Value::Undefined => "undefined",
+        Value::Null => "object",
+        Value::Boolean(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Object(object) => match object.borrow().kind {
+            ObjectKind::Function(_) => "function",
+            _ => "object",
+        },
+    };
+
+    JsValue::string(name.to_owned())
+}
+
+/// Removes a property, telling whether it is gone. Non-configurable
+/// properties stay, like they do outside strict mode.
+pub fn delete_property(object: &JsValue, key: &JsValue) -> Result<JsValue, JsError> {
+    let key = js_value_to_string(key);
+
+    match object.view() {
+        Value::Object(object) => Ok(JsValue::boolean(object.delete(&key))),
+        Value::String(string) => {
+            let is_own = key == "length" || key.parse::<usize>().is_ok_and(|index| index < string.chars().count());
+            Ok(JsValue::boolean(!is_own))
+        },
+        Value::Null | Value::Undefined => {
+            Err(JsError::type_error(format!("Cannot delete property '{}' of {}", key, js_value_to_string(object))))
+        },
+        _ => Ok(JsValue::TRUE),
     }
+}
+
+pub fn mlp(a: &JsValue, b: &JsValue) -> JsValue {
+    JsValue::number(js_value_to_number(a) * js_value_to_number(b))
+}
+
+pub fn div(a: &JsValue, b: &JsValue) -> JsValue {
+    JsValue::number(js_value_to_number(a) / js_value_to_number(b))
+}
+
+pub fn sub(a: &JsValue, b: &JsValue) -> JsValue {
+    JsValue::number(js_value_to_number(a) - js_value_to_number(b))
+}
 
-    if a == &JsValue::JsTrue || a == &JsValue::JsFalse {
-        return handle_eq_with_bool(a, b)
+/// Whether both values have the same type.
+fn same_type(a: &JsValue, b: &JsValue) -> bool {
+    mem::discriminant(&a.view()) == mem::discriminant(&b.view())
+}
+
+/// The IsStrictlyEqual comparison. NaN equals nothing, 0 equals -0 and
+/// objects are only equal to themselves.
+pub fn is_strictly_equal(a: &JsValue, b: &JsValue) -> bool {
+    match a.as_number() {
+        Some(number) if number.is_nan() => false,
+        _ => a == b,
     }
+}
 
-    if b == &JsValue::JsTrue || b == &JsValue::JsFalse {
-        return handle_eq_with_bool(b, a)
+/// The IsLooselyEqual comparison behind `==`, which converts the operands
+/// towards numbers until their types match.
+pub fn is_loosely_equal(vm: &mut VM, a: &JsValue, b: &JsValue) -> Result<bool, JsError> {
+    if same_type(a, b) {
+        return Ok(is_strictly_equal(a, b))
     }
 
-    match (a, b) {
-        (&JsValue::JsString(ref x), &JsValue::JsString(ref y)) => {
-            return rust_to_js_boolean(x == y)
+    match (a.view(), b.view()) {
+        (Value::Null, Value::Undefined) | (Value::Undefined, Value::Null) => Ok(true),
+        (Value::Number(_), Value::String(_)) | (Value::String(_), Value::Number(_)) => {
+            Ok(js_value_to_number(a) == js_value_to_number(b))
         },
-        (&JsValue::JsNumber(x), &JsValue::JsNumber(y)) => {
-            return rust_to_js_boolean(x == y)
+        (Value::Boolean(_), _) => is_loosely_equal(vm, &to_number(a), b),
+        (_, Value::Boolean(_)) => is_loosely_equal(vm, a, &to_number(b)),
+        (Value::Object(_), Value::Number(_)) | (Value::Object(_), Value::String(_)) => {
+            let a = to_primitive(vm, a)?;
+            is_loosely_equal(vm, &a, b)
         },
-        (&JsValue::JsString(ref x), &JsValue::JsNumber(y)) => {
-            match x.clone().parse::<f64>() {
-                Ok(parsed_x) => return rust_to_js_boolean(parsed_x == y),
-                Err(_) => return JsValue::JsFalse,
+        (Value::Number(_), Value::Object(_)) | (Value::String(_), Value::Object(_)) => {
+            let b = to_primitive(vm, b)?;
+            is_loosely_equal(vm, a, &b)
+        },
+        _ => Ok(false),
+    }
+}
+
+pub fn eq(vm: &mut VM, a: &JsValue, b: &JsValue) -> Result<JsValue, JsError> {
+    Ok(JsValue::boolean(is_loosely_equal(vm, a, b)?))
+}
+
+pub fn neq(vm: &mut VM, a: &JsValue, b: &JsValue) -> Result<JsValue, JsError> {
+    Ok(JsValue::boolean(!is_loosely_equal(vm, a, b)?))
+}
+
+pub fn strict_eq(a: &JsValue, b: &JsValue) -> JsValue {
+    JsValue::boolean(is_strictly_equal(a, b))
+}
+
+pub fn strict_neq(a: &JsValue, b: &JsValue) -> JsValue {
+    JsValue::boolean(!is_strictly_equal(a, b))
+}
+
+pub fn modulo(a: &JsValue, b: &JsValue) -> JsValue {
+    JsValue::number(js_value_to_number(a) % js_value_to_number(b))
+}
+
+pub fn shl(a: &JsValue, b: &JsValue) -> JsValue {
+    JsValue::number(js_value_to_int32(a).wrapping_shl(js_value_to_uint32(b) & 0x1f) as f64)
+}
+
+pub fn shr(a: &JsValue, b: &JsValue) -> JsValue {
+    JsValue::number((js_value_to_int32(a) >> (js_value_to_uint32(b) & 0x1f)) as f64)
+}
+
+pub fn ushr(a: &JsValue, b: &JsValue) -> JsValue {
+    JsValue::number((js_value_to_uint32(a) >> (js_value_to_uint32(b) & 0x1f)) as f64)
+}
+
+pub fn bit_and(a: &JsValue, b: &JsValue) -> JsValue {
+    JsValue::number((js_value_to_int32(a) & js_value_to_int32(b)) as f64)
+}
+
+pub fn bit_or(a: &JsValue, b: &JsValue) -> JsValue {
+    JsValue::number((js_value_to_int32(a) | js_value_to_int32(b)) as f64)
+}
+
+pub fn bit_xor(a: &JsValue, b: &JsValue) -> JsValue {
+    JsValue::number((js_value_to_int32(a) ^ js_value_to_int32(b)) as f64)
+}
+
+/// The ToPrimitive conversion, which asks an object for `valueOf` and then
+/// `toString`, settling on the first primitive they return. That is the
+/// order of the number hint, which is also what objects without a
+/// `Symbol.toPrimitive` use by default.
+pub fn to_primitive(vm: &mut VM, value: &JsValue) -> Result<JsValue, JsError> {
+    if value.is_object() {
+        for name in &["valueOf", "toString"] {
+            let method = get_property(vm, value, &JsValue::string((*name).to_owned()))?;
+            if !is_callable(&method) {
+                continue
             }
-        }
-        (&JsValue::JsNumber(x), &JsValue::JsString(ref y)) => {
-            match y.clone().parse::<f64>() {
-                Ok(parsed_y) => return rust_to_js_boolean(x == parsed_y),
-                Err(_) => return JsValue::JsFalse,
+
+            let result = vm.call_function(&method, value.clone(), &[])?;
+            if !result.is_object() {
+                return Ok(result)
             }
         }
-        _ => return JsValue::JsFalse
+        return Err(JsError::type_error("Cannot convert object to primitive value".to_owned()))
     }
+
+    Ok(value.clone())
 }
 
-pub fn neq(a: &JsValue, b: &JsValue) -> JsValue {
-    return flip_js_bool(eq(a, b))
+fn is_callable(value: &JsValue) -> bool {
+    value.as_object().is_some_and(|object| object.is_callable())
 }
 
-pub fn strict_eq(a: &JsValue, b: &JsValue) -> JsValue {
-    match (a, b) {
-        (&JsValue::JsString(ref x), &JsValue::JsString(ref y)) => {
-            return rust_to_js_boolean(x == y)
-        },
-        (&JsValue::JsNumber(x), &JsValue::JsNumber(y)) => {
-            return rust_to_js_boolean(x == y)
-        },
-        (&JsValue::JsTrue, &JsValue::JsTrue) => {
-            return rust_to_js_boolean(true)
+/// The abstract relational comparison `a < b` of two primitives, which is
+/// `None` when either side is NaN. Strings compare by UTF-16 code units.
+fn less_than(a: &JsValue, b: &JsValue) -> Option<bool> {
+    match (a.view(), b.view()) {
+        (Value::String(x), Value::String(y)) => Some(x.encode_utf16().lt(y.encode_utf16())),
+        _ => {
+            let x = js_value_to_number(a);
+            let y = js_value_to_number(b);
+            if x.is_nan() || y.is_nan() {
+                return None
+            }
+            Some(x < y)
         },
-        (&JsValue::JsFalse, &JsValue::JsFalse) => {
-            return rust_to_js_boolean(true)
-        }
-        _ => return JsValue::JsFalse
     }
 }
 
-pub fn strict_neq(a: &JsValue, b: &JsValue) -> JsValue {
-    return flip_js_bool(strict_eq(a, b))
+/// Converts both operands of a comparison, left one first.
+fn primitives(vm: &mut VM, a: &JsValue, b: &JsValue) -> Result<(JsValue, JsValue), JsError> {
+    let a = to_primitive(vm, a)?;
+    let b = to_primitive(vm, b)?;
+    Ok((a, b))
+}
+
+pub fn lt(vm: &mut VM, a: &JsValue, b: &JsValue) -> Result<JsValue, JsError> {
+    let (a, b) = primitives(vm, a, b)?;
+    Ok(JsValue::boolean(less_than(&a, &b) == Some(true)))
+}
+
+pub fn gt(vm: &mut VM, a: &JsValue, b: &JsValue) -> Result<JsValue, JsError> {
+    let (a, b) = primitives(vm, a, b)?;
+    Ok(JsValue::boolean(less_than(&b, &a) == Some(true)))
+}
+
+pub fn lte(vm: &mut VM, a: &JsValue, b: &JsValue) -> Result<JsValue, JsError> {
+    let (a, b) = primitives(vm, a, b)?;
+    Ok(JsValue::boolean(less_than(&b, &a) == Some(false)))
+}
+
+pub fn gte(vm: &mut VM, a: &JsValue, b: &JsValue) -> Result<JsValue, JsError> {
+    let (a, b) = primitives(vm, a, b)?;
+    Ok(JsValue::boolean(less_than(&a, &b) == Some(false)))
+}
+
+pub fn has_property(key: &JsValue, object: &JsValue) -> Result<JsValue, JsError> {
+    let key = js_value_to_string(key);
+
+    match object.as_object() {
+        Some(object) => Ok(JsValue::boolean(object.find_property(&key).is_some())),
+        None => Err(JsError::type_error(format!("Cannot use 'in' operator to search for '{}' in {}", key, js_value_to_string(object)))),
+    }
+}
+
+/// Whether `constructor.prototype` is on the prototype chain of `value`.
+pub fn instance_of(vm: &mut VM, value: &JsValue, constructor: &JsValue) -> Result<JsValue, JsError> {
+    if !is_callable(constructor) {
+        return Err(JsError::type_error("Right-hand side of 'instanceof' is not callable".to_owned()))
+    }
+
+    let mut object = match value.as_object() {
+        Some(object) => object,
+        None => return Ok(JsValue::FALSE),
+    };
+
+    let prototype = get_property(vm, constructor, &JsValue::string("prototype"))?;
+    let prototype = match prototype.as_object() {
+        Some(prototype) => prototype,
+        None => return Err(JsError::type_error(format!("Function has non-object prototype '{}' in instanceof check", js_value_to_string(&prototype)))),
+    };
+
+    loop {
+        let next = object.borrow().prototype.clone();
+        match next {
+            Some(next) if next == prototype => return Ok(JsValue::TRUE),
+            Some(next) => object = next,
+            None => return Ok(JsValue::FALSE),
+        }
+    }
 }
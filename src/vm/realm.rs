@@ -0,0 +1,117 @@
+use super::JsValue;
+use super::array;
+use super::array::JsArray;
+use super::error;
+use super::error::ErrorKind;
+use super::function;
+use super::function::Function;
+use super::function::NativeFunction;
+use super::heap::Heap;
+use super::number;
+use super::object;
+use super::object::ObjectKind;
+use super::object::ObjectRef;
+use super::scope::ScopeRef;
+use super::super::bytecode::Block;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// The built-in objects scripts share. A realm is created along with the
+/// global scope, so they survive from one REPL line to the next.
+#[derive(Clone)]
+pub struct Realm {
+    pub object_prototype: ObjectRef,
+    pub function_prototype: ObjectRef,
+    pub array_prototype: ObjectRef,
+    /// Where properties of number values are looked up
+    pub number_prototype: ObjectRef,
+    /// `Error.prototype` and the prototypes of the other kinds of errors,
+    /// which inherit from it
+    pub error_prototypes: HashMap<ErrorKind, ObjectRef>,
+    /// Built-ins that are bound as globals
+    pub globals: Vec<(String, JsValue)>,
+    /// Values bound as globals that scripts can't change
+    pub constants: Vec<(String, JsValue)>,
+    /// Where every object of the realm is allocated
+    pub heap: Rc<Heap>,
+}
+
+impl Realm {
+    pub fn new() -> Realm {
+        let heap = Rc::new(Heap::new());
+        let object_prototype = heap.allocate(ObjectKind::Ordinary, None);
+        let function_prototype = heap.allocate(ObjectKind::Ordinary, Some(object_prototype.clone()));
+        let array_prototype = heap.allocate(ObjectKind::Array(JsArray::new(Vec::new())), Some(object_prototype.clone()));
+        let number_prototype = heap.allocate(ObjectKind::Ordinary, Some(object_prototype.clone()));
+
+        let error_prototype = heap.allocate(ObjectKind::Ordinary, Some(object_prototype.clone()));
+        let mut error_prototypes = HashMap::new();
+        for kind in [ErrorKind::TypeError, ErrorKind::ReferenceError, ErrorKind::RangeError, ErrorKind::InternalError] {
+            error_prototypes.insert(kind, heap.allocate(ObjectKind::Ordinary, Some(error_prototype.clone())));
+        }
+        error_prototypes.insert(ErrorKind::Error, error_prototype);
+        for (kind, prototype) in error_prototypes.iter() {
+            prototype.set_hidden("name".to_owned(), JsValue::string(kind.name()));
+            prototype.set_hidden("message".to_owned(), JsValue::string(""));
+        }
+
+        let mut realm = Realm { object_prototype, function_prototype, array_prototype, number_prototype, error_prototypes, globals: Vec::new(), constants: Vec::new(), heap };
+
+        function::install(&realm);
+        let object = object::install(&realm);
+        realm.globals.push(("Object".to_owned(), JsValue::object(object)));
+        let array = array::install(&realm);
+        realm.globals.push(("Array".to_owned(), JsValue::object(array)));
+        let number = number::install(&realm);
+        realm.globals.push(("Number".to_owned(), JsValue::object(number)));
+        for (name, constructor) in error::install(&realm) {
+            realm.globals.push((name, JsValue::object(constructor)));
+        }
+        realm.constants.push(("undefined".to_owned(), JsValue::UNDEFINED));
+        realm.constants.push(("NaN".to_owned(), JsValue::NAN));
+        realm.constants.push(("Infinity".to_owned(), JsValue::number(f64::INFINITY)));
+
+        realm
+    }
+
+    pub fn allocate(&self, kind: ObjectKind, prototype: Option<ObjectRef>) -> ObjectRef {
+        self.heap.allocate(kind, prototype)
+    }
+
+    pub fn new_object(&self) -> ObjectRef {
+        self.allocate(ObjectKind::Ordinary, Some(self.object_prototype.clone()))
+    }
+
+    pub fn new_array(&self, values: Vec<JsValue>) -> ObjectRef {
+        self.allocate(ObjectKind::Array(JsArray::new(values)), Some(self.array_prototype.clone()))
+    }
+
+    /// Creates a compiled function, along with the `prototype` object that
+    /// `new` links its instances to.
+    pub fn new_function(&self, code: Rc<Block>, scope: Option<ScopeRef>) -> ObjectRef {
+        let function = self.allocate(ObjectKind::Function(Function::Bytecode { code, scope }), Some(self.function_prototype.clone()));
+
+        let prototype = self.new_object();
+        prototype.set_hidden("constructor".to_owned(), JsValue::object(function.clone()));
+        function.set_hidden("prototype".to_owned(), JsValue::object(prototype));
+
+        function
+    }
+
+    pub fn error_prototype(&self, kind: ErrorKind) -> &ObjectRef {
+        &self.error_prototypes[&kind]
+    }
+
+    /// Creates an error object, like `new TypeError(message)` does.
+    pub fn new_error(&self, kind: ErrorKind, message: Option<String>) -> ObjectRef {
+        let error = self.allocate(ObjectKind::Ordinary, Some(self.error_prototype(kind).clone()));
+        if let Some(message) = message {
+            error.set_hidden("message".to_owned(), JsValue::string(message));
+        }
+        error
+    }
+
+    pub fn new_native_function(&self, function: NativeFunction) -> ObjectRef {
+        self.allocate(ObjectKind::Function(Function::Native(function)), Some(self.function_prototype.clone()))
+    }
+}
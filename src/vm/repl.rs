@@ -1,15 +1,90 @@
 use super::JsValue;
+use super::Value;
+use super::array::JsArray;
+use super::number::number_to_string;
+use super::object::ObjectKind;
+use super::object::ObjectRef;
 
 use ansi_term::Colour::RGB;
 
+/// Arrays nested deeper than this are shown as `[Array]`.
+const MAX_ARRAY_DEPTH: usize = 2;
+
+/// Arrays show this many elements at most, a run of holes counting as one.
+const MAX_ARRAY_ITEMS: usize = 100;
+
 pub fn ret_value_fmt(val: &JsValue) -> String {
-    match val {
-        &JsValue::JsNull => return format!("{}", RGB(130, 130, 130).paint("null".to_owned())),
-        &JsValue::JsUndefined => return format!("{}", RGB(130, 130, 130).paint("undefined".to_owned())),
-        &JsValue::JsNan => return format!("{}", RGB(209, 154, 102).paint("NaN".to_owned())),
-        &JsValue::JsNumber(num) => return format!("{}", RGB(209, 154, 102).paint(format!("{}", num))),
-        &JsValue::JsString(ref s) => return format!("{}", RGB(152, 195, 121).paint(format!("\"{}\"", s.clone()))),
-        &JsValue::JsTrue => return format!("{}", RGB(209, 154, 102).paint("true".to_owned())),
-        &JsValue::JsFalse => return format!("{}", RGB(209, 154, 102).paint("false".to_owned())),
+    value_fmt(val, 0)
+}
+
+fn value_fmt(val: &JsValue, depth: usize) -> String {
+    match val.view() {
+        Value::Null => format!("{}", RGB(130, 130, 130).paint("null".to_owned())),
+        Value::Undefined => format!("{}", RGB(130, 130, 130).paint("undefined".to_owned())),
+        Value::Number(num) => format!("{}", RGB(209, 154, 102).paint(number_to_string(num))),
+        Value::String(s) => format!("{}", RGB(152, 195, 121).paint(format!("\"{}\"", s))),
+        Value::Boolean(b) => format!("{}", RGB(209, 154, 102).paint(b.to_string())),
+        Value::Object(object) => object_fmt(&object, depth),
     }
 }
+
+fn object_fmt(object: &ObjectRef, depth: usize) -> String {
+    let (items, rest) = match object.borrow().kind {
+        ObjectKind::Function(_) => return format!("{}", RGB(97, 175, 239).paint("[Function]".to_owned())),
+        ObjectKind::Ordinary => return format!("{}", RGB(130, 130, 130).paint("[object Object]".to_owned())),
+        ObjectKind::Array(_) if depth >= MAX_ARRAY_DEPTH => return format!("{}", RGB(97, 175, 239).paint("[Array]".to_owned())),
+        ObjectKind::Array(ref array) => array_items(array),
+    };
+
+    let mut shown: Vec<String> = items.iter().map(|item| {
+        match *item {
+            Item::Element(Some(ref value)) => value_fmt(value, depth + 1),
+            Item::Element(None) => format!("{}", RGB(97, 175, 239).paint("[Getter/Setter]".to_owned())),
+            Item::Holes(1) => format!("{}", RGB(130, 130, 130).paint("<1 empty item>".to_owned())),
+            Item::Holes(count) => format!("{}", RGB(130, 130, 130).paint(format!("<{} empty items>", count))),
+        }
+    }).collect();
+    if rest > 0 {
+        shown.push(format!("{}", RGB(130, 130, 130).paint(format!("... {} more items", rest))));
+    }
+
+    format!("[{}]", shown.join(", "))
+}
+
+/// What an array is shown as, one element or run of holes at a time.
+enum Item {
+    /// An element, `None` for accessors
+    Element(Option<JsValue>),
+    Holes(u32),
+}
+
+/// The first items of an array, along with how many indices are left out
+/// after them. Only the elements that are not holes are looked at, so huge
+/// sparse arrays show as quickly as small ones.
+fn array_items(array: &JsArray) -> (Vec<Item>, u32) {
+    let mut items = Vec::new();
+    let mut next = 0;
+
+    while next < array.length {
+        if items.len() >= MAX_ARRAY_ITEMS {
+            return (items, array.length - next)
+        }
+
+        match array.next_index(next, array.length) {
+            Some(index) if index == next => {
+                items.push(Item::Element(array.get(index).cloned()));
+                next = index + 1;
+            },
+            Some(index) => {
+                items.push(Item::Holes(index - next));
+                next = index;
+            },
+            None => {
+                items.push(Item::Holes(array.length - next));
+                next = array.length;
+            },
+        }
+    }
+
+    (items, 0)
+}
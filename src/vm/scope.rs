@@ -1,37 +1,184 @@
 use super::JsValue;
+use super::heap::BINDING_BYTES;
+use super::heap::note_allocation;
+use super::realm::Realm;
+use std::cell::RefCell;
 use std::collections::HashMap;
-use std;
+use std::fmt;
+use std::rc::Rc;
 
+/// A shared reference to a non-global scope, which closures keep alive.
+pub type ScopeRef = Rc<RefCell<Scope>>;
+
+#[derive(Clone)]
+struct Binding {
+    value: JsValue,
+    /// `let` and `const` bindings can't be used before their declaration
+    /// has run, this is their temporal dead zone
+    initialized: bool,
+    mutable: bool,
+    /// Built-in constants like `undefined` ignore assignments and
+    /// redeclarations, rather than throwing like `const` bindings do
+    read_only: bool,
+}
+
+/// Ways using a binding can go wrong.
+#[derive(Debug, PartialEq)]
+pub enum BindingError {
+    Uninitialized(String),
+    AssignToConstant(String),
+    /// Malformed bytecode initialized a binding it never declared
+    Undeclared(String),
+}
+
+/// An environment record mapping names to values. Scopes form a chain
+/// through their parents; a scope without a parent is enclosed directly by
+/// the global scope.
 #[derive(Clone)]
 pub struct Scope {
     id: i32,
     is_global: bool,
-    parent: i32,
-    variables: HashMap<String, JsValue>,
+    parent: Option<ScopeRef>,
+    variables: HashMap<String, Binding>,
+    /// The built-ins, owned by the global scope
+    realm: Option<Realm>,
 }
 
 impl Scope {
-    pub fn new(id: i32, parent: i32) {
+    pub fn new(id: i32, parent: Option<ScopeRef>) -> Scope {
         Scope {
-            id: id,
+            id,
             is_global: false,
             variables: HashMap::new(),
-            parent: id
-        };
+            parent,
+            realm: None,
+        }
     }
 
+    /// Creates the global scope, with a new realm of built-ins bound in it.
     pub fn new_global() -> Scope {
-        Scope { id: 0, is_global: true, variables: HashMap::new(), parent: 0 }
-    }
+        let realm = Realm::new();
+        let mut scope = Scope { id: 0, is_global: true, variables: HashMap::new(), parent: None, realm: None };
 
-    pub fn get_var(&self, string: String) -> JsValue {
-        match self.variables.get(&string) {
-            Some(a) => return a.clone(),
-            None => return JsValue::JsUndefined
+        for (name, value) in realm.globals.iter() {
+            scope.set_var(name.clone(), value.clone());
+        }
+        for (name, value) in realm.constants.iter() {
+            note_allocation(BINDING_BYTES);
+            scope.variables.insert(name.clone(), Binding { value: value.clone(), initialized: true, mutable: true, read_only: true });
         }
+        scope.realm = Some(realm);
+
+        scope
+    }
+
+    /// Creates a scope sharing the parent and holding copies of the bindings
+    /// of this one, giving every iteration of a `for (let …)` loop its own
+    /// bindings.
+    pub fn copy(&self, id: i32) -> Scope {
+        Scope { id, is_global: self.is_global, variables: self.variables.clone(), parent: self.parent.clone(), realm: self.realm.clone() }
     }
 
+    pub fn parent(&self) -> Option<ScopeRef> {
+        self.parent.clone()
+    }
+
+    pub fn realm(&self) -> Option<&Realm> {
+        self.realm.as_ref()
+    }
+
+    /// The values bound in this scope, leaving out its parents.
+    pub fn values(&self) -> impl Iterator<Item = &JsValue> {
+        self.variables.values().map(|binding| &binding.value)
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut JsValue> {
+        self.variables.values_mut().map(|binding| &mut binding.value)
+    }
+
+    pub fn has_var(&self, string: &str) -> bool {
+        self.variables.contains_key(string)
+    }
+
+    /// Declares a `var` style binding, which is usable right away.
     pub fn set_var(&mut self, string: String, js_value: JsValue) {
-        self.variables.insert(string, js_value);
+        if self.variables.get(&string).is_some_and(|binding| binding.read_only) {
+            return
+        }
+        note_allocation(BINDING_BYTES);
+        self.variables.insert(string, Binding { value: js_value, initialized: true, mutable: true, read_only: false });
+    }
+
+    /// Declares a `let` or `const` binding that stays in its temporal dead
+    /// zone until it is initialized.
+    pub fn declare_lexical(&mut self, string: String, mutable: bool) {
+        note_allocation(BINDING_BYTES);
+        self.variables.insert(string, Binding { value: JsValue::UNDEFINED, initialized: false, mutable, read_only: false });
+    }
+
+    pub fn initialize(&mut self, string: String, js_value: JsValue) -> Result<(), BindingError> {
+        match self.variables.get_mut(&string) {
+            Some(binding) => {
+                binding.value = js_value;
+                binding.initialized = true;
+                Ok(())
+            },
+            None => Err(BindingError::Undeclared(string)),
+        }
+    }
+
+    /// Looks a name up in this scope and its parents, returning `None` when
+    /// it has to be resolved in the global scope instead.
+    pub fn lookup(&self, string: &str) -> Result<Option<JsValue>, BindingError> {
+        if let Some(binding) = self.variables.get(string) {
+            if !binding.initialized {
+                return Err(BindingError::Uninitialized(string.to_owned()))
+            }
+
+            return Ok(Some(binding.value.clone()))
+        }
+
+        match self.parent {
+            Some(ref parent) => return parent.borrow().lookup(string),
+            None => Ok(None)
+        }
+    }
+
+    /// Assigns to the closest scope in the chain declaring the name. The
+    /// value is handed back when no scope in the chain declares it.
+    pub fn assign(&mut self, string: String, js_value: JsValue) -> Result<Option<JsValue>, BindingError> {
+        if let Some(binding) = self.variables.get_mut(&string) {
+            if !binding.initialized {
+                return Err(BindingError::Uninitialized(string))
+            }
+
+            if !binding.mutable {
+                return Err(BindingError::AssignToConstant(string))
+            }
+
+            if !binding.read_only {
+                binding.value = js_value;
+            }
+            return Ok(None)
+        }
+
+        match self.parent {
+            Some(ref parent) => return parent.borrow_mut().assign(string, js_value),
+            None => Ok(Some(js_value))
+        }
+    }
+}
+
+impl fmt::Debug for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names: Vec<&String> = self.variables.keys().collect();
+        names.sort();
+
+        f.debug_struct("Scope")
+            .field("id", &self.id)
+            .field("is_global", &self.is_global)
+            .field("parent", &self.parent.as_ref().map(|parent| parent.borrow().id))
+            .field("variables", &names)
+            .finish()
     }
 }
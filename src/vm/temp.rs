@@ -1,13 +1,108 @@
 use super::JsValue;
+use super::Value;
+use super::error::JsError;
+use super::function::Function;
+use super::heap::MAX_STRING_LENGTH;
+use super::number::number_to_string;
+use super::object::ObjectKind;
+use super::object::ObjectRef;
+
+use std::cell::RefCell;
+use std::cmp;
+
+thread_local! {
+    /// Arrays that are being joined right now, so joining a cyclic array ends
+    static JOINING: RefCell<Vec<ObjectRef>> = const { RefCell::new(Vec::new()) };
+}
 
 pub fn js_value_to_string(val: &JsValue) -> String {
-    match val {
-        &JsValue::JsNull => return "null".to_owned(),
-        &JsValue::JsUndefined => return "undefined".to_owned(),
-        &JsValue::JsNan => return "NaN".to_owned(),
-        &JsValue::JsNumber(num) => return format!("{}", num),
-        &JsValue::JsString(ref s) => return s.clone(),
-        &JsValue::JsTrue => return "true".to_owned(),
-        &JsValue::JsFalse => return "false".to_owned(),
+    match val.view() {
+        Value::Null => "null".to_owned(),
+        Value::Undefined => "undefined".to_owned(),
+        Value::Number(num) => number_to_string(num),
+        Value::String(s) => s.to_owned(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Object(object) => object_to_string(&object),
+    }
+}
+
+fn object_to_string(object: &ObjectRef) -> String {
+    match object.borrow().kind {
+        ObjectKind::Function(Function::Bytecode { .. }) => return "function () { [bytecode] }".to_owned(),
+        ObjectKind::Function(Function::Native(_)) => return "function () { [native code] }".to_owned(),
+        ObjectKind::Array(_) => {},
+        ObjectKind::Ordinary => return "[object Object]".to_owned(),
     }
+
+    join_array(object, ",", MAX_STRING_LENGTH, &mut || Ok(())).unwrap_or_default()
+}
+
+/// Joins the elements of an array like `Array.prototype.join`, where holes,
+/// `undefined` and `null` become empty strings. An array that contains
+/// itself joins to an empty string the second time around. Gives up with a
+/// `RangeError` as soon as the result gets longer than `max_length`, and
+/// calls `tick` for every element so the VM can stop long joins.
+pub fn join_array(array: &ObjectRef, separator: &str, max_length: usize, tick: &mut dyn FnMut() -> Result<(), JsError>) -> Result<String, JsError> {
+    let is_joining = JOINING.with(|joining| joining.borrow().contains(array));
+    if is_joining {
+        return Ok(String::new())
+    }
+
+    let length = match array.borrow().kind {
+        ObjectKind::Array(ref elements) => elements.length,
+        _ => return Ok(String::new()),
+    };
+
+    JOINING.with(|joining| joining.borrow_mut().push(array.clone()));
+    let joined = join_elements(array, length, separator, max_length, tick);
+    JOINING.with(|joining| joining.borrow_mut().pop());
+
+    joined
+}
+
+/// Joins the elements that are not holes, putting in the separators of
+/// the holes between them all at once.
+fn join_elements(array: &ObjectRef, length: u32, separator: &str, max_length: usize, tick: &mut dyn FnMut() -> Result<(), JsError>) -> Result<String, JsError> {
+    let mut joined = String::new();
+    let mut next = 0;
+    loop {
+        let (index, element) = match array.borrow().kind {
+            ObjectKind::Array(ref elements) => match elements.next_index(next, length) {
+                Some(index) => (Some(index), elements.get(index).cloned()),
+                None => (None, None),
+            },
+            _ => (None, None),
+        };
+
+        // Every index but the first is preceded by a separator
+        let end = index.map_or(length as u64, |index| index as u64 + 1);
+        let separators = end.saturating_sub(cmp::max(next as u64, 1));
+        if separators.saturating_mul(separator.len() as u64) > max_length.saturating_sub(joined.len()) as u64 {
+            return Err(too_long())
+        }
+        joined.push_str(&separator.repeat(separators as usize));
+
+        let index = match index {
+            Some(index) => index,
+            None => return Ok(joined),
+        };
+        tick()?;
+        match element.as_ref().map(JsValue::view) {
+            None | Some(Value::Undefined) | Some(Value::Null) => {},
+            // Nested arrays get what is left of the limit
+            Some(Value::Object(ref object)) if object.is_array() => {
+                joined.push_str(&join_array(object, ",", max_length - joined.len(), tick)?);
+            },
+            Some(_) => joined.push_str(&js_value_to_string(element.as_ref().unwrap())),
+        }
+
+        if joined.len() > max_length {
+            return Err(too_long())
+        }
+        next = index + 1;
+    }
+}
+
+fn too_long() -> JsError {
+    JsError::range_error("Invalid string length".to_owned())
 }
@@ -1,25 +1,128 @@
 use super::JsValue;
+use super::Value;
+use super::temp::js_value_to_string;
 
-pub fn rust_to_js_boolean(b: bool) -> JsValue {
-    if b == true {
-        return JsValue::JsTrue
-    } else {
-        return JsValue::JsFalse
+/// Applies the ToBoolean conversion, deciding whether a value is truthy.
+pub fn js_value_to_rust_boolean(v: &JsValue) -> bool {
+    match v.view() {
+        Value::Null | Value::Undefined => false,
+        Value::Boolean(b) => b,
+        Value::Number(num) => num != 0.0 && !num.is_nan(),
+        Value::String(s) => !s.is_empty(),
+        Value::Object(_) => true,
     }
 }
 
-pub fn try_js_value_to_js_number(v: &JsValue) -> JsValue {
-    match v {
-        &JsValue::JsString(ref string) => {
-            match string.clone().parse::<f64>() {
-                Ok(parsed_x) => return JsValue::JsNumber(parsed_x),
-                Err(_) => return JsValue::JsString(string.clone()),
-            }
-        },
-        _ => return v.clone()
+/// Applies the ToNumber conversion.
+pub fn js_value_to_number(v: &JsValue) -> f64 {
+    match v.view() {
+        Value::Number(num) => num,
+        Value::Boolean(b) => if b { 1.0 } else { 0.0 },
+        Value::Null => 0.0,
+        Value::Undefined => f64::NAN,
+        Value::String(s) => string_to_number(s),
+        // Without a VM to call `valueOf` with, objects convert through their
+        // string form. Operators run `to_primitive` first, which calls it.
+        Value::Object(_) => string_to_number(&js_value_to_string(v)),
+    }
+}
+
+/// The StringToNumber conversion. Surrounding whitespace is ignored, an
+/// empty string is 0, and besides decimals the string may hold a `0x`, `0o`
+/// or `0b` literal or a signed `Infinity`. Anything else is NaN.
+pub fn string_to_number(s: &str) -> f64 {
+    let s = s.trim_matches(is_js_whitespace);
+    if s.is_empty() {
+        return 0.0
+    }
+
+    let radix = match s.get(..2) {
+        Some("0x") | Some("0X") => 16,
+        Some("0o") | Some("0O") => 8,
+        Some("0b") | Some("0B") => 2,
+        _ => return decimal_to_number(s),
+    };
+
+    let digits = &s[2..];
+    if digits.is_empty() {
+        return f64::NAN
     }
+
+    let mut number = 0.0;
+    for c in digits.chars() {
+        match c.to_digit(radix) {
+            Some(digit) => number = number * radix as f64 + digit as f64,
+            None => return f64::NAN,
+        }
+    }
+    number
 }
 
-pub fn flip_js_bool(b: JsValue) -> JsValue {
-    return rust_to_js_boolean(b != JsValue::JsTrue)
+/// Parses an optionally signed decimal literal or `Infinity`.
+fn decimal_to_number(s: &str) -> f64 {
+    let (sign, unsigned) = match s.as_bytes()[0] {
+        b'+' => (1.0, &s[1..]),
+        b'-' => (-1.0, &s[1..]),
+        _ => (1.0, s),
+    };
+
+    if unsigned == "Infinity" {
+        return sign * f64::INFINITY
+    }
+
+    // Rust also takes `inf` and `nan`, so only the characters of a decimal
+    // literal are let through, with signs only at the start of the exponent
+    let mut previous = None;
+    let is_literal = unsigned.chars().all(|c| {
+        let allowed = match c {
+            '0'..='9' | '.' | 'e' | 'E' => true,
+            '+' | '-' => matches!(previous, Some('e') | Some('E')),
+            _ => false,
+        };
+        previous = Some(c);
+        allowed
+    });
+    if !is_literal {
+        return f64::NAN
+    }
+
+    match unsigned.parse::<f64>() {
+        Ok(number) => sign * number,
+        Err(_) => f64::NAN,
+    }
+}
+
+/// The white space and line terminators that string to number conversions
+/// ignore around the number.
+fn is_js_whitespace(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\u{b}' | '\u{c}' | '\r' | ' ' | '\u{a0}' | '\u{1680}' | '\u{2000}'..='\u{200a}'
+        | '\u{2028}' | '\u{2029}' | '\u{202f}' | '\u{205f}' | '\u{3000}' | '\u{feff}')
+}
+
+/// The ToInt32 conversion used by the bitwise operators, wrapping the
+/// integer part of a number modulo 2^32.
+pub fn js_value_to_int32(v: &JsValue) -> i32 {
+    js_value_to_uint32(v) as i32
+}
+
+/// The ToUint32 conversion, wrapping the integer part of a number modulo
+/// 2^32.
+pub fn js_value_to_uint32(v: &JsValue) -> u32 {
+    let number = js_value_to_number(v);
+    if !number.is_finite() {
+        return 0
+    }
+
+    number.trunc().rem_euclid(4294967296.0) as u32
+}
+
+/// The SameValue comparison, which tells NaN equal to itself and 0 apart
+/// from -0.
+pub fn same_value(a: &JsValue, b: &JsValue) -> bool {
+    match (a.view(), b.view()) {
+        (Value::Number(x), Value::Number(y)) => {
+            (x.is_nan() && y.is_nan()) || (x == y && x.is_sign_negative() == y.is_sign_negative())
+        },
+        _ => a == b,
+    }
 }
@@ -0,0 +1,216 @@
+use super::heap::STRING_BYTES;
+use super::heap::note_allocation;
+use super::object::JsObject;
+use super::object::ObjectRef;
+use std::cell::RefCell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::rc::Rc;
+
+/// Numbers are stored as they are. Every NaN is stored as this one, which
+/// leaves the other NaNs free to hold the values that are not numbers.
+const CANONICAL_NAN: u64 = 0x7ff8_0000_0000_0000;
+
+/// The top 16 bits of a value that is not a number, which make it a
+/// negative quiet NaN. The low 48 bits are the payload.
+const NULL_TAG: u64 = 0xfff9 << 48;
+const UNDEFINED_TAG: u64 = 0xfffa << 48;
+const BOOLEAN_TAG: u64 = 0xfffb << 48;
+const STRING_TAG: u64 = 0xfffc << 48;
+const OBJECT_TAG: u64 = 0xfffd << 48;
+
+const TAG_MASK: u64 = 0xffff << 48;
+const PAYLOAD_MASK: u64 = !TAG_MASK;
+
+/// A JS value, NaN-boxed into 8 bytes. Strings and objects are reference
+/// counted, so copying a value never copies what it points at. Match on
+/// `view()` to take a value apart.
+pub struct JsValue {
+    bits: u64,
+    // Holds `Rc`s, so it can't leave the thread
+    marker: PhantomData<Rc<()>>,
+}
+
+/// What a `JsValue` holds, borrowed from it.
+#[derive(Debug)]
+pub enum Value<'a> {
+    Null,
+    Undefined,
+    Number(f64),
+    Boolean(bool),
+    String(&'a str),
+    Object(ObjectView<'a>),
+}
+
+/// An object borrowed from a `JsValue`, usable as an `ObjectRef`.
+pub struct ObjectView<'a> {
+    object: ManuallyDrop<ObjectRef>,
+    marker: PhantomData<&'a JsValue>,
+}
+
+impl JsValue {
+    pub const NULL: JsValue = JsValue::from_bits(NULL_TAG);
+    pub const UNDEFINED: JsValue = JsValue::from_bits(UNDEFINED_TAG);
+    pub const TRUE: JsValue = JsValue::from_bits(BOOLEAN_TAG | 1);
+    pub const FALSE: JsValue = JsValue::from_bits(BOOLEAN_TAG);
+    pub const NAN: JsValue = JsValue::from_bits(CANONICAL_NAN);
+
+    const fn from_bits(bits: u64) -> JsValue {
+        JsValue { bits, marker: PhantomData }
+    }
+
+    pub fn number(number: f64) -> JsValue {
+        if number.is_nan() {
+            return JsValue::NAN
+        }
+        JsValue::from_bits(number.to_bits())
+    }
+
+    pub fn boolean(boolean: bool) -> JsValue {
+        JsValue::from_bits(BOOLEAN_TAG | boolean as u64)
+    }
+
+    pub fn string<S: Into<String>>(string: S) -> JsValue {
+        let string = string.into();
+        note_allocation(STRING_BYTES + string.len());
+        let pointer = Rc::into_raw(Rc::new(string));
+        JsValue::from_pointer(STRING_TAG, pointer as u64)
+    }
+
+    pub fn object(object: ObjectRef) -> JsValue {
+        JsValue::from_pointer(OBJECT_TAG, object.into_raw() as u64)
+    }
+
+    fn from_pointer(tag: u64, address: u64) -> JsValue {
+        // Pointers fit in the payload on the platforms we run on
+        assert_eq!(address & TAG_MASK, 0, "pointer does not fit in a value");
+        JsValue::from_bits(tag | address)
+    }
+
+    fn tag(&self) -> u64 {
+        self.bits & TAG_MASK
+    }
+
+    fn payload(&self) -> u64 {
+        self.bits & PAYLOAD_MASK
+    }
+
+    fn is_number(&self) -> bool {
+        !matches!(self.tag(), NULL_TAG | UNDEFINED_TAG | BOOLEAN_TAG | STRING_TAG | OBJECT_TAG)
+    }
+
+    pub fn view(&self) -> Value<'_> {
+        if self.is_number() {
+            return Value::Number(f64::from_bits(self.bits))
+        }
+
+        match self.tag() {
+            NULL_TAG => Value::Null,
+            UNDEFINED_TAG => Value::Undefined,
+            BOOLEAN_TAG => Value::Boolean(self.payload() != 0),
+            // The value keeps the string alive for as long as it is borrowed
+            STRING_TAG => Value::String(unsafe { &*(self.payload() as *const String) }),
+            _ => Value::Object(ObjectView {
+                object: ManuallyDrop::new(unsafe { ObjectRef::from_raw(self.payload() as *const RefCell<JsObject>) }),
+                marker: PhantomData,
+            }),
+        }
+    }
+
+    pub fn is_undefined(&self) -> bool {
+        self.bits == UNDEFINED_TAG
+    }
+
+    pub fn is_object(&self) -> bool {
+        self.tag() == OBJECT_TAG
+    }
+
+    /// The number this value holds, without converting other values.
+    pub fn as_number(&self) -> Option<f64> {
+        match self.view() {
+            Value::Number(number) => Some(number),
+            _ => None,
+        }
+    }
+
+    /// The object this value holds.
+    pub fn as_object(&self) -> Option<ObjectRef> {
+        match self.view() {
+            Value::Object(object) => Some(object.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl Clone for JsValue {
+    fn clone(&self) -> JsValue {
+        match self.tag() {
+            STRING_TAG => unsafe { Rc::increment_strong_count(self.payload() as *const String) },
+            OBJECT_TAG => unsafe { Rc::increment_strong_count(self.payload() as *const RefCell<JsObject>) },
+            _ => {},
+        }
+        JsValue::from_bits(self.bits)
+    }
+}
+
+impl Drop for JsValue {
+    fn drop(&mut self) {
+        match self.tag() {
+            STRING_TAG => unsafe { Rc::decrement_strong_count(self.payload() as *const String) },
+            OBJECT_TAG => unsafe { Rc::decrement_strong_count(self.payload() as *const RefCell<JsObject>) },
+            _ => {},
+        }
+    }
+}
+
+/// Values compare with SameValueZero, so NaN equals itself and 0 equals -0.
+/// Strings compare by content and objects by identity.
+impl PartialEq for JsValue {
+    fn eq(&self, other: &JsValue) -> bool {
+        match (self.view(), other.view()) {
+            (Value::Number(a), Value::Number(b)) => a == b || (a.is_nan() && b.is_nan()),
+            (Value::String(a), Value::String(b)) => a == b,
+            _ => self.bits == other.bits,
+        }
+    }
+}
+
+impl fmt::Debug for JsValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.view().fmt(f)
+    }
+}
+
+impl From<bool> for JsValue {
+    fn from(boolean: bool) -> JsValue {
+        JsValue::boolean(boolean)
+    }
+}
+
+impl From<f64> for JsValue {
+    fn from(number: f64) -> JsValue {
+        JsValue::number(number)
+    }
+}
+
+impl From<ObjectRef> for JsValue {
+    fn from(object: ObjectRef) -> JsValue {
+        JsValue::object(object)
+    }
+}
+
+impl<'a> Deref for ObjectView<'a> {
+    type Target = ObjectRef;
+
+    fn deref(&self) -> &ObjectRef {
+        &self.object
+    }
+}
+
+impl<'a> fmt::Debug for ObjectView<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.object.fmt(f)
+    }
+}